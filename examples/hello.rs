@@ -6,9 +6,9 @@ fn main() {
         .build()
         .expect("nix build to work");
 
-    let hello = outputs[0].out().expect("default output to be available");
-
-    let stdout = std::process::Command::new(format!("{}/bin/hello", hello.display().to_string()))
+    let stdout = outputs[0]
+        .command("hello")
+        .expect("hello to be available")
         .output()
         .expect("hello to succeed")
         .stdout;
@@ -0,0 +1,44 @@
+use crate::Derivation;
+
+impl Derivation {
+    /// Returns the clang arguments needed to find the headers of this derivation
+    ///
+    /// The `include` directories of the `dev`, `out` and `lib` outputs are passed with `-I`
+    ///
+    /// # Example
+    /// ```no_run
+    /// # fn main() -> Result<(), nix_build::Error> {
+    /// let derivations = nix_build::build()?;
+    ///
+    /// let bindings = bindgen::Builder::default()
+    ///     .header("wrapper.h")
+    ///     .clang_args(derivations[0].bindgen_clang_args())
+    ///     .generate()
+    ///     .expect("bindings to be generated");
+    /// # Ok(()) }
+    /// ```
+    pub fn bindgen_clang_args(&self) -> Vec<String> {
+        self.find_dirs(&["dev", "out", "lib"], "include")
+            .map(|dir| format!("-I{}", dir.display()))
+            .collect()
+    }
+
+    /// Returns the clang arguments needed to find the headers of this derivation,
+    /// as system headers
+    ///
+    /// Like [`Derivation::bindgen_clang_args`], but the directories are passed with `-isystem`,
+    /// so warnings in the headers don't fail builds with `-Werror`
+    pub fn bindgen_system_clang_args(&self) -> Vec<String> {
+        self.find_dirs(&["dev", "out", "lib"], "include")
+            .flat_map(|dir| ["-isystem".to_owned(), dir.display().to_string()])
+            .collect()
+    }
+
+    /// Add the include directories of this derivation to the given [`bindgen::Builder`],
+    /// as system headers
+    ///
+    /// See [`Derivation::bindgen_system_clang_args`]
+    pub fn configure_bindgen(&self, builder: ::bindgen::Builder) -> ::bindgen::Builder {
+        builder.clang_args(self.bindgen_system_clang_args())
+    }
+}
@@ -0,0 +1,122 @@
+//! Parsing of the results printed by `nix build --json`, across nix versions
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::{version, Derivation, Error, Result};
+
+/// Shapes of the results printed by `nix build --json`
+enum Schema {
+    /// `{"drvPath": ..., "outputs": {"out": "/nix/store/..."}}`, since nix 2.4
+    ///
+    /// Nix 2.18 adds the `startTime` and `stopTime` of the build, which are ignored
+    Paths,
+    /// `{"drvPath": ..., "outputs": {"out": {"id": ..., "outPath": ...}}}`,
+    /// printed for content-addressed derivations by some versions
+    Realisations,
+    /// `{"path": "/nix/store/..."}`, printed for store paths that are not built
+    Opaque,
+}
+
+impl Schema {
+    /// Returns the shape of the result `entry`, if it's known
+    fn detect(entry: &Value) -> Option<Self> {
+        let entry = entry.as_object()?;
+
+        if entry.contains_key("drvPath") {
+            let outputs = entry.get("outputs")?.as_object()?;
+            if outputs.values().all(Value::is_string) {
+                Some(Self::Paths)
+            } else if outputs.values().all(Value::is_object) {
+                Some(Self::Realisations)
+            } else {
+                None
+            }
+        } else if entry.get("path").is_some_and(Value::is_string) {
+            Some(Self::Opaque)
+        } else {
+            None
+        }
+    }
+}
+
+/// Fail with [`Error::UnrecognizedBuildJson`], noting the detected nix version
+fn unrecognized(reason: String) -> Error {
+    Error::UnrecognizedBuildJson {
+        nix_version: version::detect(),
+        reason,
+    }
+}
+
+/// Parse the derivations printed by `nix build --json`, in order
+pub(crate) fn parse(stdout: &[u8]) -> Result<Vec<Derivation>> {
+    let json: Value = serde_json::from_slice(stdout)
+        .map_err(|err| unrecognized(format!("invalid JSON: {err}")))?;
+    let Value::Array(entries) = json else {
+        return Err(unrecognized("expected a list of results".to_owned()));
+    };
+
+    entries
+        .into_iter()
+        .enumerate()
+        .map(|(i, entry)| match Schema::detect(&entry) {
+            Some(Schema::Paths | Schema::Realisations) => Derivation::deserialize(entry)
+                .map_err(|err| unrecognized(format!("result {i}: {err}"))),
+            Some(Schema::Opaque) => Err(unrecognized(format!(
+                "result {i}: store path {} is not the output of a derivation",
+                entry["path"].as_str().unwrap_or_default()
+            ))),
+            None => Err(unrecognized(format!("result {i}: unknown shape {entry}"))),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_paths() {
+        // nix 2.18, with the build times
+        let stdout = br#"[{"drvPath":"/nix/store/vkb3h6vb2nhcbmmp7zs1w2n0x1i8sv3l-openssl-3.0.13.drv","outputs":{"bin":"/nix/store/0c8ybjvkn0jxx2xy7yv5c3d7zg2g3yy2-openssl-3.0.13-bin","dev":"/nix/store/2l6ww0f0lb4kxs3bqhdzpljzjxvhc6kb-openssl-3.0.13-dev","out":"/nix/store/9w0bd6yv5m0gxvwryjfxvjvr6ch0bqsm-openssl-3.0.13"},"startTime":1718200000,"stopTime":1718200042}]"#;
+
+        let derivations = parse(stdout).unwrap();
+        assert_eq!(derivations.len(), 1);
+        assert_eq!(
+            derivations[0].drv_path.as_os_str(),
+            "/nix/store/vkb3h6vb2nhcbmmp7zs1w2n0x1i8sv3l-openssl-3.0.13.drv"
+        );
+        assert_eq!(derivations[0].outputs.len(), 3);
+        assert_eq!(derivations[0].outputs["dev"].name(), "openssl-3.0.13-dev");
+        assert!(derivations[0].realisations.is_empty());
+    }
+
+    #[test]
+    fn realisations() {
+        let stdout = br#"[{"drvPath":"/nix/store/vkb3h6vb2nhcbmmp7zs1w2n0x1i8sv3l-hello-2.12.1.drv","outputs":{"out":{"dependentRealisations":{},"id":"sha256:5a4a4e8a9ecb1e5d2d8c8c0a6b1d7a58e0f1f0f9e0f4c1c6b1b0f0a1e2d3c4b5!out","outPath":"0c8ybjvkn0jxx2xy7yv5c3d7zg2g3yy2-hello-2.12.1","signatures":[]}}}]"#;
+
+        let derivations = parse(stdout).unwrap();
+        let out = &derivations[0].outputs["out"];
+        assert_eq!(
+            out.as_os_str(),
+            "/nix/store/0c8ybjvkn0jxx2xy7yv5c3d7zg2g3yy2-hello-2.12.1"
+        );
+
+        let realisation = derivations[0].realisation("out").unwrap();
+        assert_eq!(&realisation.out_path, out);
+        assert_eq!(realisation.output_name(), Some("out"));
+    }
+
+    #[test]
+    fn unrecognized_results() {
+        let opaque = br#"[{"path":"/nix/store/0c8ybjvkn0jxx2xy7yv5c3d7zg2g3yy2-source"}]"#;
+        let unknown = br#"[{"drvPath":"/nix/store/vkb3h6vb2nhcbmmp7zs1w2n0x1i8sv3l-hello-2.12.1.drv","outputs":{"out":1}}]"#;
+
+        for stdout in [&opaque[..], unknown, b"{}", b"not json"] {
+            assert!(matches!(
+                parse(stdout),
+                Err(Error::UnrecognizedBuildJson { .. })
+            ));
+        }
+    }
+}
@@ -0,0 +1,384 @@
+//! Structured nix logs, from `--log-format internal-json`, and the reports built from them
+
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::out_paths;
+
+/// Arguments making nix print structured logs
+pub(crate) const LOG_FORMAT_ARGS: [&str; 2] = ["--log-format", "internal-json"];
+
+/// Prefix of the structured log lines
+const PREFIX: &str = "@nix ";
+
+// activity types, see `ActivityType` in nix
+const ACT_FILE_TRANSFER: u64 = 101;
+const ACT_BUILD: u64 = 105;
+const ACT_SUBSTITUTE: u64 = 108;
+const ACT_FETCH_TREE: u64 = 112;
+
+// result types, see `ResultType` in nix
+const RES_BUILD_LOG_LINE: u64 = 101;
+const RES_PROGRESS: u64 = 105;
+
+/// Progress of a build, see [`Config::on_event`](crate::Config::on_event)
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum BuildEvent {
+    /// nix has been started and is evaluating the target
+    EvaluationStarted,
+    /// A flake input or fetcher source is being fetched
+    FetchingInput { url: String },
+    /// A derivation started building locally
+    BuildingDrv { drv_path: PathBuf },
+    /// Progress of a download, in bytes
+    DownloadProgress {
+        url: String,
+        done: u64,
+        expected: u64,
+    },
+    /// nix exited
+    Done { success: bool },
+}
+
+/// Shared callback receiving [`BuildEvent`]s
+pub(crate) type EventHandler = Arc<Mutex<dyn FnMut(BuildEvent) + Send>>;
+
+/// Call each of `handlers` with `event`
+pub(crate) fn emit(handlers: &[EventHandler], event: BuildEvent) {
+    for handler in handlers {
+        let mut handler = handler.lock().unwrap_or_else(|err| err.into_inner());
+        handler(event.clone());
+    }
+}
+
+/// Derivation built locally
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuiltDerivation {
+    pub drv_path: PathBuf,
+    /// Time spent building it
+    pub duration: Duration,
+}
+
+/// Metrics of a build
+///
+/// # Example
+/// ```no_run
+/// # fn main() -> Result<(), nix_build::Error> {
+/// let (derivations, report) = nix_build::Config::new().build_with_report()?;
+///
+/// println!(
+///     "cargo:warning=nix built {} derivations and substituted {} paths in {:?}",
+///     report.built.len(),
+///     report.substituted.len(),
+///     report.wall_time,
+/// );
+/// # Ok(()) }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BuildReport {
+    /// Time spent running nix
+    pub wall_time: Duration,
+    /// Whether the result was taken from the cache of the [`Config`](crate::Config),
+    /// or from an identical build earlier in the process, without running nix
+    pub cached: bool,
+    /// Derivations built locally, in the order they completed
+    pub built: Vec<BuiltDerivation>,
+    /// Store paths downloaded from binary caches
+    pub substituted: Vec<PathBuf>,
+    /// Bytes downloaded from binary caches and fetchers
+    pub bytes_downloaded: u64,
+}
+
+/// Line of the structured log
+#[derive(Deserialize)]
+#[serde(tag = "action", rename_all = "lowercase")]
+enum Message {
+    Start {
+        id: u64,
+        #[serde(rename = "type", default)]
+        kind: u64,
+        #[serde(default)]
+        text: String,
+        #[serde(default)]
+        fields: Vec<Value>,
+    },
+    Stop {
+        id: u64,
+    },
+    Result {
+        id: u64,
+        #[serde(rename = "type")]
+        kind: u64,
+        #[serde(default)]
+        fields: Vec<Value>,
+    },
+    Msg {
+        msg: String,
+    },
+}
+
+/// Activity in progress
+struct Activity {
+    kind: u64,
+    started: Instant,
+    /// Derivation being built or store path being substituted
+    path: Option<PathBuf>,
+    /// URL being downloaded
+    url: Option<String>,
+    /// Progress of the activity, in bytes for transfers
+    done: u64,
+}
+
+/// Remove the terminal escape sequences from `text`
+pub(crate) fn strip_ansi(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            stripped.push(c);
+            continue;
+        }
+
+        if chars.next() == Some('[') {
+            // parameters, up to the final byte
+            for c in chars.by_ref() {
+                if ('@'..='~').contains(&c) {
+                    break;
+                }
+            }
+        }
+    }
+
+    stripped
+}
+
+/// Incremental parser of the structured log of a nix invocation
+///
+/// Also reconstructs the plain text nix would have printed
+pub(crate) struct LogParser<'h> {
+    activities: HashMap<u64, Activity>,
+    report: BuildReport,
+    handlers: &'h [EventHandler],
+}
+
+impl<'h> LogParser<'h> {
+    /// Create a new [`LogParser`], reporting events to `handlers`
+    pub(crate) fn new(handlers: &'h [EventHandler]) -> Self {
+        Self {
+            activities: HashMap::new(),
+            report: BuildReport::default(),
+            handlers,
+        }
+    }
+
+    /// Parse a line printed by nix at `now`, returning its plain text, if any
+    ///
+    /// Lines not in the structured format are returned as is
+    pub(crate) fn line(&mut self, line: &str, now: Instant) -> Option<String> {
+        let Some(message) = line
+            .strip_prefix(PREFIX)
+            .and_then(|json| serde_json::from_str::<Message>(json).ok())
+        else {
+            return Some(line.to_owned());
+        };
+
+        match message {
+            Message::Msg { msg } => return Some(strip_ansi(&msg)),
+            Message::Start {
+                id,
+                kind,
+                text,
+                fields,
+            } => {
+                let first = fields.first().and_then(Value::as_str);
+                let path = match kind {
+                    ACT_BUILD | ACT_SUBSTITUTE => first.map(PathBuf::from),
+                    _ => None,
+                };
+                let url = match kind {
+                    ACT_FILE_TRANSFER => first.map(str::to_owned),
+                    // like `fetching git input 'git+https://...'`
+                    ACT_FETCH_TREE => text.split('\'').nth(1).map(str::to_owned),
+                    _ => None,
+                };
+
+                match (kind, &path, &url) {
+                    (ACT_BUILD, Some(drv_path), _) => emit(
+                        self.handlers,
+                        BuildEvent::BuildingDrv {
+                            drv_path: drv_path.clone(),
+                        },
+                    ),
+                    (ACT_FETCH_TREE, _, Some(url)) => emit(
+                        self.handlers,
+                        BuildEvent::FetchingInput { url: url.clone() },
+                    ),
+                    _ => {}
+                }
+
+                self.activities.insert(
+                    id,
+                    Activity {
+                        kind,
+                        started: now,
+                        path,
+                        url,
+                        done: 0,
+                    },
+                );
+            }
+            Message::Stop { id } => {
+                let activity = self.activities.remove(&id)?;
+                match activity.kind {
+                    ACT_BUILD => self.report.built.push(BuiltDerivation {
+                        drv_path: activity.path?,
+                        duration: now.duration_since(activity.started),
+                    }),
+                    ACT_SUBSTITUTE => self.report.substituted.extend(activity.path),
+                    ACT_FILE_TRANSFER => self.report.bytes_downloaded += activity.done,
+                    _ => {}
+                }
+            }
+            Message::Result { id, kind, fields } => {
+                let activity = self.activities.get_mut(&id)?;
+                match kind {
+                    RES_PROGRESS => {
+                        let progress =
+                            |i| fields.get(i).and_then(Value::as_u64).unwrap_or_default();
+                        activity.done = progress(0);
+
+                        if let (ACT_FILE_TRANSFER, Some(url)) = (activity.kind, &activity.url) {
+                            emit(
+                                self.handlers,
+                                BuildEvent::DownloadProgress {
+                                    url: url.clone(),
+                                    done: activity.done,
+                                    expected: progress(1),
+                                },
+                            );
+                        }
+                    }
+                    RES_BUILD_LOG_LINE => {
+                        let line = strip_ansi(fields.first()?.as_str()?);
+                        let name = activity
+                            .path
+                            .as_deref()
+                            .and_then(out_paths::store_name)
+                            .map_or("", |name| name.trim_end_matches(".drv"));
+                        return Some(format!("{name}> {line}"));
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Returns the report of the invocation, which ran for `wall_time`
+    pub(crate) fn finish(self, wall_time: Duration) -> BuildReport {
+        BuildReport {
+            wall_time,
+            ..self.report
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DRV: &str = "/nix/store/vkb3h6vb2nhcbmmp7zs1w2n0x1i8sv3l-hello-2.12.1.drv";
+    const ZLIB: &str = "/nix/store/0c8ybjvkn0jxx2xy7yv5c3d7zg2g3yy2-zlib-1.3.1";
+
+    /// Log of `nix build --log-format internal-json`, substituting zlib and building hello
+    fn log() -> String {
+        [
+            r#"@nix {"action":"start","id":1,"level":3,"parent":0,"text":"fetching git input 'git+file:///home/user/project'","type":112,"fields":[]}"#,
+            r#"@nix {"action":"stop","id":1}"#,
+            r#"@nix {"action":"msg","level":1,"msg":"\u001b[35;1mwarning:\u001b[0m Git tree '/home/user/project' is dirty"}"#,
+            r#"@nix {"action":"start","id":2,"level":4,"parent":0,"text":"copying path '/nix/store/0c8ybjvkn0jxx2xy7yv5c3d7zg2g3yy2-zlib-1.3.1' from 'https://cache.nixos.org'","type":108,"fields":["/nix/store/0c8ybjvkn0jxx2xy7yv5c3d7zg2g3yy2-zlib-1.3.1","https://cache.nixos.org"]}"#,
+            r#"@nix {"action":"start","id":3,"level":4,"parent":2,"text":"downloading 'https://cache.nixos.org/nar/1xq2p1fbq8gqb4d3k0j6pgdxwq9l3ns3n8fh9gkfsyr1hvjvy3ab.nar.xz'","type":101,"fields":["https://cache.nixos.org/nar/1xq2p1fbq8gqb4d3k0j6pgdxwq9l3ns3n8fh9gkfsyr1hvjvy3ab.nar.xz"]}"#,
+            r#"@nix {"action":"result","id":3,"type":105,"fields":[65536,131072,0,0]}"#,
+            r#"@nix {"action":"result","id":3,"type":105,"fields":[131072,131072,0,0]}"#,
+            r#"@nix {"action":"stop","id":3}"#,
+            r#"@nix {"action":"stop","id":2}"#,
+            r#"@nix {"action":"start","id":4,"level":3,"parent":0,"text":"building '/nix/store/vkb3h6vb2nhcbmmp7zs1w2n0x1i8sv3l-hello-2.12.1.drv'","type":105,"fields":["/nix/store/vkb3h6vb2nhcbmmp7zs1w2n0x1i8sv3l-hello-2.12.1.drv","",1,1]}"#,
+            r#"@nix {"action":"result","id":4,"type":101,"fields":["\u001b[1mchecking for gcc... gcc\u001b[0m"]}"#,
+            r#"@nix {"action":"stop","id":4}"#,
+            "error: unexpected line",
+        ]
+        .join("\n")
+    }
+
+    #[test]
+    fn report_and_plain_text() {
+        let events = Arc::new(Mutex::new(vec![]));
+        let handler: EventHandler = {
+            let events = events.clone();
+            Arc::new(Mutex::new(move |event| events.lock().unwrap().push(event)))
+        };
+        let handlers = [handler];
+
+        let start = Instant::now();
+        let mut parser = LogParser::new(&handlers);
+        let text: Vec<String> = log()
+            .lines()
+            .enumerate()
+            .filter_map(|(i, line)| parser.line(line, start + Duration::from_secs(i as u64)))
+            .collect();
+        let report = parser.finish(Duration::from_secs(12));
+
+        assert_eq!(
+            text,
+            [
+                "warning: Git tree '/home/user/project' is dirty",
+                "hello-2.12.1> checking for gcc... gcc",
+                "error: unexpected line",
+            ]
+        );
+
+        assert_eq!(report.wall_time, Duration::from_secs(12));
+        assert_eq!(
+            report.built,
+            [BuiltDerivation {
+                drv_path: PathBuf::from(DRV),
+                duration: Duration::from_secs(2),
+            }]
+        );
+        assert_eq!(report.substituted, [PathBuf::from(ZLIB)]);
+        assert_eq!(report.bytes_downloaded, 131072);
+
+        let nar = "https://cache.nixos.org/nar/1xq2p1fbq8gqb4d3k0j6pgdxwq9l3ns3n8fh9gkfsyr1hvjvy3ab.nar.xz";
+        assert_eq!(
+            *events.lock().unwrap(),
+            [
+                BuildEvent::FetchingInput {
+                    url: "git+file:///home/user/project".to_owned()
+                },
+                BuildEvent::DownloadProgress {
+                    url: nar.to_owned(),
+                    done: 65536,
+                    expected: 131072,
+                },
+                BuildEvent::DownloadProgress {
+                    url: nar.to_owned(),
+                    done: 131072,
+                    expected: 131072,
+                },
+                BuildEvent::BuildingDrv {
+                    drv_path: PathBuf::from(DRV)
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,133 @@
+//! Record of the nix artifacts used by the builds of a workspace
+
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{cargo, meta, version, Config, Derivation, Error, Installable, Result};
+
+/// Name of the manifest written by [`Config::write_manifest`]
+const MANIFEST_NAME: &str = "nix-manifest.json";
+
+/// Manifest of the nix artifacts used by the crates of a workspace,
+/// written by [`Config::write_manifest`]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BuildManifest {
+    /// Builds of the workspace, sorted by package and target
+    pub entries: Vec<ManifestEntry>,
+}
+
+/// Build recorded in a [`BuildManifest`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// Package whose build script ran the build, from `CARGO_PKG_NAME`
+    pub package: String,
+    /// Version of the package, from `CARGO_PKG_VERSION`
+    pub package_version: String,
+    /// Target of the build, like `nixpkgs#openssl`
+    pub target: String,
+    /// Locked revision of the target flake, if any
+    pub flake_revision: Option<String>,
+    /// Version of nix used for the build
+    pub nix_version: Option<String>,
+    /// Derivations built, by derivation path
+    pub derivations: BTreeMap<PathBuf, BTreeMap<String, PathBuf>>,
+}
+
+impl BuildManifest {
+    /// Load the manifest at `path`
+    ///
+    /// # Example
+    /// ```no_run
+    /// # fn main() -> Result<(), nix_build::Error> {
+    /// use nix_build::BuildManifest;
+    ///
+    /// let manifest = BuildManifest::load("target/nix-manifest.json")?;
+    /// for entry in &manifest.entries {
+    ///     println!("{} uses {}", entry.package, entry.target);
+    /// }
+    /// # Ok(()) }
+    /// ```
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let json = std::fs::read(path)?;
+        let manifest = serde_json::from_slice(&json).map_err(std::io::Error::from)?;
+        Ok(manifest)
+    }
+
+    /// Returns the path of the manifest of the current cargo build, like `target/nix-manifest.json`
+    pub fn path() -> Option<PathBuf> {
+        let profile = cargo::profile_dir()?;
+        Some(profile.parent()?.join(MANIFEST_NAME))
+    }
+
+    /// Add `entry`, replacing the previous build of the same package and target
+    fn insert(&mut self, entry: ManifestEntry) {
+        self.entries.retain(|old| {
+            (old.package.as_str(), old.target.as_str())
+                != (entry.package.as_str(), entry.target.as_str())
+        });
+        self.entries.push(entry);
+        self.entries.sort_by(|a, b| {
+            (a.package.as_str(), a.target.as_str()).cmp(&(b.package.as_str(), b.target.as_str()))
+        });
+    }
+}
+
+/// Merge the derivations built for `config` into the manifest of the current cargo build
+///
+/// The manifest is locked while updated, as build scripts of the workspace run concurrently
+pub(crate) fn record(config: &Config, derivations: &[Derivation]) -> Result<()> {
+    let path = BuildManifest::path().ok_or(Error::MissingEnvVar("OUT_DIR"))?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+
+    let flake_revision = match &config.target {
+        Installable::Flake { flake_ref, .. } => {
+            meta::flake_revision(&flake_ref.without_attr().to_string())
+        }
+        _ => None,
+    };
+    let entry = ManifestEntry {
+        package: std::env::var("CARGO_PKG_NAME").unwrap_or_default(),
+        package_version: std::env::var("CARGO_PKG_VERSION").unwrap_or_default(),
+        target: config.target.to_string(),
+        flake_revision,
+        nix_version: version::detect().map(|version| version.to_string()),
+        derivations: derivations
+            .iter()
+            .map(|drv| {
+                let outputs = drv
+                    .outputs
+                    .iter()
+                    .map(|(name, path)| (name.clone(), path.to_path_buf()))
+                    .collect();
+                (drv.drv_path.to_path_buf(), outputs)
+            })
+            .collect(),
+    };
+
+    let lock = File::options()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(path.with_extension("lock"))?;
+    crate::config::lock_exclusive(&lock)?;
+
+    let mut manifest = match BuildManifest::load(&path) {
+        Ok(manifest) => manifest,
+        Err(Error::Io(err)) if err.kind() == std::io::ErrorKind::NotFound => {
+            BuildManifest::default()
+        }
+        Err(err) => return Err(err),
+    };
+    manifest.insert(entry);
+
+    let json = serde_json::to_vec_pretty(&manifest).map_err(std::io::Error::from)?;
+    std::fs::write(&path, json)?;
+    Ok(())
+}
@@ -0,0 +1,134 @@
+//! Results of builds, by target
+
+use std::ops::Index;
+
+use crate::{store::StorePath, Derivation, Installable};
+
+/// Derivations built for one target of a [`BuildOutputs`]
+#[derive(Debug, Clone)]
+pub struct BuildOutput {
+    /// Label of the target, its installable unless set by [`BuildSet::add`](crate::BuildSet::add)
+    pub label: String,
+    /// Target built
+    pub target: Installable,
+    /// Derivations built for the target, in the order reported by nix
+    pub derivations: Vec<Derivation>,
+}
+
+/// Derivations built for each target, in the order the targets were configured
+///
+/// # Example
+/// ```no_run
+/// # fn main() -> Result<(), nix_build::Error> {
+/// use nix_build::{BuildSet, Config};
+///
+/// let built = BuildSet::new()
+///     .add("openssl", Config::new().target_flake("nixpkgs#openssl"))
+///     .add("zlib", Config::new().target_flake("nixpkgs#zlib"))
+///     .build_outputs()?;
+///
+/// let zlib = &built["nixpkgs#zlib"];
+/// let openssl = built["openssl"][0].include_dir();
+/// for output in &built {
+///     println!("{} built {} derivation(s)", output.label, output.derivations.len());
+/// }
+/// # Ok(()) }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct BuildOutputs {
+    outputs: Vec<BuildOutput>,
+}
+
+impl BuildOutputs {
+    pub(crate) fn new(outputs: Vec<BuildOutput>) -> Self {
+        Self { outputs }
+    }
+
+    /// Returns the derivations built for `target`, by label, installable like `nixpkgs#zlib`,
+    /// or attribute like `zlib`
+    pub fn get(&self, target: &str) -> Option<&[Derivation]> {
+        self.output(target)
+            .map(|output| output.derivations.as_slice())
+    }
+
+    /// Returns the result of `target`, by label, installable like `nixpkgs#zlib`,
+    /// or attribute like `zlib`
+    pub fn output(&self, target: &str) -> Option<&BuildOutput> {
+        let find = |matches: fn(&BuildOutput, &str) -> bool| {
+            self.outputs.iter().find(|output| matches(output, target))
+        };
+
+        find(|output, target| output.label == target)
+            .or_else(|| find(|output, target| output.target.to_string() == target))
+            .or_else(|| find(|output, target| output.target.attr() == Some(target)))
+    }
+
+    /// Returns the `out` output of the first derivation of the first target, if any
+    ///
+    /// # Example
+    /// ```no_run
+    /// # fn main() -> Result<(), nix_build::Error> {
+    /// let built = nix_build::Config::new()
+    ///     .target_flake("nixpkgs#hello")
+    ///     .build_outputs()?;
+    ///
+    /// let hello = built.first_out().expect("hello has an out output").join("bin/hello");
+    /// # Ok(()) }
+    /// ```
+    pub fn first_out(&self) -> Option<&StorePath> {
+        self.outputs.first()?.derivations.first()?.out()
+    }
+
+    /// Returns the results of the targets, in the order they were configured
+    pub fn iter(&self) -> std::slice::Iter<'_, BuildOutput> {
+        self.outputs.iter()
+    }
+
+    /// Returns the number of targets
+    pub fn len(&self) -> usize {
+        self.outputs.len()
+    }
+
+    /// Returns whether no target was built
+    pub fn is_empty(&self) -> bool {
+        self.outputs.is_empty()
+    }
+
+    /// Returns the derivations of all the targets, in order
+    pub fn into_derivations(self) -> Vec<Derivation> {
+        self.outputs
+            .into_iter()
+            .flat_map(|output| output.derivations)
+            .collect()
+    }
+}
+
+impl<'a> IntoIterator for &'a BuildOutputs {
+    type Item = &'a BuildOutput;
+    type IntoIter = std::slice::Iter<'a, BuildOutput>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.outputs.iter()
+    }
+}
+
+impl IntoIterator for BuildOutputs {
+    type Item = BuildOutput;
+    type IntoIter = std::vec::IntoIter<BuildOutput>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.outputs.into_iter()
+    }
+}
+
+impl Index<&str> for BuildOutputs {
+    type Output = [Derivation];
+
+    /// Returns the derivations built for `target`, see [`BuildOutputs::get`]
+    ///
+    /// Panics if no target matches
+    fn index(&self, target: &str) -> &Self::Output {
+        self.get(target)
+            .unwrap_or_else(|| panic!("no build output for target `{target}`"))
+    }
+}
@@ -0,0 +1,194 @@
+//! What a build would do, from `nix build --dry-run`
+
+use std::{ffi::OsString, path::PathBuf};
+
+use crate::{failure, version, Config, Error, Result};
+
+/// Derivations built and store paths downloaded by a build, see [`Config::plan`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BuildPlan {
+    /// Derivations built locally, as they're missing from the binary caches
+    pub to_build: Vec<PathBuf>,
+    /// Store paths downloaded from the binary caches
+    pub to_fetch: Vec<PathBuf>,
+    /// Size in bytes of the downloads, compressed
+    pub download_size: u64,
+    /// Size in bytes of the downloaded paths once unpacked, their NAR size
+    pub nar_size: u64,
+}
+
+impl BuildPlan {
+    /// Returns whether everything is already in the store
+    pub fn is_empty(&self) -> bool {
+        self.to_build.is_empty() && self.to_fetch.is_empty()
+    }
+
+    /// Parse the plan printed by `nix build --dry-run`, like
+    /// `these 2 paths will be fetched (1.20 MiB download, 5.73 MiB unpacked):`
+    /// followed by the indented paths
+    ///
+    /// Fails with [`Error::UnknownOutput`] if the sizes of the downloads can't be parsed
+    fn parse(stderr: &str) -> Result<Self> {
+        // sizes are in MiB, rendered with the closest unit since nix 2.25
+        let size = |text: &str, kind: &str| -> Result<u64> {
+            text.split(", ")
+                .find_map(|part| part.strip_suffix(kind))
+                .and_then(|size| {
+                    let (number, unit) = size.trim().split_once(' ')?;
+                    let scale = match unit {
+                        "B" => 1u64,
+                        "KiB" => 1 << 10,
+                        "MiB" => 1 << 20,
+                        "GiB" => 1 << 30,
+                        "TiB" => 1 << 40,
+                        _ => return None,
+                    };
+                    Some((number.parse::<f64>().ok()? * scale as f64).round() as u64)
+                })
+                .ok_or(Error::UnknownOutput)
+        };
+
+        let mut plan = Self::default();
+        let mut section: Option<&mut Vec<PathBuf>> = None;
+
+        for line in stderr.lines() {
+            if let Some(path) = line.strip_prefix("  ").map(str::trim) {
+                if let Some(paths) = section.as_mut().filter(|_| path.starts_with('/')) {
+                    paths.push(PathBuf::from(path));
+                }
+                continue;
+            }
+
+            section = None;
+            if line.contains("will be built") {
+                section = Some(&mut plan.to_build);
+            } else if line.contains("will be fetched") {
+                let sizes = line
+                    .split_once('(')
+                    .and_then(|(_, sizes)| sizes.split_once(')'))
+                    .map_or("", |(sizes, _)| sizes);
+                plan.download_size += size(sizes, "download")?;
+                plan.nar_size += size(sizes, "unpacked")?;
+                section = Some(&mut plan.to_fetch);
+            }
+        }
+
+        Ok(plan)
+    }
+}
+
+impl Config {
+    /// Returns the derivations the build would run locally and the paths it would download,
+    /// with `nix build --dry-run`, without building anything
+    ///
+    /// Sizes are rounded by nix to two decimals, so they're approximate.
+    /// Fails with [`Error::UnknownOutput`] if they can't be parsed.
+    /// Useful to pick a runner, or warn, before a large download or a long build
+    ///
+    /// # Example
+    /// ```no_run
+    /// # fn main() -> Result<(), nix_build::Error> {
+    /// const LIMIT: u64 = 2 * 1024 * 1024 * 1024;
+    ///
+    /// let mut config = nix_build::Config::new();
+    /// config.target_flake("nixpkgs#cudatoolkit");
+    ///
+    /// let plan = config.plan()?;
+    /// if plan.download_size > LIMIT || !plan.to_build.is_empty() {
+    ///     println!(
+    ///         "cargo:warning=downloading {} MiB and building {} derivations",
+    ///         plan.download_size / 1024 / 1024,
+    ///         plan.to_build.len()
+    ///     );
+    /// }
+    /// # Ok(()) }
+    /// ```
+    pub fn plan(&self) -> Result<BuildPlan> {
+        self.validate()?;
+
+        let mut args: Vec<OsString> = vec!["build".into(), "--dry-run".into(), "--no-link".into()];
+        args.extend(self.installable_args_of(&self.selected_target()));
+        args.extend(
+            version::experimental_features_args()
+                .iter()
+                .map(OsString::from),
+        );
+
+        let output = self.run_output(args)?;
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if !output.status.success() {
+            return Err(failure::classify(&stderr));
+        }
+
+        BuildPlan::parse(&stderr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fetched_in_mib() {
+        // nix 2.18
+        let stderr = "\
+these 2 derivations will be built:
+  /nix/store/vkb3h6vb2nhcbmmp7zs1w2n0x1i8sv3l-my-crate-deps-0.1.0.drv
+  /nix/store/2l6ww0f0lb4kxs3bqhdzpljzjxvhc6kb-my-crate-0.1.0.drv
+these 3 paths will be fetched (12.34 MiB download, 56.78 MiB unpacked):
+  /nix/store/0c8ybjvkn0jxx2xy7yv5c3d7zg2g3yy2-openssl-3.0.13
+  /nix/store/9w0bd6yv5m0gxvwryjfxvjvr6ch0bqsm-glibc-2.37-8
+  /nix/store/3y6hzxhfmxp2l8sd3b1n4smr5vcx4mdv-zlib-1.3.1
+";
+        let plan = BuildPlan::parse(stderr).unwrap();
+        assert_eq!(
+            plan.to_build,
+            [
+                "/nix/store/vkb3h6vb2nhcbmmp7zs1w2n0x1i8sv3l-my-crate-deps-0.1.0.drv",
+                "/nix/store/2l6ww0f0lb4kxs3bqhdzpljzjxvhc6kb-my-crate-0.1.0.drv",
+            ]
+            .map(PathBuf::from)
+        );
+        assert_eq!(plan.to_fetch.len(), 3);
+        assert_eq!(plan.download_size, 12_939_428);
+        assert_eq!(plan.nar_size, 59_538_145);
+    }
+
+    #[test]
+    fn fetched_in_kib() {
+        // nix 2.25, with the closest unit
+        let stderr = "\
+this derivation will be built:
+  /nix/store/2l6ww0f0lb4kxs3bqhdzpljzjxvhc6kb-my-crate-0.1.0.drv
+this path will be fetched (52.4 KiB download, 220.6 KiB unpacked):
+  /nix/store/3y6hzxhfmxp2l8sd3b1n4smr5vcx4mdv-zlib-1.3.1
+";
+        let plan = BuildPlan::parse(stderr).unwrap();
+        assert_eq!(plan.to_build.len(), 1);
+        assert_eq!(
+            plan.to_fetch,
+            [PathBuf::from(
+                "/nix/store/3y6hzxhfmxp2l8sd3b1n4smr5vcx4mdv-zlib-1.3.1"
+            )]
+        );
+        assert_eq!(plan.download_size, 53_658);
+        assert_eq!(plan.nar_size, 225_894);
+    }
+
+    #[test]
+    fn nothing_to_do() {
+        assert!(BuildPlan::parse("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn unknown_sizes() {
+        let stderr = "\
+this path will be fetched (52.4 kB download, 220.6 kB unpacked):
+  /nix/store/3y6hzxhfmxp2l8sd3b1n4smr5vcx4mdv-zlib-1.3.1
+";
+        assert!(matches!(
+            BuildPlan::parse(stderr),
+            Err(Error::UnknownOutput)
+        ));
+    }
+}
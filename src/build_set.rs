@@ -0,0 +1,168 @@
+//! Concurrent builds of several configurations
+
+use std::{
+    collections::BTreeMap,
+    ffi::OsString,
+    num::NonZeroUsize,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+use crate::{BuildOutput, BuildOutputs, Config, Derivation, Error, Result};
+
+/// Set of configurations built concurrently, identified by a label
+///
+/// # Example
+/// ```no_run
+/// # fn main() -> Result<(), nix_build::Error> {
+/// use nix_build::{BuildSet, Config};
+///
+/// let built = BuildSet::new()
+///     .add("openssl", Config::new().target_flake("nixpkgs#openssl"))
+///     .add("zlib", Config::new().target_flake("nixpkgs#zlib"))
+///     .jobs(2)
+///     .build()?;
+///
+/// let openssl = &built["openssl"];
+/// # Ok(()) }
+/// ```
+#[derive(Clone, Default)]
+pub struct BuildSet {
+    configs: Vec<(String, Config)>,
+    jobs: Option<usize>,
+    batch: bool,
+}
+
+impl BuildSet {
+    /// Create an empty [`BuildSet`]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a configuration to build, identified by `label`
+    pub fn add(&mut self, label: impl Into<String>, config: &Config) -> &mut Self {
+        self.configs.push((label.into(), config.clone()));
+        self
+    }
+
+    /// Set the maximum number of concurrent builds
+    ///
+    /// Defaults to the available parallelism
+    pub fn jobs(&mut self, jobs: usize) -> &mut Self {
+        self.jobs = Some(jobs.max(1));
+        self
+    }
+
+    /// Set to build configurations targeting attributes of the same flake or file
+    /// with a single `nix build`, so it's evaluated once
+    ///
+    /// Configurations must otherwise share the same settings,
+    /// and not rely on caching or dependency tracking
+    pub fn batch(&mut self, batch: bool) -> &mut Self {
+        self.batch = batch;
+        self
+    }
+
+    /// Group the configurations into the units built concurrently
+    fn groups(&self) -> Vec<Vec<usize>> {
+        let mut groups: Vec<(Option<Vec<OsString>>, Vec<usize>)> = vec![];
+
+        for (i, (_, config)) in self.configs.iter().enumerate() {
+            let key = config.batch_key().filter(|_| self.batch);
+            match groups
+                .iter_mut()
+                .find(|(other, _)| key.is_some() && *other == key)
+            {
+                Some((_, group)) => group.push(i),
+                None => groups.push((key, vec![i])),
+            }
+        }
+
+        groups.into_iter().map(|(_, group)| group).collect()
+    }
+
+    /// Build all the configurations, returning the derivations of each by label
+    ///
+    /// Every configuration is built even if some fail,
+    /// failures are collected in [`Error::BuildSetFailed`]
+    pub fn build(&self) -> Result<BTreeMap<String, Vec<Derivation>>> {
+        let built = self.build_all()?;
+        Ok(built
+            .into_iter()
+            .map(|(i, derivations)| (self.configs[i].0.clone(), derivations))
+            .collect())
+    }
+
+    /// Build all the configurations like [`BuildSet::build`], returning the derivations
+    /// of each in the order they were added
+    pub fn build_outputs(&self) -> Result<BuildOutputs> {
+        let built = self.build_all()?;
+        Ok(BuildOutputs::new(
+            built
+                .into_iter()
+                .map(|(i, derivations)| {
+                    let (label, config) = &self.configs[i];
+                    BuildOutput {
+                        label: label.clone(),
+                        target: config.target.clone(),
+                        derivations,
+                    }
+                })
+                .collect(),
+        ))
+    }
+
+    /// Build all the configurations, returning the derivations of each by index, in order
+    fn build_all(&self) -> Result<Vec<(usize, Vec<Derivation>)>> {
+        let groups = self.groups();
+        let jobs = self
+            .jobs
+            .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, NonZeroUsize::get))
+            .min(groups.len());
+
+        let next = AtomicUsize::new(0);
+        let results = Mutex::new(Vec::with_capacity(self.configs.len()));
+
+        std::thread::scope(|scope| {
+            for _ in 0..jobs {
+                scope.spawn(|| {
+                    while let Some(group) = groups.get(next.fetch_add(1, Ordering::Relaxed)) {
+                        let configs: Vec<&Config> =
+                            group.iter().map(|&i| &self.configs[i].1).collect();
+
+                        let built = match &*configs {
+                            [config] => vec![config.build()],
+                            configs => Config::build_batch(configs),
+                        };
+
+                        let mut results = results.lock().unwrap_or_else(|err| err.into_inner());
+                        for (&i, result) in group.iter().zip(built) {
+                            results.push((i, result));
+                        }
+                    }
+                });
+            }
+        });
+
+        let mut results = results.into_inner().unwrap_or_else(|err| err.into_inner());
+        results.sort_by_key(|(i, _)| *i);
+
+        let mut built = vec![];
+        let mut failures = vec![];
+        for (i, result) in results {
+            match result {
+                Ok(derivations) => built.push((i, derivations)),
+                Err(err) => failures.push((self.configs[i].0.clone(), err)),
+            }
+        }
+
+        if failures.is_empty() {
+            Ok(built)
+        } else {
+            failures.sort_by(|(a, _), (b, _)| a.cmp(b));
+            Err(Error::BuildSetFailed(failures))
+        }
+    }
+}
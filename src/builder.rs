@@ -0,0 +1,184 @@
+//! Owned variant of the [`Config`] builder
+
+use std::{
+    collections::BTreeMap,
+    ffi::OsStr,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    store::StorePath, Backend, BuildEvent, BuildHandle, BuildOutputs, BuildPlan, CacheStatus,
+    Config, Derivation, DropPolicy, FlakeRef, Installable, LicensePolicy, NixRunner, OutputFormat,
+    Result, SandboxMode,
+};
+
+/// [`Config`] built by value, for one-liners and functions returning a configuration
+///
+/// # Example
+/// ```no_run
+/// # fn main() -> Result<(), nix_build::Error> {
+/// use nix_build::{Config, ConfigBuilder};
+///
+/// fn openssl() -> ConfigBuilder {
+///     Config::builder().target_flake("nixpkgs#openssl").impure(true)
+/// }
+///
+/// let derivations = openssl().rpath(true).build()?;
+/// let config: Config = openssl().into();
+/// # Ok(()) }
+/// ```
+#[derive(Clone, Default)]
+pub struct ConfigBuilder(Config);
+
+/// Define by-value setters forwarding to the [`Config`] setters of the same name
+macro_rules! setters {
+    ($($(#[$attr:meta])* $name:ident($($arg:ident: $ty:ty),*);)*) => {
+        $(
+            #[doc = concat!("See [`Config::", stringify!($name), "`]")]
+            $(#[$attr])*
+            pub fn $name(mut self, $($arg: $ty),*) -> Self {
+                self.0.$name($($arg),*);
+                self
+            }
+        )*
+    };
+}
+
+impl ConfigBuilder {
+    setters! {
+        arg_expr(name: &str, value: &str);
+        arg_str(name: &str, value: &str);
+        arg_text(name: &str, contents: &str);
+        override_flake(name: &str, flake_ref: &str);
+        target_file(filename: impl AsRef<OsStr>);
+        target_flake(flake: impl Into<FlakeRef>);
+        target_expr(expr: &str);
+        target(installable: impl Into<Installable>);
+        attribute(attr: &str);
+        outputs(names: &[&str]);
+        backend(backend: Backend);
+        output_format(format: OutputFormat);
+        impure(impure: bool);
+        rpath(rpath: bool);
+        forward_warnings(forward: bool);
+        out_link(path: impl Into<PathBuf>);
+        gc_root(enabled: bool);
+        shared_out_link(enabled: bool);
+        write_manifest(enabled: bool);
+        on_event(handler: impl FnMut(BuildEvent) + Send + 'static);
+        #[cfg(feature = "indicatif")]
+        progress(enabled: bool);
+        nom(enabled: bool);
+        log_file(path: impl Into<PathBuf>);
+        cache(cache: bool);
+        cache_dir(dir: impl Into<PathBuf>);
+        lock(lock: bool);
+        lock_file(path: impl Into<PathBuf>);
+        track_dependencies(track: bool);
+        track_env(name: &str);
+        clear_env(clear: bool);
+        keep_env(names: &[&str]);
+        env(key: impl AsRef<OsStr>, value: impl AsRef<OsStr>);
+        env_remove(key: impl AsRef<OsStr>);
+        git_ssh_command(cmd: impl AsRef<OsStr>);
+        ssh_auth_sock(socket: impl AsRef<Path>);
+        forward_ssh_agent(forward: bool);
+        cargo_integration(enabled: bool);
+        verify_outputs(enabled: bool);
+        sign_with(key_file: impl Into<PathBuf>);
+        post_build_copy(store_uri: impl Into<String>);
+        access_token(host: impl Into<String>, token: impl Into<String>);
+        netrc_file(path: impl Into<PathBuf>);
+        eval_store(uri: impl Into<String>);
+        store_root(dir: impl Into<PathBuf>);
+        ca_derivations(enabled: bool);
+        system(system: impl Into<String>);
+        substituter(url: impl Into<String>);
+        require_sigs(required: bool);
+        trusted_substituters_only(enabled: bool);
+        sandbox(mode: SandboxMode);
+        sandbox_paths(paths: &[&str]);
+        extra_sandbox_path(path: impl AsRef<Path>);
+        extra_sandbox_path_at(target: impl AsRef<Path>, source: impl AsRef<Path>);
+        use_cgroups(enabled: bool);
+        option(name: impl Into<String>, value: impl Into<String>);
+        nice(level: i32);
+        max_silent_time(secs: u64);
+        build_timeout(secs: u64);
+        respect_cargo_jobs(enabled: bool);
+        retries(retries: u32);
+        fallback(enabled: bool);
+        eval_cache(enabled: bool);
+        allow_ifd(allowed: bool);
+        runner(runner: impl NixRunner + 'static);
+        license_policy(policy: LicensePolicy);
+        drop_policy(policy: DropPolicy);
+    }
+
+    /// See [`Config::env_overrides`]
+    pub fn env_overrides(mut self) -> Result<Self> {
+        self.0.env_overrides()?;
+        Ok(self)
+    }
+
+    /// See [`Config::build`]
+    pub fn build(&self) -> Result<Vec<Derivation>> {
+        self.0.build()
+    }
+
+    /// See [`Config::build_outputs`]
+    pub fn build_outputs(&self) -> Result<BuildOutputs> {
+        self.0.build_outputs()
+    }
+
+    /// See [`Config::build_optional`]
+    pub fn build_optional(&self) -> Result<Option<Vec<Derivation>>> {
+        self.0.build_optional()
+    }
+
+    /// See [`Config::emit_eval_env`]
+    pub fn emit_eval_env(&self, vars: &[(&str, &str)]) -> Result<BTreeMap<String, String>> {
+        self.0.emit_eval_env(vars)
+    }
+
+    /// See [`Config::plan`]
+    pub fn plan(&self) -> Result<BuildPlan> {
+        self.0.plan()
+    }
+
+    /// See [`Config::check_cache`]
+    pub fn check_cache(&self, substituter: &str) -> Result<CacheStatus> {
+        self.0.check_cache(substituter)
+    }
+
+    /// See [`Config::instantiate`]
+    pub fn instantiate(&self) -> Result<Vec<StorePath>> {
+        self.0.instantiate()
+    }
+
+    /// See [`Config::spawn`]
+    pub fn spawn(&self) -> BuildHandle {
+        self.0.spawn()
+    }
+}
+
+impl From<ConfigBuilder> for Config {
+    fn from(builder: ConfigBuilder) -> Self {
+        builder.0
+    }
+}
+
+impl From<Config> for ConfigBuilder {
+    fn from(config: Config) -> Self {
+        Self(config)
+    }
+}
+
+impl Config {
+    /// Create a new [`ConfigBuilder`], setting the configuration by value
+    ///
+    /// Target is defaulted to `default.nix`
+    pub fn builder() -> ConfigBuilder {
+        ConfigBuilder::default()
+    }
+}
@@ -0,0 +1,155 @@
+//! Presence of the outputs of the target in a binary cache, before building them
+
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    store::{self, StorePath},
+    version, Config, Derivation, Error, Installable, Result,
+};
+
+/// Outputs of the target found in a binary cache, see [`Config::check_cache`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheStatus {
+    /// URL of the binary cache queried
+    pub substituter: String,
+    /// Outputs available in the binary cache
+    pub cached: Vec<StorePath>,
+    /// Outputs missing from the binary cache, built locally unless found elsewhere
+    pub missing: Vec<StorePath>,
+}
+
+impl CacheStatus {
+    /// Returns whether all the outputs are available in the binary cache
+    pub fn is_hit(&self) -> bool {
+        self.missing.is_empty()
+    }
+
+    /// Returns whether none of the outputs are available in the binary cache
+    pub fn is_miss(&self) -> bool {
+        self.cached.is_empty()
+    }
+}
+
+impl Config {
+    /// Query the binary cache at `substituter` for the outputs of the target, without building
+    ///
+    /// The output paths are evaluated with `nix eval`, then looked up with
+    /// `nix path-info --store`. Useful to warn about, or skip, a long local build when the
+    /// cache wasn't populated yet. Only the outputs themselves are queried, not their closure,
+    /// and the outputs of content-addressed derivations are reported missing as their paths
+    /// are only known once built
+    ///
+    /// # Example
+    /// ```no_run
+    /// # fn main() -> Result<(), nix_build::Error> {
+    /// let mut config = nix_build::Config::new();
+    /// config.target_flake("nixpkgs#llvm");
+    ///
+    /// let status = config.check_cache("https://cache.nixos.org")?;
+    /// if !status.is_hit() {
+    ///     println!("cargo:warning=llvm isn't cached and will be built locally");
+    /// }
+    /// let derivations = config.build()?;
+    /// # Ok(()) }
+    /// ```
+    pub fn check_cache(&self, substituter: &str) -> Result<CacheStatus> {
+        self.validate()?;
+
+        let mut status = CacheStatus {
+            substituter: substituter.to_owned(),
+            cached: vec![],
+            missing: vec![],
+        };
+
+        for path in self.out_paths()? {
+            let path = StorePath::new(path)?;
+            if self.is_substitutable(substituter, &path)? {
+                status.cached.push(path);
+            } else {
+                status.missing.push(path);
+            }
+        }
+
+        Ok(status)
+    }
+
+    /// Evaluate the paths of the outputs of the target, selected by [`Config::outputs`]
+    fn out_paths(&self) -> Result<Vec<PathBuf>> {
+        let selected: Vec<String> = match &self.target {
+            _ if !self.outputs.is_empty() => self.outputs.clone(),
+            Installable::Flake { outputs, .. } | Installable::Drv { outputs, .. } => outputs
+                .iter()
+                .filter(|output| *output != "*")
+                .cloned()
+                .collect(),
+            _ => vec![],
+        };
+
+        if let Installable::Drv { path, .. } = &self.target {
+            let drv = Derivation {
+                drv_path: StorePath::new(path)?,
+                outputs: Default::default(),
+                realisations: Default::default(),
+            };
+
+            return Ok(drv
+                .show()?
+                .outputs
+                .into_iter()
+                .filter(|(name, _)| selected.is_empty() || selected.contains(name))
+                .filter_map(|(_, output)| output.path)
+                .collect());
+        }
+
+        let outputs = match selected.is_empty() {
+            true => "(drv.outputs or [ \"out\" ])".to_owned(),
+            false => format!("[ {} ]", quoted(&selected)),
+        };
+        let expr = format!(
+            "target: builtins.concatMap (drv: map (output: drv.${{output}}.outPath) {outputs}) \
+             (if builtins.isList target then target else [ target ])"
+        );
+
+        // outputs can't be selected when evaluating an installable
+        let mut config = self.clone();
+        config.target = self.target.clone().with_outputs(Vec::<String>::new());
+        config.eval_json(&expr)
+    }
+
+    /// Returns whether `path` can be substituted from the binary cache at `substituter`
+    fn is_substitutable(&self, substituter: &str, path: &Path) -> Result<bool> {
+        let mut args: Vec<OsString> = vec![
+            "path-info".into(),
+            "--json".into(),
+            "--store".into(),
+            substituter.into(),
+            path.into(),
+        ];
+        args.extend(
+            version::experimental_features_args()
+                .iter()
+                .map(OsString::from),
+        );
+
+        match self.run_command(args) {
+            Ok(stdout) => store::path_info::lists_valid(&stdout),
+            // nix fails on invalid paths before 2.19
+            Err(Error::CommandFailed { stderr, .. }) if stderr.contains("is not valid") => {
+                Ok(false)
+            }
+            Err(err) => Err(err),
+        }
+    }
+}
+
+/// Returns the names as a list of nix strings
+fn quoted(names: &[String]) -> String {
+    names
+        .iter()
+        .map(|name| format!("{name:?}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
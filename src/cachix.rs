@@ -0,0 +1,99 @@
+//! Pushing to [Cachix](https://cachix.org) binary caches
+
+use std::{fmt, path::Path};
+
+use crate::{vendor, Derivation, Error, Result};
+
+/// A Cachix binary cache, pushed to with the `cachix` program
+///
+/// `cachix` is looked up in `PATH`, or built from `nixpkgs#cachix` if absent
+///
+/// # Example
+/// ```no_run
+/// # fn main() -> Result<(), nix_build::Error> {
+/// use nix_build::Cachix;
+///
+/// let derivations = nix_build::build()?;
+///
+/// let token = std::env::var("CACHIX_AUTH_TOKEN").unwrap();
+/// Cachix::new("my-team", token).push(&derivations)?;
+/// # Ok(()) }
+/// ```
+#[derive(Clone)]
+pub struct Cachix {
+    cache: String,
+    auth_token: String,
+    signing_key: Option<String>,
+}
+
+impl fmt::Debug for Cachix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Cachix")
+            .field("cache", &self.cache)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Cachix {
+    /// Create a new [`Cachix`] for the cache named `cache`, authenticated with `auth_token`
+    pub fn new(cache: impl Into<String>, auth_token: impl Into<String>) -> Self {
+        Self {
+            cache: cache.into(),
+            auth_token: auth_token.into(),
+            signing_key: None,
+        }
+    }
+
+    /// Sign the pushed paths with the given key, for caches not managed by Cachix
+    pub fn signing_key(&mut self, key: impl Into<String>) -> &mut Self {
+        self.signing_key = Some(key.into());
+        self
+    }
+
+    /// Returns the URL of the cache, to be used as a substituter
+    pub fn url(&self) -> String {
+        format!("https://{}.cachix.org", self.cache)
+    }
+
+    /// Push all the outputs of the given derivations, along with their runtime closure
+    pub fn push<'a>(&self, derivations: impl IntoIterator<Item = &'a Derivation>) -> Result<()> {
+        self.push_paths(
+            derivations
+                .into_iter()
+                .flat_map(|derivation| derivation.outputs.values()),
+        )
+    }
+
+    /// Push the given store paths, along with their runtime closure
+    pub fn push_paths<I, P>(&self, paths: I) -> Result<()>
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        let cachix = vendor::find_tool("cachix", "nixpkgs#cachix")?;
+
+        let mut cmd = std::process::Command::new(cachix);
+        cmd.arg("push")
+            .arg(&self.cache)
+            .args(
+                paths
+                    .into_iter()
+                    .map(|path| path.as_ref().as_os_str().to_owned()),
+            )
+            .env("CACHIX_AUTH_TOKEN", &self.auth_token);
+        if let Some(key) = &self.signing_key {
+            cmd.env("CACHIX_SIGNING_KEY", key);
+        }
+        debug!("pushing to cachix cache {}", self.cache);
+
+        let output = cmd.output()?;
+        if !output.status.success() {
+            return Err(Error::CommandFailed {
+                program: "cachix".to_owned(),
+                stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+            });
+        }
+
+        Ok(())
+    }
+}
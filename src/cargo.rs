@@ -0,0 +1,76 @@
+//! Helpers to emit build script directives understood by cargo
+
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use crate::LinkKind;
+
+/// Prints a single `cargo:{key}={value}` directive
+pub(crate) fn directive(key: &str, value: impl Display) {
+    println!("cargo:{key}={value}");
+}
+
+pub(crate) fn rerun_if_changed(path: &Path) {
+    directive("rerun-if-changed", path.display());
+}
+
+pub(crate) fn rerun_if_env_changed(var: &str) {
+    directive("rerun-if-env-changed", var);
+}
+
+pub(crate) fn warning(message: impl Display) {
+    directive("warning", message);
+}
+
+pub(crate) fn rustc_link_search(kind: &str, path: &Path) {
+    directive(
+        "rustc-link-search",
+        format_args!("{kind}={}", path.display()),
+    );
+}
+
+pub(crate) fn rustc_link_lib(kind: LinkKind, name: &str) {
+    directive("rustc-link-lib", format_args!("{}={name}", kind.as_str()));
+}
+
+pub(crate) fn rustc_link_arg(arg: impl Display) {
+    directive("rustc-link-arg", arg);
+}
+
+pub(crate) fn rustc_env(name: &str, value: &str) {
+    directive("rustc-env", format_args!("{name}={value}"));
+}
+
+/// Returns whether the current process is a build script run by cargo
+pub(crate) fn is_build_script() -> bool {
+    std::env::var_os("CARGO").is_some() && std::env::var_os("TARGET").is_some()
+}
+
+/// Returns the number of jobs cargo allows the build script to run, from `NUM_JOBS`
+pub(crate) fn num_jobs() -> Option<usize> {
+    std::env::var("NUM_JOBS").ok()?.parse().ok()
+}
+
+/// Returns the target OS of the crate being built, as reported by cargo to build scripts
+pub(crate) fn target_os() -> Option<String> {
+    std::env::var("CARGO_CFG_TARGET_OS").ok()
+}
+
+/// Returns the profile directory of the current cargo build (like `target/debug`)
+///
+/// Inferred from `OUT_DIR`, which is `<profile>/build/<package>-<hash>/out`
+pub(crate) fn profile_dir() -> Option<PathBuf> {
+    let out_dir = PathBuf::from(std::env::var_os("OUT_DIR")?);
+
+    out_dir
+        .ancestors()
+        .find(|dir| {
+            dir.file_name()
+                .map(|name| name == "build")
+                .unwrap_or_default()
+        })
+        .and_then(Path::parent)
+        .map(Path::to_path_buf)
+}
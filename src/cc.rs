@@ -0,0 +1,25 @@
+use crate::Derivation;
+
+impl Derivation {
+    /// Add the include directories of this derivation to the given [`cc::Build`]
+    ///
+    /// The `include` directories of the `dev`, `out` and `lib` outputs are added,
+    /// and `cargo:rustc-link-search=native=` is emitted for the `lib` directories
+    ///
+    /// # Example
+    /// ```no_run
+    /// # fn main() -> Result<(), nix_build::Error> {
+    /// let derivations = nix_build::build()?;
+    ///
+    /// derivations[0]
+    ///     .configure_cc(&mut cc::Build::new())
+    ///     .file("shim.c")
+    ///     .compile("shim");
+    /// # Ok(()) }
+    /// ```
+    pub fn configure_cc<'b>(&self, build: &'b mut ::cc::Build) -> &'b mut ::cc::Build {
+        build.includes(self.find_dirs(&["dev", "out", "lib"], "include"));
+        self.emit_link_search();
+        build
+    }
+}
@@ -0,0 +1,71 @@
+//! Helpers to invoke auxiliary `nix` subcommands
+
+use std::{ffi::OsStr, process::Command};
+
+use crate::{Error, Result};
+
+/// Arguments enabling the experimental features used by the crate
+///
+/// Used when the nix implementation couldn't be detected, see [`crate::version::experimental_features_args`]
+pub(crate) const EXPERIMENTAL_FEATURES: [&str; 2] =
+    ["--experimental-features", "nix-command flakes"];
+
+/// Returns a [`Command`] invoking the found `nix` program
+pub(crate) fn nix() -> Result<Command> {
+    let nix = crate::is_nix_available().ok_or(Error::NixNotAvailable)?;
+    Ok(Command::new(nix))
+}
+
+/// Run the given command, returning its stdout
+///
+/// Fails with [`Error::CommandFailed`] if the command is unsuccessful
+pub(crate) fn run(cmd: &mut Command) -> Result<Vec<u8>> {
+    debug!("running {cmd:?}");
+    let output = cmd.output()?;
+
+    if !output.status.success() {
+        return Err(Error::CommandFailed {
+            program: cmd.get_program().to_string_lossy().into_owned(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        });
+    }
+
+    Ok(output.stdout)
+}
+
+/// Run `nix` with the given arguments, returning its stdout
+pub(crate) fn nix_stdout<I, S>(args: I) -> Result<Vec<u8>>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    run(nix()?
+        .args(args)
+        .args(crate::version::experimental_features_args()))
+}
+
+/// Run `nix` with the given arguments, parsing its stdout as JSON
+pub(crate) fn nix_json<T, I, S>(args: I) -> Result<T>
+where
+    T: serde::de::DeserializeOwned,
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let stdout = nix_stdout(args)?;
+    serde_json::from_slice(&stdout).map_err(|_| Error::UnknownOutput)
+}
+
+/// Returns a [`Command`] invoking the legacy `nix-*` program `name`
+///
+/// The program is looked up next to the found `nix` program first, then in `PATH`
+pub(crate) fn legacy(name: &str) -> Result<Command> {
+    let sibling = crate::is_nix_available()
+        .and_then(|nix| nix.canonicalize().ok())
+        .and_then(|nix| Some(nix.parent()?.join(name)))
+        .filter(|path| path.exists());
+
+    sibling
+        .or_else(|| which::which(name).ok())
+        .map(Command::new)
+        .ok_or(Error::NixNotAvailable)
+}
@@ -1,30 +1,100 @@
 use std::{
-    collections::HashMap,
+    collections::BTreeSet,
     ffi::{OsStr, OsString},
+    fs::File,
+    io::{BufRead, BufReader, Read, Write},
     path::{Path, PathBuf},
-    process::Command,
+    process::{Command, Output, Stdio},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
 };
 
-use crate::{Error, Result};
-
-enum NixTarget {
-    Function(OsString),
-    Flake(String),
-    Expr(String),
-}
+use crate::{
+    build_json,
+    build_log::{self, EventHandler, LogParser},
+    build_manifest, cargo, command, daemon, failure,
+    fingerprint::{Fingerprint, Hasher},
+    legacy, logging, memo, meta, nom, out_paths,
+    spawn::{self, DropPolicy, Process},
+    store::{self, StorePath},
+    tracking, version, Backend, BuildEvent, BuildOutput, BuildOutputs, BuildReport, Derivation,
+    Error, FlakeRef, Installable, LicensePolicy, NixRunner, OutputFormat, Result, SandboxMode,
+};
 
-impl Default for NixTarget {
-    fn default() -> Self {
-        Self::Function(OsString::from("default.nix"))
-    }
-}
+/// Nix settings the daemon ignores for untrusted users, besides substituters
+const RESTRICTED_SETTINGS: &[&str] = &[
+    "builders",
+    "builders-use-substitutes",
+    "require-sigs",
+    "sandbox",
+    "sandbox-paths",
+    "extra-sandbox-paths",
+    "trusted-public-keys",
+    "extra-trusted-public-keys",
+    "post-build-hook",
+    "use-cgroups",
+];
 
 /// Build style configration for a pending build.
+#[derive(Clone)]
 pub struct Config {
-    target: NixTarget,
-    arg_exprs: Vec<(String, String)>,
-    arg_strs: Vec<(String, String)>,
-    impure: bool,
+    pub(crate) target: Installable,
+    pub(crate) arg_exprs: Vec<(String, String)>,
+    pub(crate) arg_strs: Vec<(String, String)>,
+    pub(crate) flake_overrides: Vec<(String, String)>,
+    pub(crate) impure: bool,
+    pub(crate) outputs: Vec<String>,
+    backend: Backend,
+    output_format: OutputFormat,
+    rpath: bool,
+    forward_warnings: bool,
+    cache: bool,
+    cache_dir: Option<PathBuf>,
+    lock: Option<Option<PathBuf>>,
+    track_dependencies: bool,
+    tracked_env: Vec<String>,
+    clear_env: bool,
+    kept_env: Vec<String>,
+    env_vars: Vec<(OsString, Option<OsString>)>,
+    cargo_integration: Option<bool>,
+    verify_outputs: bool,
+    sign_key_file: Option<PathBuf>,
+    copy_to: Vec<String>,
+    access_tokens: Vec<(String, String)>,
+    netrc_file: Option<PathBuf>,
+    pub(crate) eval_store: Option<String>,
+    store_root: Option<PathBuf>,
+    ca_derivations: bool,
+    system: Option<String>,
+    substituters: Vec<String>,
+    options: Vec<(String, String)>,
+    nice: Option<i32>,
+    max_silent_time: Option<u64>,
+    build_timeout: Option<u64>,
+    respect_cargo_jobs: bool,
+    license_policy: Option<LicensePolicy>,
+    out_link: Option<PathBuf>,
+    gc_root: bool,
+    shared_out_link: bool,
+    write_manifest: bool,
+    retries: u32,
+    fallback: bool,
+    eval_cache: bool,
+    allow_ifd: bool,
+    require_sigs: Option<bool>,
+    trusted_substituters_only: bool,
+    sandbox: Option<SandboxMode>,
+    sandbox_paths: Option<Vec<String>>,
+    extra_sandbox_paths: Vec<String>,
+    use_cgroups: Option<bool>,
+    on_event: Option<EventHandler>,
+    nom: bool,
+    log_file: Option<PathBuf>,
+    pub(crate) runner: Option<Arc<dyn NixRunner>>,
+    pub(crate) process: Option<Process>,
+    pub(crate) drop_policy: DropPolicy,
+    #[cfg(feature = "indicatif")]
+    progress: bool,
 }
 
 impl Default for Config {
@@ -33,34 +103,116 @@ impl Default for Config {
     }
 }
 
-/// Represents a nix build output derivation
-#[derive(Debug, serde::Deserialize)]
-pub struct Derivation {
-    #[serde(alias = "drvPath")]
-    /// Derivation path
-    pub drv_path: PathBuf,
-    /// List of outputs for this derivation
-    ///
-    /// Example outputs: `out`, `dev`
-    pub outputs: HashMap<String, PathBuf>,
+/// Target picked by [`Config::auto`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DetectedTarget {
+    /// `flake.nix`, built as `.#default`
+    Flake,
+    /// `default.nix`
+    DefaultNix,
+    /// `shell.nix`
+    ShellNix,
 }
 
-impl Derivation {
-    pub fn out(&self) -> Option<&PathBuf> {
-        self.outputs.get("out")
+impl Config {
+    /// Create a new nix build [`Config`] targeting whatever is found in the working directory
+    ///
+    /// Picks, in order, `flake.nix` (as `.#default`), `default.nix` and `shell.nix`,
+    /// returning which one has been chosen
+    ///
+    /// # Example
+    /// ```no_run
+    /// # fn main() -> Result<(), nix_build::Error> {
+    /// use nix_build::Config;
+    ///
+    /// let (mut config, detected) = Config::auto()?;
+    /// println!("cargo:warning=building {detected:?}");
+    ///
+    /// let derivations = config.impure(true).build()?;
+    /// # Ok(()) }
+    /// ```
+    pub fn auto() -> Result<(Self, DetectedTarget)> {
+        let cwd = std::env::current_dir()?;
+        let mut config = Self::new();
+
+        let detected = if cwd.join("flake.nix").is_file() {
+            config.target_flake(".#default");
+            DetectedTarget::Flake
+        } else if cwd.join("default.nix").is_file() {
+            config.target_file("default.nix");
+            DetectedTarget::DefaultNix
+        } else if cwd.join("shell.nix").is_file() {
+            config.target_file("shell.nix");
+            DetectedTarget::ShellNix
+        } else {
+            return Err(Error::NoTargetFound);
+        };
+
+        Ok((config, detected))
     }
-}
 
-impl Config {
     /// Create a new nix build [`Config`]
     ///
     /// Target is defaulted to `default.nix`
     pub fn new() -> Self {
         Self {
-            target: NixTarget::default(),
+            target: Installable::default(),
             arg_exprs: vec![],
             arg_strs: vec![],
+            flake_overrides: vec![],
             impure: false,
+            outputs: vec![],
+            backend: Backend::default(),
+            output_format: OutputFormat::default(),
+            rpath: false,
+            forward_warnings: false,
+            cache: false,
+            cache_dir: None,
+            lock: None,
+            track_dependencies: false,
+            tracked_env: vec![],
+            clear_env: false,
+            kept_env: vec![],
+            env_vars: vec![],
+            cargo_integration: None,
+            verify_outputs: false,
+            sign_key_file: None,
+            copy_to: vec![],
+            access_tokens: vec![],
+            netrc_file: None,
+            eval_store: None,
+            store_root: None,
+            ca_derivations: false,
+            system: None,
+            substituters: vec![],
+            options: vec![],
+            nice: None,
+            max_silent_time: None,
+            build_timeout: None,
+            respect_cargo_jobs: false,
+            license_policy: None,
+            out_link: None,
+            gc_root: false,
+            shared_out_link: false,
+            write_manifest: false,
+            retries: 0,
+            fallback: false,
+            eval_cache: true,
+            allow_ifd: true,
+            require_sigs: None,
+            trusted_substituters_only: false,
+            sandbox: None,
+            sandbox_paths: None,
+            extra_sandbox_paths: vec![],
+            use_cgroups: None,
+            on_event: None,
+            nom: false,
+            log_file: None,
+            runner: None,
+            process: None,
+            drop_policy: DropPolicy::default(),
+            #[cfg(feature = "indicatif")]
+            progress: false,
         }
     }
 
@@ -68,7 +220,10 @@ impl Config {
     ///
     /// # Example
     /// ```
-    /// Config::default().arg_expr("{pkgs}:pkgs.hello").arg_expr("pkgs", "import <nixpkgs> {}")
+    /// # use nix_build::Config;
+    /// Config::default()
+    ///     .target_expr("{pkgs}: pkgs.hello")
+    ///     .arg_expr("pkgs", "import <nixpkgs> {}");
     /// ```
     pub fn arg_expr(&mut self, name: &str, value: &str) -> &mut Self {
         self.arg_exprs.push((name.to_owned(), value.to_owned()));
@@ -79,43 +234,159 @@ impl Config {
     ///
     /// # Example
     /// ```
-    /// Config::default().arg_str("{pkgs,name}:pkgs.hello.overrideAttrs (_: {inherit name;})").arg_str("name", "not-hello")
+    /// # use nix_build::Config;
+    /// Config::default()
+    ///     .target_expr("{pkgs, name}: pkgs.hello.overrideAttrs (_: {inherit name;})")
+    ///     .arg_expr("pkgs", "import <nixpkgs> {}")
+    ///     .arg_str("name", "not-hello");
     /// ```
     pub fn arg_str(&mut self, name: &str, value: &str) -> &mut Self {
         self.arg_strs.push((name.to_owned(), value.to_owned()));
         self
     }
 
+    /// Add the path of a file with the given contents as an argument to the invoked nix expression
+    ///
+    /// The file is added to the store as `name`, see [`exprs::write_text`](crate::exprs::write_text)
+    ///
+    /// # Example
+    /// ```
+    /// # use nix_build::Config;
+    /// Config::default()
+    ///     .target_expr("{pkgs, config}: pkgs.hello.overrideAttrs (_: {inherit config;})")
+    ///     .arg_expr("pkgs", "import <nixpkgs> {}")
+    ///     .arg_text("config", "#define GREETING \"hi\"\n");
+    /// ```
+    pub fn arg_text(&mut self, name: &str, contents: &str) -> &mut Self {
+        self.arg_expr(name, &crate::exprs::write_text(name, contents))
+    }
+
+    /// Resolve the indirect flake reference `name`, like `nixpkgs`, to `flake_ref`,
+    /// with `--override-flake`
+    ///
+    /// Applies to the references used inside expressions too, like `flake:nixpkgs` or
+    /// `builtins.getFlake "nixpkgs"`, so they resolve to a local checkout during development.
+    /// Not supported by the legacy backend
+    ///
+    /// # Example
+    /// ```
+    /// # use nix_build::Config;
+    /// Config::default()
+    ///     .target_expr("(builtins.getFlake \"nixpkgs\").legacyPackages.x86_64-linux.hello")
+    ///     .override_flake("nixpkgs", "/home/user/src/nixpkgs");
+    /// ```
+    pub fn override_flake(&mut self, name: &str, flake_ref: &str) -> &mut Self {
+        self.flake_overrides
+            .push((name.to_owned(), flake_ref.to_owned()));
+        self
+    }
+
     /// Build the derivation described by the given .nix file
     ///
     /// # Example
     /// ```
-    /// Config::default().target_file("hello.nix")
+    /// # use nix_build::Config;
+    /// Config::default().target_file("hello.nix");
     /// ```
     pub fn target_file(&mut self, filename: impl AsRef<OsStr>) -> &mut Self {
-        self.target = NixTarget::Function(filename.as_ref().to_owned());
+        self.target = Installable::file(filename.as_ref());
         self
     }
 
     /// Build the derivation described by the given flake output
     ///
+    /// The reference is validated by [`Config::validate`], see [`FlakeRef`]
+    ///
     /// # Example
     /// ```
-    /// Config::default().target_flake("nixpkgs#hello")
+    /// # use nix_build::{Config, FlakeRef};
+    /// Config::default().target_flake("nixpkgs#hello");
+    /// Config::default().target_flake(FlakeRef::github("NixOS", "nixpkgs").with_attr("hello"));
     /// ```
-    pub fn target_flake(&mut self, flake: &str) -> &mut Self {
-        self.target = NixTarget::Flake(flake.to_owned());
+    pub fn target_flake(&mut self, flake: impl Into<FlakeRef>) -> &mut Self {
+        self.target = Installable::flake(flake);
         self
     }
 
     /// Build the derivation described by the given expression
     ///
     /// # Example
-    /// ```
-    /// Config::default().target_expr("{pkgs}: pkgs.hello").build()
+    /// ```no_run
+    /// # use nix_build::Config;
+    /// Config::default()
+    ///     .target_expr("{pkgs}: pkgs.hello")
+    ///     .arg_expr("pkgs", "import <nixpkgs> {}")
+    ///     .build();
     /// ```
     pub fn target_expr(&mut self, expr: &str) -> &mut Self {
-        self.target = NixTarget::Expr(expr.to_owned());
+        self.target = Installable::expr(expr);
+        self
+    }
+
+    /// Build the given installable
+    ///
+    /// See [`Installable`]
+    pub fn target(&mut self, installable: impl Into<Installable>) -> &mut Self {
+        self.target = installable.into();
+        self
+    }
+
+    /// Select the attribute `attr` of the target file or expression
+    ///
+    /// Must be set after the target. Ignored for flake targets,
+    /// which include the attribute in the installable
+    ///
+    /// # Example
+    /// ```
+    /// # use nix_build::Config;
+    /// Config::default().target_file("release.nix").attribute("packages.foo");
+    /// ```
+    pub fn attribute(&mut self, attr: &str) -> &mut Self {
+        if let Installable::File { attr: old, .. } | Installable::Expr { attr: old, .. } =
+            &mut self.target
+        {
+            *old = Some(attr.to_owned());
+        }
+        self
+    }
+
+    /// Build only the outputs `names` of the target, like `dev`, or `*` for all of them
+    ///
+    /// By default nix builds the outputs to install of the target, usually `out` and `bin`,
+    /// downloading their whole runtime closures from the binary caches. Selecting `dev` for
+    /// the headers of a library avoids downloading the closure of `out`, unless `dev`
+    /// references it, which saves bandwidth on large packages.
+    /// Overrides the outputs selected with `^` in flake references
+    ///
+    /// # Example
+    /// ```no_run
+    /// # fn main() -> Result<(), nix_build::Error> {
+    /// let derivations = nix_build::Config::new()
+    ///     .target_flake("nixpkgs#openssl")
+    ///     .outputs(&["dev"])
+    ///     .build()?;
+    ///
+    /// let include = derivations[0].include_dir();
+    /// # Ok(()) }
+    /// ```
+    pub fn outputs(&mut self, names: &[&str]) -> &mut Self {
+        self.outputs = names.iter().map(|name| (*name).to_owned()).collect();
+        self
+    }
+
+    /// Set the program used to build the target
+    ///
+    /// See [`Backend`]
+    pub fn backend(&mut self, backend: Backend) -> &mut Self {
+        self.backend = backend;
+        self
+    }
+
+    /// Set how the results of `nix build` are retrieved
+    ///
+    /// See [`OutputFormat`]
+    pub fn output_format(&mut self, format: OutputFormat) -> &mut Self {
+        self.output_format = format;
         self
     }
 
@@ -127,74 +398,1973 @@ impl Config {
         self
     }
 
-    /// Invoke `nix build` with the given configuration
-    #[must_use]
-    pub fn build(&self) -> Result<Vec<Derivation>> {
-        let nix = crate::is_nix_available().ok_or(Error::NixNotAvailable)?;
+    /// Set to emit rpath link arguments for the built derivations
+    ///
+    /// See [`Derivation::emit_rpath`]
+    pub fn rpath(&mut self, rpath: bool) -> &mut Self {
+        self.rpath = rpath;
+        self
+    }
 
-        let cwd = std::env::current_dir().unwrap();
-        let mut cmd = Command::new(nix);
-        cmd.current_dir(&cwd);
-        cmd.arg("build");
+    /// Set to forward the warnings printed by nix as `cargo:warning` directives
+    ///
+    /// Makes warnings like a dirty git tree or an untrusted substituter
+    /// show up in the cargo build summary
+    pub fn forward_warnings(&mut self, forward: bool) -> &mut Self {
+        self.forward_warnings = forward;
+        self
+    }
 
-        cmd.args(&["--no-link", "--json"]);
+    /// Create a symlink to the built outputs at `path`, registered as a GC root
+    ///
+    /// Named like `nix build --out-link`: `path` for the `out` output,
+    /// `path-<output>` for the others and `path-<n>` for the derivations after the first
+    pub fn out_link(&mut self, path: impl Into<PathBuf>) -> &mut Self {
+        self.out_link = Some(path.into());
+        self
+    }
 
-        match &self.target {
-            NixTarget::Function(file) => {
-                cmd.args(&[OsStr::new("-f"), &file]);
+    /// Set to protect the built outputs from garbage collection
+    ///
+    /// Creates an out-link named after the fingerprint of the configuration
+    /// in `nix-gc-roots` in the cargo profile directory (like `target/debug`).
+    /// Remove them with [`store::remove_crate_roots`](crate::store::remove_crate_roots),
+    /// or the stale ones only with [`store::clean_roots`](crate::store::clean_roots)
+    pub fn gc_root(&mut self, enabled: bool) -> &mut Self {
+        self.gc_root = enabled;
+        self
+    }
 
-                // make sure the build script is rerun if the file changes
-                println!(
-                    "cargo:rerun-if-changed={}",
-                    AsRef::<Path>::as_ref(file).display()
-                );
-            }
-            NixTarget::Flake(installable) => {
-                cmd.arg(installable);
+    /// Set to create the out-link in the `nix` directory of the cargo target directory,
+    /// like `target/nix/<package>-<hash>`, named after the package and a hash of the target
+    ///
+    /// The build scripts of a workspace, run concurrently, don't overwrite each other's links
+    /// this way. Outputs and derivations are suffixed like [`Config::out_link`],
+    /// which takes precedence
+    pub fn shared_out_link(&mut self, enabled: bool) -> &mut Self {
+        self.shared_out_link = enabled;
+        self
+    }
 
-                // try to detect if the flake is local
-                if let Some(Ok(local_flake)) = cwd
-                    .to_string_lossy()
-                    .split_once('#')
-                    .map(|(path, _)| path)
-                    .map(std::fs::canonicalize)
-                {
-                    // and if so, rerun if it changes
-                    println!(
-                        "cargo:rerun-if-changed={}",
-                        local_flake.join("flake.lock").display()
-                    );
-                }
+    /// Set to record the store paths, derivations and flake revision of the build
+    /// in the manifest of the workspace, `target/nix-manifest.json`
+    ///
+    /// The builds of every crate of the workspace are merged in the manifest, replacing
+    /// the previous build of the same crate and target. See [`BuildManifest`](crate::BuildManifest)
+    pub fn write_manifest(&mut self, enabled: bool) -> &mut Self {
+        self.write_manifest = enabled;
+        self
+    }
+
+    /// Call `handler` with the progress of the build, as reported by nix
+    ///
+    /// # Example
+    /// ```no_run
+    /// # fn main() -> Result<(), nix_build::Error> {
+    /// use nix_build::{BuildEvent, Config};
+    ///
+    /// let derivations = Config::new()
+    ///     .target_flake("nixpkgs#openssl")
+    ///     .on_event(|event| {
+    ///         if let BuildEvent::BuildingDrv { drv_path } = event {
+    ///             println!("cargo:warning=building {}", drv_path.display());
+    ///         }
+    ///     })
+    ///     .build()?;
+    /// # Ok(()) }
+    /// ```
+    pub fn on_event(&mut self, handler: impl FnMut(BuildEvent) + Send + 'static) -> &mut Self {
+        self.on_event = Some(Arc::new(Mutex::new(handler)));
+        self
+    }
+
+    /// Set to render the progress of the build on stderr
+    ///
+    /// Only drawn when stderr is a terminal, which cargo doesn't provide to build scripts
+    /// unless they're run by hand
+    #[cfg(feature = "indicatif")]
+    pub fn progress(&mut self, enabled: bool) -> &mut Self {
+        self.progress = enabled;
+        self
+    }
+
+    /// Set to render the build with [nix-output-monitor](https://github.com/maralorn/nix-output-monitor)
+    /// on stderr, when `nom` is found in `PATH`
+    ///
+    /// The log is still parsed by the crate, see [`Config::on_event`]
+    pub fn nom(&mut self, enabled: bool) -> &mut Self {
+        self.nom = enabled;
+        self
+    }
+
+    /// Write the full log of the build to `path`, like `$OUT_DIR/nix-build.log`
+    ///
+    /// The log is written regardless of the verbosity, and its path is reported
+    /// by [`Error::BuildError`]
+    pub fn log_file(&mut self, path: impl Into<PathBuf>) -> &mut Self {
+        self.log_file = Some(path.into());
+        self
+    }
+
+    /// Truncate the log file, if any, before invoking nix
+    fn create_log_file(&self) -> Result<()> {
+        if let Some(file) = &self.log_file {
+            if let Some(dir) = file.parent() {
+                std::fs::create_dir_all(dir)?;
             }
-            NixTarget::Expr(expr) => {
-                cmd.args(["--expr", expr.as_str()]);
+            File::create(file)?;
+        }
+        Ok(())
+    }
+
+    /// Classify the failure reported in `stderr`, pointing at the log file if any
+    fn failure(&self, stderr: &str) -> Error {
+        let mut err = failure::classify(stderr);
+        if let Error::BuildError { log_file, .. } = &mut err {
+            log_file.clone_from(&self.log_file);
+        }
+        err
+    }
+
+    /// Returns the handlers of the events of the build
+    fn event_handlers(&self) -> Vec<EventHandler> {
+        let handlers = self.on_event.iter().cloned();
+
+        #[cfg(feature = "indicatif")]
+        let handlers = handlers.chain(
+            self.progress
+                .then(|| crate::progress::handler(&self.target.to_string())),
+        );
+
+        handlers.collect()
+    }
+
+    /// Returns the out-link to create, if any
+    fn out_link_path(&self, cwd: &Path) -> Result<Option<PathBuf>> {
+        if let Some(path) = &self.out_link {
+            return Ok(Some(cwd.join(path)));
+        }
+        if self.shared_out_link {
+            let dir = cargo::profile_dir()
+                .and_then(|profile| Some(profile.parent()?.join("nix")))
+                .ok_or(Error::MissingEnvVar("OUT_DIR"))?;
+            let package = std::env::var("CARGO_PKG_NAME")
+                .map_err(|_| Error::MissingEnvVar("CARGO_PKG_NAME"))?;
+
+            let mut hasher = Hasher::new();
+            hasher.write(self.selected_target().to_string());
+            if let Some(system) = &self.system {
+                hasher.write(system);
             }
+            return Ok(Some(dir.join(format!("{package}-{}", hasher.finish()))));
+        }
+        if !self.gc_root {
+            return Ok(None);
         }
 
-        for (key, val) in &self.arg_exprs {
-            cmd.args(&["--arg", &key, &val]);
+        let dir = cargo::profile_dir()
+            .ok_or(Error::MissingEnvVar("OUT_DIR"))?
+            .join("nix-gc-roots");
+        let name = format!("{}{}", store::gc::ROOT_PREFIX, self.fingerprint(cwd)?);
+
+        Ok(Some(dir.join(name)))
+    }
+
+    /// Register the outputs of the given derivations as GC roots, named after `link`
+    fn create_roots(link: &Path, derivations: &[Derivation]) -> Result<()> {
+        for (i, derivation) in derivations.iter().enumerate() {
+            let mut base = link.as_os_str().to_owned();
+            if i > 0 {
+                base.push(format!("-{i}"));
+            }
+
+            for (output, path) in &derivation.outputs {
+                let mut link = base.clone();
+                if output != "out" {
+                    link.push(format!("-{output}"));
+                }
+                store::add_root(path, PathBuf::from(link))?;
+            }
         }
 
-        for (key, val) in &self.arg_strs {
-            cmd.args(&["--argstr", &key, &val]);
+        Ok(())
+    }
+
+    /// Set to cache the build result across runs
+    ///
+    /// The result is stored under `$OUT_DIR`, or the directory set with [`Config::cache_dir`],
+    /// keyed by a fingerprint of the effective configuration and the version of nix.
+    /// The content of the directory of the target file, of the local flake or of the current
+    /// directory for expressions is part of the fingerprint, so editing any file imported
+    /// from there invalidates the result. Hidden files and `target` directories are ignored,
+    /// as are files imported from elsewhere.
+    ///
+    /// The cached result is only used if all of its output paths still exist
+    pub fn cache(&mut self, cache: bool) -> &mut Self {
+        self.cache = cache;
+        self
+    }
+
+    /// Set the directory to store cached results in
+    ///
+    /// Defaults to `$OUT_DIR/nix-build-cache`
+    pub fn cache_dir(&mut self, dir: impl Into<PathBuf>) -> &mut Self {
+        self.cache_dir = Some(dir.into());
+        self
+    }
+
+    /// Set to serialize the nix invocations of concurrent build scripts
+    ///
+    /// A file lock is held for the duration of the build,
+    /// by default `nix-build.lock` in the cargo profile directory (like `target/debug`).
+    /// When combined with [`Config::cache`] identical concurrent builds are only run once
+    pub fn lock(&mut self, lock: bool) -> &mut Self {
+        self.lock = lock.then_some(None);
+        self
+    }
+
+    /// Serialize the nix invocations using the given lock file
+    ///
+    /// See [`Config::lock`]
+    pub fn lock_file(&mut self, path: impl Into<PathBuf>) -> &mut Self {
+        self.lock = Some(Some(path.into()));
+        self
+    }
+
+    /// Acquire the configured lock, if any
+    fn acquire_lock(&self) -> Result<Option<File>> {
+        let Some(path) = &self.lock else {
+            return Ok(None);
+        };
+
+        let path = match path {
+            Some(path) => path.clone(),
+            None => cargo::profile_dir()
+                .ok_or(Error::MissingEnvVar("OUT_DIR"))?
+                .join("nix-build.lock"),
+        };
+
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
         }
 
-        if self.impure {
-            cmd.arg("--impure");
+        let file = File::options()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(path)?;
+        lock_exclusive(&file)?;
+
+        Ok(Some(file))
+    }
+
+    /// Set to emit `cargo:rerun-if-changed` for every local file read during evaluation
+    ///
+    /// The evaluated files are discovered by increasing the verbosity of nix,
+    /// which also requires disabling the flake evaluation cache.
+    /// Files are translated back from the store copy of local flakes, while any other
+    /// store path is ignored
+    pub fn track_dependencies(&mut self, track: bool) -> &mut Self {
+        self.track_dependencies = track;
+        self
+    }
+
+    /// Rerun the build script when the environment variable `name` changes
+    ///
+    /// Meant for variables read by impure evaluations, like with `builtins.getEnv`.
+    /// The variables consulted by nix itself, like `NIX_PATH` and `NIX_CONFIG`, are always tracked
+    pub fn track_env(&mut self, name: &str) -> &mut Self {
+        self.tracked_env.push(name.to_owned());
+        self
+    }
+
+    /// Set to clear the environment of the spawned `nix` commands, except for the variables
+    /// kept with [`Config::keep_env`]
+    ///
+    /// Keeps impure evaluations from depending on the environment of the developer.
+    /// `HOME` and `NIX_*` are usually needed by nix itself, and `NIX_CONFIG` is only extended
+    /// with the settings of the build when kept. Not applied to the invocations of a [`Config::runner`]
+    pub fn clear_env(&mut self, clear: bool) -> &mut Self {
+        self.clear_env = clear;
+        self
+    }
+
+    /// Keep the environment variables `names` when the environment is cleared with [`Config::clear_env`]
+    ///
+    /// Names ending with `*` keep every variable starting with the prefix, like `NIX_*`
+    pub fn keep_env(&mut self, names: &[&str]) -> &mut Self {
+        self.kept_env
+            .extend(names.iter().map(|name| (*name).to_owned()));
+        self
+    }
+
+    /// Set the environment variable `key` to `value` on the `nix` invocations only,
+    /// like `GIT_SSH_COMMAND`, without changing the environment of the build script
+    ///
+    /// The nix settings of the build are appended to a `NIX_CONFIG` set this way
+    pub fn env(&mut self, key: impl AsRef<OsStr>, value: impl AsRef<OsStr>) -> &mut Self {
+        self.set_env_var(key.as_ref(), Some(value.as_ref().to_owned()));
+        self
+    }
+
+    /// Remove the environment variable `key` from the `nix` invocations only
+    ///
+    /// Not applied to the invocations of a [`Config::runner`], which only get the variables to set
+    pub fn env_remove(&mut self, key: impl AsRef<OsStr>) -> &mut Self {
+        self.set_env_var(key.as_ref(), None);
+        self
+    }
+
+    /// Set the command run by git to connect over ssh, like `ssh -i deploy_key`,
+    /// to fetch the `git+ssh://` inputs of private flakes
+    ///
+    /// Sets the `GIT_SSH_COMMAND` environment variable on the `nix` invocations
+    pub fn git_ssh_command(&mut self, cmd: impl AsRef<OsStr>) -> &mut Self {
+        self.env("GIT_SSH_COMMAND", cmd)
+    }
+
+    /// Set the socket of the ssh agent holding the keys to fetch `git+ssh://` inputs
+    ///
+    /// Sets the `SSH_AUTH_SOCK` environment variable on the `nix` invocations
+    pub fn ssh_auth_sock(&mut self, socket: impl AsRef<Path>) -> &mut Self {
+        self.env("SSH_AUTH_SOCK", socket.as_ref())
+    }
+
+    /// Set whether the ssh agent of the build script, from `SSH_AUTH_SOCK`,
+    /// is available to the `nix` invocations
+    ///
+    /// When forwarded, `SSH_AUTH_SOCK` is kept even if the environment is cleared with
+    /// [`Config::clear_env`]. Otherwise it's removed, so fetching private inputs can't
+    /// use the keys of the developer
+    pub fn forward_ssh_agent(&mut self, forward: bool) -> &mut Self {
+        let name = OsStr::new("SSH_AUTH_SOCK");
+        if forward {
+            self.env_vars
+                .retain(|(key, value)| key != name || value.is_some());
+            self.keep_env(&["SSH_AUTH_SOCK"])
+        } else {
+            self.env_remove(name)
         }
+    }
 
-        //show build logs
-        cmd.arg("-L");
+    fn set_env_var(&mut self, key: &OsStr, value: Option<OsString>) {
+        self.env_vars.retain(|(name, _)| name != key);
+        self.env_vars.push((key.to_owned(), value));
+    }
 
-        // enable split commands and flakes
-        cmd.args(&["--experimental-features", "nix-command flakes"]);
+    /// Set to emit cargo directives, like `cargo:rerun-if-changed`, during the build
+    ///
+    /// By default directives are only emitted when running inside a build script,
+    /// detected by the presence of the `CARGO` and `TARGET` environment variables.
+    /// Disable to use the crate outside of build scripts without polluting stdout
+    pub fn cargo_integration(&mut self, enabled: bool) -> &mut Self {
+        self.cargo_integration = Some(enabled);
+        self
+    }
 
-        let output = cmd.output().map_err(|_| Error::BuildFailed)?;
+    /// Set to check that the outputs of each fresh build are valid paths of the local store
+    ///
+    /// `nix build` can report outputs that are not present locally, like when building
+    /// for another store or after a garbage collection racing with the build.
+    /// Fails the build with [`Error::OutputMissing`] for the first such output.
+    /// Disabled by default, as it costs an extra `nix path-info` invocation,
+    /// which is skipped when a [`Config::runner`] is set
+    pub fn verify_outputs(&mut self, enabled: bool) -> &mut Self {
+        self.verify_outputs = enabled;
+        self
+    }
 
-        if !output.status.success() {
-            return Err(Error::BuildFailed);
+    /// Sign the built outputs with the secret key in `key_file` after each fresh build
+    ///
+    /// Outputs are signed before being copied with [`Config::post_build_copy`]
+    pub fn sign_with(&mut self, key_file: impl Into<PathBuf>) -> &mut Self {
+        self.sign_key_file = Some(key_file.into());
+        self
+    }
+
+    /// Copy the built outputs to the store at `store_uri` after each fresh build
+    ///
+    /// Results reused with [`Config::cache`] are not copied again.
+    /// See [`store::CopyTo`](crate::store::CopyTo) for more options
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use nix_build::Config;
+    /// let derivations = Config::new()
+    ///     .post_build_copy("s3://my-cache?region=eu-west-1")
+    ///     .build();
+    /// ```
+    pub fn post_build_copy(&mut self, store_uri: impl Into<String>) -> &mut Self {
+        self.copy_to.push(store_uri.into());
+        self
+    }
+
+    /// Authenticate requests to `host` with `token`, like for private flake inputs
+    ///
+    /// Maps to the `access-tokens` nix setting, passed through `NIX_CONFIG`
+    /// to keep the token out of the command line
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use nix_build::Config;
+    /// let token = std::env::var("GITHUB_TOKEN").unwrap();
+    /// let derivations = Config::new()
+    ///     .target_flake("github:my-org/private-deps#native")
+    ///     .access_token("github.com", token)
+    ///     .build();
+    /// ```
+    pub fn access_token(&mut self, host: impl Into<String>, token: impl Into<String>) -> &mut Self {
+        self.access_tokens.push((host.into(), token.into()));
+        self
+    }
+
+    /// Use the given netrc file to authenticate downloads
+    ///
+    /// Maps to the `netrc-file` nix setting
+    pub fn netrc_file(&mut self, path: impl Into<PathBuf>) -> &mut Self {
+        self.netrc_file = Some(path.into());
+        self
+    }
+
+    /// Evaluate the target in the store at `uri`, building and substituting in the default one
+    ///
+    /// Maps to `--eval-store`, like to evaluate in a throwaway store with
+    /// `local?root=/tmp/eval`. Not supported by the legacy backend
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use nix_build::Config;
+    /// let derivations = Config::new()
+    ///     .target_flake("nixpkgs#hello")
+    ///     .eval_store("local?root=/tmp/eval")
+    ///     .build();
+    /// ```
+    pub fn eval_store(&mut self, uri: impl Into<String>) -> &mut Self {
+        self.eval_store = Some(uri.into());
+        self
+    }
+
+    /// Use the local store rooted at `dir`, like `<dir>/nix/store`, instead of the default one
+    ///
+    /// Maps to the `store` nix setting set to `local?root=<dir>`, so nix can be used without
+    /// privileges or a `/nix` mount, and without a daemon. The returned output paths are
+    /// translated to their physical location under `dir`, while programs built in the store
+    /// keep referring to `/nix/store`.
+    ///
+    /// A result reused with [`Config::cache`] is only valid while its outputs are in `dir`,
+    /// but removing `dir` doesn't rerun the build script by itself.
+    /// Out links, GC roots, signing, copying and `--print-out-paths` go through the default
+    /// store, so they are rejected by [`Config::validate`], and [`Config::verify_outputs`]
+    /// only checks that the outputs exist
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use nix_build::Config;
+    /// let out_dir = std::env::var("OUT_DIR").unwrap();
+    /// let derivations = Config::new()
+    ///     .target_flake("nixpkgs#hello")
+    ///     .store_root(format!("{out_dir}/nix-root"))
+    ///     .build();
+    /// ```
+    pub fn store_root(&mut self, dir: impl Into<PathBuf>) -> &mut Self {
+        self.store_root = Some(dir.into());
+        self
+    }
+
+    /// Returns where the store path `path` is found on the filesystem,
+    /// under the root set with [`Config::store_root`]
+    pub(crate) fn physical_path(&self, path: &Path) -> PathBuf {
+        match &self.store_root {
+            Some(root) => root.join(path.strip_prefix("/").unwrap_or(path)),
+            None => path.to_path_buf(),
+        }
+    }
+
+    /// Translate the paths of `derivations` to their location on the filesystem
+    fn physical_paths(&self, derivations: Vec<Derivation>) -> Result<Vec<Derivation>> {
+        if self.store_root.is_none() {
+            return Ok(derivations);
         }
 
-        serde_json::from_slice(&output.stdout).map_err(|_| Error::UnknownOutput)
+        derivations
+            .into_iter()
+            .map(|mut derivation| {
+                let physical = |path: &StorePath| StorePath::new(self.physical_path(path));
+                derivation.drv_path = physical(&derivation.drv_path)?;
+                for path in derivation.outputs.values_mut() {
+                    *path = physical(path)?;
+                }
+                Ok(derivation)
+            })
+            .collect()
+    }
+
+    /// Set to enable content-addressed derivations, the `ca-derivations` experimental feature
+    ///
+    /// Added to the `extra-experimental-features` nix setting.
+    /// The realisations reported for their outputs are found in [`Derivation::realisations`]
+    pub fn ca_derivations(&mut self, enabled: bool) -> &mut Self {
+        self.ca_derivations = enabled;
+        self
+    }
+
+    /// Build for the given system, like `aarch64-linux`
+    ///
+    /// Maps to the `system` nix setting, which also selects the outputs of flakes
+    pub fn system(&mut self, system: impl Into<String>) -> &mut Self {
+        self.system = Some(system.into());
+        self
+    }
+
+    /// Substitute store paths from the binary cache at `url`, besides the configured ones
+    ///
+    /// Maps to the `extra-substituters` nix setting, only honored by the nix daemon
+    /// for trusted users or caches listed in `trusted-substituters`
+    pub fn substituter(&mut self, url: impl Into<String>) -> &mut Self {
+        self.substituters.push(url.into());
+        self
+    }
+
+    /// Set whether substituted store paths must be signed by one of the `trusted-public-keys`
+    ///
+    /// Maps to the `require-sigs` nix setting, overriding the nix configuration.
+    /// The nix daemon only honors it for trusted users
+    pub fn require_sigs(&mut self, required: bool) -> &mut Self {
+        self.require_sigs = Some(required);
+        self
+    }
+
+    /// Set to only substitute from the binary caches listed in the `trusted-substituters`
+    /// of the nix configuration
+    ///
+    /// Replaces the `substituters` nix setting with them, ignoring other caches configured
+    /// globally or with [`Config::substituter`]. [`Config::validate`] fails with
+    /// [`Error::InvalidConfig`] when none is trusted or the nix configuration couldn't be read,
+    /// instead of disabling every binary cache
+    pub fn trusted_substituters_only(&mut self, enabled: bool) -> &mut Self {
+        self.trusted_substituters_only = enabled;
+        self
+    }
+
+    /// Set whether builders run in a sandbox, overriding the `sandbox` nix setting
+    ///
+    /// Relaxed allows derivations marked `__noChroot` to escape it. Only honored by the
+    /// nix daemon for trusted users, see [`Config::preflight`]
+    ///
+    /// # Example
+    /// ```
+    /// # use nix_build::{Config, SandboxMode};
+    /// // inside a container without user namespaces
+    /// Config::default().sandbox(SandboxMode::Disabled);
+    /// ```
+    pub fn sandbox(&mut self, mode: SandboxMode) -> &mut Self {
+        self.sandbox = Some(mode);
+        self
+    }
+
+    /// Set the paths of the host exposed in the sandbox, replacing the `sandbox-paths`
+    /// nix setting
+    ///
+    /// Entries are paths, `target=source` to expose `source` at `target`, and end with `?`
+    /// when the source may not exist. Prefer [`Config::extra_sandbox_path`], which keeps the
+    /// configured ones, like `/bin/sh`
+    pub fn sandbox_paths(&mut self, paths: &[&str]) -> &mut Self {
+        self.sandbox_paths = Some(paths.iter().map(|path| (*path).to_owned()).collect());
+        self
+    }
+
+    /// Expose the host path `path` in the sandbox, like `/dev/nvidia0` or a socket
+    ///
+    /// Maps to the `extra-sandbox-paths` nix setting. Only honored by the nix daemon
+    /// for trusted users, see [`Config::preflight`]
+    ///
+    /// # Example
+    /// ```
+    /// # use nix_build::Config;
+    /// Config::default()
+    ///     .target_flake(".#cuda-kernels")
+    ///     .extra_sandbox_path("/dev/nvidia0")
+    ///     .extra_sandbox_path("/dev/nvidiactl")
+    ///     .extra_sandbox_path_at("/run/opengl-driver", "/run/opengl-driver");
+    /// ```
+    pub fn extra_sandbox_path(&mut self, path: impl AsRef<Path>) -> &mut Self {
+        self.extra_sandbox_paths
+            .push(path.as_ref().display().to_string());
+        self
+    }
+
+    /// Expose the host path `source` in the sandbox at `target`
+    ///
+    /// See [`Config::extra_sandbox_path`]
+    pub fn extra_sandbox_path_at(
+        &mut self,
+        target: impl AsRef<Path>,
+        source: impl AsRef<Path>,
+    ) -> &mut Self {
+        self.extra_sandbox_paths.push(format!(
+            "{}={}",
+            target.as_ref().display(),
+            source.as_ref().display()
+        ));
+        self
+    }
+
+    /// Set whether builds run in their own cgroup, to kill their processes reliably
+    /// and measure their resource usage
+    ///
+    /// Maps to the `use-cgroups` nix setting, Linux only, which requires
+    /// the `cgroups` experimental feature
+    pub fn use_cgroups(&mut self, enabled: bool) -> &mut Self {
+        self.use_cgroups = Some(enabled);
+        self
+    }
+
+    /// Set the nix setting `name` to `value`, like `--option name value`
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use nix_build::Config;
+    /// let derivations = Config::new()
+    ///     .substituter("https://my-cache.cachix.org")
+    ///     .option("extra-trusted-public-keys", "my-cache.cachix.org-1:...")
+    ///     .build();
+    /// ```
+    pub fn option(&mut self, name: impl Into<String>, value: impl Into<String>) -> &mut Self {
+        self.options.push((name.into(), value.into()));
+        self
+    }
+
+    /// Run `nix` with the scheduling priority `level`, from -20 (highest) to 19 (lowest)
+    ///
+    /// Builds run by the nix daemon, as in multi-user installations,
+    /// keep the priority of the daemon. Ignored on Windows
+    pub fn nice(&mut self, level: i32) -> &mut Self {
+        self.nice = Some(level);
+        self
+    }
+
+    /// Fail a build that doesn't print anything for `secs` seconds
+    ///
+    /// Maps to the `max-silent-time` nix setting
+    pub fn max_silent_time(&mut self, secs: u64) -> &mut Self {
+        self.max_silent_time = Some(secs);
+        self
+    }
+
+    /// Fail a build that runs for longer than `secs` seconds
+    ///
+    /// Maps to the `timeout` nix setting, which applies to each derivation built by nix.
+    /// The `nix` process itself is not killed
+    pub fn build_timeout(&mut self, secs: u64) -> &mut Self {
+        self.build_timeout = Some(secs);
+        self
+    }
+
+    /// Set to limit nix to the jobs cargo allows the build script to run, from `NUM_JOBS`
+    ///
+    /// Sets the `max-jobs` nix setting to 1 and `cores` to `NUM_JOBS`,
+    /// so nix builds one derivation at a time with as many cores as cargo allows.
+    /// Disabled by default, only applies when `NUM_JOBS` is set, like in build scripts
+    pub fn respect_cargo_jobs(&mut self, enabled: bool) -> &mut Self {
+        self.respect_cargo_jobs = enabled;
+        self
+    }
+
+    /// Retry the build up to `retries` times when it fails with a transient error,
+    /// like a binary cache timing out, see [`Error::is_transient`]
+    ///
+    /// Retries wait 1 second, doubling the delay after each attempt.
+    /// Failing builders and evaluation errors are never retried
+    pub fn retries(&mut self, retries: u32) -> &mut Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Set to build from source when the binary caches can't be reached
+    ///
+    /// A build failing with [`Error::SubstituterUnavailable`] is run again with the
+    /// `fallback` nix setting, like `nix build --fallback`, which may take much longer
+    pub fn fallback(&mut self, enabled: bool) -> &mut Self {
+        self.fallback = enabled;
+        self
+    }
+
+    /// Set to use the evaluation cache of flakes, enabled by default
+    ///
+    /// Disabling it sets the `eval-cache` nix setting to `false`, like `--no-eval-cache`.
+    /// To clear the stale cache of a single flake instead, see [`purge_eval_cache`](crate::purge_eval_cache)
+    pub fn eval_cache(&mut self, enabled: bool) -> &mut Self {
+        self.eval_cache = enabled;
+        self
+    }
+
+    /// Set to allow import from derivation, where the evaluation waits for a derivation
+    /// to be built, allowed by default
+    ///
+    /// Disallowing it sets the `allow-import-from-derivation` nix setting to `false`,
+    /// so an evaluation relying on it fails early with [`Error::ImportFromDerivation`]
+    /// instead of serializing the builds it needs
+    pub fn allow_ifd(&mut self, allowed: bool) -> &mut Self {
+        self.allow_ifd = allowed;
+        self
+    }
+
+    /// Run the `nix` invocations of the build with `runner`, instead of spawning them directly
+    ///
+    /// The invocations are not affected by [`Config::nice`], nor killed by
+    /// [`BuildHandle::kill`](crate::BuildHandle::kill), and their events are only emitted
+    /// once they exit. The legacy backend, which doesn't go through the runner, is never used
+    pub fn runner(&mut self, runner: impl NixRunner + 'static) -> &mut Self {
+        self.runner = Some(Arc::new(runner));
+        self
+    }
+
+    /// Fail the build with [`Error::LicenseDenied`] if the target's license isn't allowed by `policy`
+    ///
+    /// The licenses are evaluated from the `meta` attribute of the target, see [`Config::meta`]
+    pub fn license_policy(&mut self, policy: LicensePolicy) -> &mut Self {
+        self.license_policy = Some(policy);
+        self
+    }
+
+    /// Returns the nix settings set by this configuration
+    fn settings(&self) -> Vec<(String, String)> {
+        let mut settings = vec![];
+
+        if !self.access_tokens.is_empty() {
+            let tokens: Vec<String> = self
+                .access_tokens
+                .iter()
+                .map(|(host, token)| format!("{host}={token}"))
+                .collect();
+            settings.push(("access-tokens".to_owned(), tokens.join(" ")));
+        }
+
+        if let Some(path) = &self.netrc_file {
+            settings.push(("netrc-file".to_owned(), path.display().to_string()));
+        }
+
+        if self.ca_derivations {
+            settings.push((
+                "extra-experimental-features".to_owned(),
+                "ca-derivations".to_owned(),
+            ));
+        }
+        if let Some(root) = &self.store_root {
+            settings.push(("store".to_owned(), format!("local?root={}", root.display())));
+        }
+        if let Some(system) = &self.system {
+            settings.push(("system".to_owned(), system.clone()));
+        }
+
+        if self.trusted_substituters_only {
+            // an empty list would disable every binary cache, rejected by `validate`
+            let trusted = version::setting_list("trusted-substituters").unwrap_or_default();
+            if !trusted.is_empty() {
+                settings.push(("substituters".to_owned(), trusted.join(" ")));
+            }
+        } else if !self.substituters.is_empty() {
+            settings.push(("extra-substituters".to_owned(), self.substituters.join(" ")));
+        }
+
+        if let Some(mode) = self.sandbox {
+            settings.push(("sandbox".to_owned(), mode.setting().to_owned()));
+        }
+        if let Some(paths) = &self.sandbox_paths {
+            settings.push(("sandbox-paths".to_owned(), paths.join(" ")));
+        }
+        if !self.extra_sandbox_paths.is_empty() {
+            settings.push((
+                "extra-sandbox-paths".to_owned(),
+                self.extra_sandbox_paths.join(" "),
+            ));
+        }
+        if let Some(enabled) = self.use_cgroups {
+            settings.push(("use-cgroups".to_owned(), enabled.to_string()));
+        }
+
+        if let Some(required) = self.require_sigs {
+            settings.push(("require-sigs".to_owned(), required.to_string()));
+        }
+
+        if let Some(secs) = self.max_silent_time {
+            settings.push(("max-silent-time".to_owned(), secs.to_string()));
+        }
+
+        if let Some(secs) = self.build_timeout {
+            settings.push(("timeout".to_owned(), secs.to_string()));
+        }
+
+        if !self.eval_cache {
+            settings.push(("eval-cache".to_owned(), "false".to_owned()));
+        }
+
+        if !self.allow_ifd {
+            settings.push((
+                "allow-import-from-derivation".to_owned(),
+                "false".to_owned(),
+            ));
+        }
+
+        if let Some(jobs) = cargo::num_jobs().filter(|_| self.respect_cargo_jobs) {
+            settings.push(("max-jobs".to_owned(), "1".to_owned()));
+            settings.push(("cores".to_owned(), jobs.to_string()));
+        }
+
+        settings.extend(self.options.iter().cloned());
+
+        settings
+    }
+
+    /// Pass the nix settings and environment variables set by this configuration to `cmd`
+    ///
+    /// Settings are appended to the `NIX_CONFIG` environment variable, if set
+    pub(crate) fn configure(&self, cmd: &mut Command) {
+        if self.clear_env {
+            cmd.env_clear();
+            cmd.envs(std::env::vars_os().filter(|(name, _)| self.is_env_kept(name)));
+        }
+        for (name, _) in self.env_vars.iter().filter(|(_, value)| value.is_none()) {
+            cmd.env_remove(name);
+        }
+        cmd.envs(self.invocation_env());
+    }
+
+    /// Returns whether the environment variable `name` is passed to the spawned commands
+    fn is_env_kept(&self, name: &OsStr) -> bool {
+        if !self.clear_env {
+            return true;
+        }
+
+        let Some(name) = name.to_str() else {
+            return false;
+        };
+        self.kept_env
+            .iter()
+            .any(|kept| match kept.strip_suffix('*') {
+                Some(prefix) => name.starts_with(prefix),
+                None => name == kept,
+            })
+    }
+
+    /// Returns the environment variables set on the `nix` invocations
+    fn invocation_env(&self) -> Vec<(OsString, OsString)> {
+        let nix_config = OsStr::new("NIX_CONFIG");
+        let mut env: Vec<(OsString, OsString)> = self
+            .env_vars
+            .iter()
+            .filter(|(name, _)| name != nix_config)
+            .filter_map(|(name, value)| Some((name.clone(), value.clone()?)))
+            .collect();
+
+        let settings = self.settings();
+        let overridden = self.env_vars.iter().find(|(name, _)| name == nix_config);
+        if settings.is_empty() && overridden.is_none() {
+            return env;
+        }
+
+        let mut config = match overridden {
+            Some((_, value)) => value
+                .as_ref()
+                .map(|value| value.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            None => std::env::var("NIX_CONFIG")
+                .ok()
+                .filter(|_| self.runner.is_some() || self.is_env_kept(nix_config))
+                .unwrap_or_default(),
+        };
+        for (name, value) in settings {
+            if !config.is_empty() {
+                config.push('\n');
+            }
+            config.push_str(&format!("{name} = {value}"));
+        }
+
+        if !config.is_empty() {
+            env.push((nix_config.to_owned(), config.into()));
+        }
+        env
+    }
+
+    /// Returns whether cargo directives should be emitted
+    pub(crate) fn is_cargo_integrated(&self) -> bool {
+        self.cargo_integration
+            .unwrap_or_else(cargo::is_build_script)
+    }
+
+    /// Returns the path of the local flake targeted, if any
+    fn local_flake(&self, cwd: &Path) -> Option<PathBuf> {
+        let Installable::Flake { flake_ref, .. } = &self.target else {
+            return None;
+        };
+
+        if !flake_ref.is_local() {
+            return None;
+        }
+
+        let location = flake_ref.location();
+        let path = location.strip_prefix("//").unwrap_or(location);
+
+        std::fs::canonicalize(cwd.join(path)).ok()
+    }
+
+    /// Returns the arguments selecting the target, shared by the `nix` subcommands
+    pub(crate) fn installable_args(&self) -> Vec<OsString> {
+        self.installable_args_of(&self.target)
+    }
+
+    /// Returns the arguments selecting `target`, with the arguments and flags of the configuration
+    pub(crate) fn installable_args_of(&self, target: &Installable) -> Vec<OsString> {
+        let mut args = target.args();
+
+        for (key, val) in &self.arg_exprs {
+            args.extend(["--arg".into(), key.into(), val.into()]);
+        }
+
+        for (key, val) in &self.arg_strs {
+            args.extend(["--argstr".into(), key.into(), val.into()]);
+        }
+
+        for (name, flake_ref) in &self.flake_overrides {
+            args.extend(["--override-flake".into(), name.into(), flake_ref.into()]);
+        }
+
+        if self.impure {
+            args.push("--impure".into());
+        }
+
+        if let Some(uri) = &self.eval_store {
+            args.extend(["--eval-store".into(), uri.into()]);
+        }
+
+        args
+    }
+
+    /// Evaluate the target with `nix eval --json`, applying the nix function `apply` to it
+    pub(crate) fn eval_json<T: serde::de::DeserializeOwned>(&self, apply: &str) -> Result<T> {
+        let mut args: Vec<OsString> = vec!["eval".into(), "--json".into()];
+        args.extend(self.installable_args());
+        args.extend(["--apply".into(), apply.into()]);
+        args.extend(
+            version::experimental_features_args()
+                .iter()
+                .map(OsString::from),
+        );
+
+        let stdout = self.run_command(args).map_err(|err| match err {
+            Error::CommandFailed { stderr, .. } => failure::classify(&stderr),
+            err => err,
+        })?;
+
+        serde_json::from_slice(&stdout).map_err(|_| Error::UnknownOutput)
+    }
+
+    /// Run `nix` with the given arguments, through the [`Config::runner`] if set,
+    /// returning its stdout
+    pub(crate) fn run_command(&self, args: Vec<OsString>) -> Result<Vec<u8>> {
+        let output = self.run_output(args)?;
+        if !output.status.success() {
+            return Err(Error::CommandFailed {
+                program: "nix".to_owned(),
+                stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+            });
+        }
+        Ok(output.stdout)
+    }
+
+    /// Run `nix` with the given arguments, through the [`Config::runner`] if set,
+    /// returning its output whether it succeeded or not
+    pub(crate) fn run_output(&self, args: Vec<OsString>) -> Result<Output> {
+        debug!("running nix {args:?}");
+        match &self.runner {
+            Some(runner) => runner.run(&args, &self.invocation_env()),
+            None => {
+                let mut cmd = command::nix()?;
+                cmd.args(args);
+                self.configure(&mut cmd);
+                Ok(cmd.output()?)
+            }
+        }
+    }
+
+    /// Returns the target with the outputs selected by [`Config::outputs`]
+    ///
+    /// Outputs are selected with `^`, following the attribute of files and expressions
+    pub(crate) fn selected_target(&self) -> Installable {
+        if self.outputs.is_empty() {
+            return self.target.clone();
+        }
+
+        let with_outputs = |attr: &Option<String>| {
+            Some(format!(
+                "{}^{}",
+                attr.as_deref().unwrap_or_default(),
+                self.outputs.join(",")
+            ))
+        };
+        match &self.target {
+            Installable::File { file, attr } => Installable::File {
+                file: file.clone(),
+                attr: with_outputs(attr),
+            },
+            Installable::Expr { expr, attr } => Installable::Expr {
+                expr: expr.clone(),
+                attr: with_outputs(attr),
+            },
+            target => target.clone().with_outputs(self.outputs.iter().cloned()),
+        }
+    }
+
+    /// Returns the arguments to pass to `nix`
+    fn args(&self, format: OutputFormat) -> Vec<OsString> {
+        let mut args: Vec<OsString> =
+            vec!["build".into(), "--no-link".into(), format.flag().into()];
+        args.extend(self.installable_args_of(&self.selected_target()));
+
+        //show build logs
+        args.push("-L".into());
+        args.extend(build_log::LOG_FORMAT_ARGS.map(OsString::from));
+
+        if self.track_dependencies {
+            // report evaluated files
+            args.push("-v".into());
+
+            // a cached evaluation wouldn't report any file
+            if self.target.is_flake() {
+                args.push("--no-eval-cache".into());
+            }
+        }
+
+        // enable split commands and flakes
+        args.extend(
+            version::experimental_features_args()
+                .iter()
+                .map(OsString::from),
+        );
+
+        args
+    }
+
+    /// Returns the environment variables influencing the build
+    fn consulted_env(&self) -> Vec<&str> {
+        let mut vars = vec!["NIX", "NIX_CONFIG", "NIX_REMOTE", "NIX_USER_CONF_FILES"];
+
+        // pure flake evaluation doesn't use the search path
+        if !self.target.is_flake() || self.impure {
+            vars.push("NIX_PATH");
+        }
+
+        vars.extend(self.tracked_env.iter().map(String::as_str));
+        vars
+    }
+
+    /// Emit the cargo directives needed to rerun the build script when the target changes
+    fn emit_rerun_directives(&self, cwd: &Path) {
+        for var in self.consulted_env() {
+            cargo::rerun_if_env_changed(var);
+        }
+
+        if let Installable::File { file, .. } = &self.target {
+            // make sure the build script is rerun if the file changes
+            cargo::rerun_if_changed(file);
+        }
+
+        // if the flake is local, rerun if it changes
+        if let Some(local_flake) = self.local_flake(cwd) {
+            cargo::rerun_if_changed(&local_flake.join("flake.lock"));
+        }
+    }
+
+    /// Compute the fingerprint of the effective configuration
+    fn fingerprint(&self, cwd: &Path) -> Result<Fingerprint> {
+        let mut version = match self.backend {
+            Backend::Legacy => command::legacy("nix-build")?,
+            _ => command::nix().or_else(|_| command::legacy("nix-build"))?,
+        };
+        let version = version.arg("--version").output()?.stdout;
+
+        let mut hasher = Hasher::new();
+        hasher.write(version);
+        hasher.write(cwd.as_os_str().as_encoded_bytes());
+
+        for arg in self.args(self.output_format) {
+            hasher.write(arg.as_encoded_bytes());
+        }
+
+        // settings selecting what is built or the policies it's built under,
+        // unlike credentials or limits
+        if self.ca_derivations {
+            hasher.write("ca-derivations");
+        }
+        if !self.allow_ifd {
+            hasher.write("allow-import-from-derivation = false");
+        }
+        if let Some(required) = self.require_sigs {
+            hasher.write(format!("require-sigs = {required}"));
+        }
+        if let Some(root) = &self.store_root {
+            hasher.write(root.as_os_str().as_encoded_bytes());
+        }
+        if let Some(system) = &self.system {
+            hasher.write(system);
+        }
+        for (name, value) in &self.options {
+            hasher.write(name).write(value);
+        }
+
+        // the whole local source, as the target may import or copy any file next to it
+        let source = match &self.target {
+            Installable::File { file, .. } if cwd.join(file).is_dir() => Some(cwd.join(file)),
+            Installable::File { file, .. } => cwd.join(file).parent().map(Path::to_path_buf),
+            Installable::Expr { .. } => Some(cwd.to_path_buf()),
+            _ => self.local_flake(cwd),
+        };
+        if let Some(source) = source {
+            // the cache is written after the fingerprint is computed
+            let excluded: Vec<PathBuf> = self
+                .cache_dir
+                .iter()
+                .cloned()
+                .chain(std::env::var_os("OUT_DIR").map(PathBuf::from))
+                .map(|dir| std::fs::canonicalize(&dir).unwrap_or(dir))
+                .collect();
+            hasher.write_tree(&source, &excluded);
+        }
+
+        Ok(hasher.finish())
+    }
+
+    /// Returns the path of the cache file for the given fingerprint
+    fn cache_file(&self, fingerprint: Fingerprint) -> Result<PathBuf> {
+        let dir = match &self.cache_dir {
+            Some(dir) => dir.clone(),
+            None => std::env::var_os("OUT_DIR")
+                .map(|out_dir| Path::new(&out_dir).join("nix-build-cache"))
+                .ok_or(Error::MissingEnvVar("OUT_DIR"))?,
+        };
+
+        Ok(dir.join(format!("{fingerprint}.json")))
+    }
+
+    /// Returns the local files evaluated in the given build log
+    fn tracked_files(&self, cwd: &Path, log: &str) -> BTreeSet<PathBuf> {
+        let local_flake = self.local_flake(cwd);
+        let flake_source = local_flake
+            .as_ref()
+            .and_then(|flake| tracking::flake_source(flake));
+
+        let flake = local_flake
+            .as_deref()
+            .zip(flake_source.as_deref())
+            .map(|(local, source)| (source, local));
+
+        tracking::local_files(tracking::evaluated_files(log), flake)
+    }
+
+    /// Run `nix` with the given arguments, parsing its structured log as it's printed
+    ///
+    /// The plain text of the log is returned as stderr
+    fn run_nix(&self, cwd: &Path, args: Vec<OsString>) -> Result<(Output, BuildReport)> {
+        debug!("running nix {args:?}");
+
+        let process = self.process.clone().unwrap_or_default();
+        process.check()?;
+
+        let start = Instant::now();
+        let handlers = self.event_handlers();
+        build_log::emit(&handlers, BuildEvent::EvaluationStarted);
+        let mut log = LogParser::new(&handlers);
+
+        let (status, stdout, stderr) = match &self.runner {
+            Some(runner) => {
+                let output = runner.run(&args, &self.invocation_env())?;
+                let stderr = self.read_log(&mut log, output.stderr.as_slice())?;
+                (output.status, output.stdout, stderr)
+            }
+            None => {
+                let mut cmd = command::nix()?;
+                self.configure(&mut cmd);
+                spawn::configure(&mut cmd, self.nice);
+                let mut child = cmd
+                    .current_dir(cwd)
+                    .args(args)
+                    .stdin(Stdio::null())
+                    .stdout(Stdio::piped())
+                    .stderr(Stdio::piped())
+                    .spawn()?;
+                let mut stdout = child.stdout.take().expect("stdout is piped");
+                let pipe = BufReader::new(child.stderr.take().expect("stderr is piped"));
+                process.attach(child)?;
+
+                let stdout = std::thread::spawn(move || {
+                    let mut buf = vec![];
+                    stdout.read_to_end(&mut buf).map(|_| buf)
+                });
+
+                let stderr = self.read_log(&mut log, pipe)?;
+                let status = process.wait()?;
+                let stdout = stdout.join().expect("stdout reader panicked")?;
+                (status, stdout, stderr)
+            }
+        };
+
+        build_log::emit(
+            &handlers,
+            BuildEvent::Done {
+                success: status.success(),
+            },
+        );
+        debug!("nix {status} after {:?}", start.elapsed());
+
+        let output = Output {
+            status,
+            stdout,
+            stderr: stderr.into_bytes(),
+        };
+        Ok((output, log.finish(start.elapsed())))
+    }
+
+    /// Parse the structured log read from `pipe`, returning its plain text
+    ///
+    /// The text is also forwarded to the logs, the cargo warnings and the [`Config::log_file`]
+    fn read_log(&self, log: &mut LogParser, pipe: impl BufRead) -> Result<String> {
+        let forward_warnings = self.forward_warnings && self.is_cargo_integrated();
+        let mut monitor = self.nom.then(nom::Monitor::spawn).flatten();
+        let mut log_file = match &self.log_file {
+            Some(path) => Some(File::options().create(true).append(true).open(path)?),
+            None => None,
+        };
+        let mut stderr = String::new();
+        for line in pipe.split(b'\n') {
+            let line = line?;
+            if let Some(monitor) = &mut monitor {
+                monitor.line(&line);
+            }
+
+            let Some(text) = log.line(&String::from_utf8_lossy(&line), Instant::now()) else {
+                continue;
+            };
+
+            for line in text.lines() {
+                logging::nix_line(line);
+
+                if let Some(warning) = line.strip_prefix("warning:").filter(|_| forward_warnings) {
+                    cargo::warning(format_args!("nix: {}", warning.trim()));
+                }
+
+                if let Some(file) = &mut log_file {
+                    writeln!(file, "{line}")?;
+                }
+
+                stderr.push_str(line);
+                stderr.push('\n');
+            }
+        }
+
+        if let Some(monitor) = monitor {
+            monitor.finish();
+        }
+
+        Ok(stderr)
+    }
+
+    /// Returns whether the legacy programs are used from the start, see [`Backend`]
+    pub(crate) fn uses_legacy(&self) -> bool {
+        match self.backend {
+            Backend::Legacy => true,
+            Backend::Auto => !version::has_nix_command() && self.supports_legacy(),
+            Backend::Unified => false,
+        }
+    }
+
+    /// Run `nix build`, returning the built derivations, the tracked local files
+    /// and the report of the build
+    fn invoke(&self, cwd: &Path) -> Result<(Vec<Derivation>, BTreeSet<PathBuf>, BuildReport)> {
+        let start = Instant::now();
+        let build_legacy = || -> Result<_> {
+            if let Some(process) = &self.process {
+                process.check()?;
+            }
+            let derivations = self.physical_paths(self.build_legacy()?)?;
+            let report = BuildReport {
+                wall_time: start.elapsed(),
+                ..Default::default()
+            };
+            Ok((derivations, BTreeSet::new(), report))
+        };
+
+        if self.uses_legacy() {
+            return build_legacy();
+        }
+
+        if self.store_root.is_none() && self.runner.is_none() {
+            self.preflight()?;
+        }
+        self.create_log_file()?;
+
+        let (output, report) = self.run_nix(cwd, self.args(self.output_format))?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            if let Some(err) = daemon::unavailable(&stderr) {
+                return Err(err);
+            }
+            if self.backend == Backend::Auto
+                && self.supports_legacy()
+                && legacy::is_unsupported(&stderr)
+            {
+                debug!("nix doesn't support the required features, using the legacy programs");
+                return build_legacy();
+            }
+
+            let err = self.failure(&stderr);
+            if let (true, Error::SubstituterUnavailable { .. }) = (self.fallback, &err) {
+                warn!("{err}, building {} from source", self.target);
+                let mut config = self.clone();
+                config.fallback(false).option("fallback", "true");
+                return config.invoke(cwd);
+            }
+            return Err(err);
+        }
+
+        let derivations = match self.output_format {
+            OutputFormat::PrintOutPaths => out_paths::derivations(&output.stdout)?,
+            OutputFormat::Json => build_json::parse(&output.stdout)?,
+            OutputFormat::Auto => match build_json::parse(&output.stdout) {
+                Ok(derivations) => derivations,
+                Err(err)
+                    if self.store_root.is_none()
+                        && version::detect_capabilities()
+                            .is_some_and(|capabilities| capabilities.print_out_paths) =>
+                {
+                    debug!("{err}, retrying with --print-out-paths");
+
+                    // already built, so only evaluated again
+                    let (output, _) = self.run_nix(cwd, self.args(OutputFormat::PrintOutPaths))?;
+                    if !output.status.success() {
+                        return Err(self.failure(&String::from_utf8_lossy(&output.stderr)));
+                    }
+                    out_paths::derivations(&output.stdout)?
+                }
+                Err(err) => return Err(err),
+            },
+        };
+
+        let tracked = if self.track_dependencies {
+            let log = String::from_utf8_lossy(&output.stderr);
+            self.tracked_files(cwd, &log)
+        } else {
+            BTreeSet::new()
+        };
+
+        Ok((self.physical_paths(derivations)?, tracked, report))
+    }
+
+    /// Check the configuration for settings that can't be combined
+    ///
+    /// Run by [`Config::build`], fails with [`Error::InvalidConfig`]
+    pub fn validate(&self) -> Result<()> {
+        let invalid = |reason: &str| Err(Error::InvalidConfig(reason.to_owned()));
+
+        match &self.target {
+            Installable::File { file, .. } if file.as_os_str().is_empty() => {
+                return invalid("empty target file")
+            }
+            Installable::Flake { flake_ref, .. } if flake_ref.location().trim().is_empty() => {
+                return invalid("empty target flake")
+            }
+            Installable::Expr { expr, .. } if expr.trim().is_empty() => {
+                return invalid("empty target expression")
+            }
+            Installable::Drv { path, .. } if path.as_os_str().is_empty() => {
+                return invalid("empty target derivation")
+            }
+            _ => {}
+        }
+
+        if let Installable::Drv { .. } = &self.target {
+            if !self.arg_exprs.is_empty() || !self.arg_strs.is_empty() {
+                return invalid(&format!(
+                    "derivation {} is already instantiated and can't be passed arguments",
+                    self.target
+                ));
+            }
+        }
+
+        if let Installable::Flake { flake_ref, .. } = &self.target {
+            let installable = &self.target;
+            FlakeRef::parse(&flake_ref.to_string())?;
+            if !self.arg_exprs.is_empty() || !self.arg_strs.is_empty() {
+                return invalid(&format!(
+                    "flake {installable} can't be passed arguments with --arg or --argstr"
+                ));
+            }
+            if self.backend == Backend::Legacy {
+                return invalid(&format!(
+                    "flake {installable} can't be built with the legacy backend"
+                ));
+            }
+        }
+
+        if self.trusted_substituters_only {
+            match version::setting_list("trusted-substituters") {
+                Ok(trusted) if !trusted.is_empty() => {}
+                Ok(_) => {
+                    return invalid("no trusted-substituters are configured to substitute from")
+                }
+                Err(err) => {
+                    return invalid(&format!("trusted-substituters couldn't be read: {err}"))
+                }
+            }
+        }
+
+        if !self.flake_overrides.is_empty() && self.backend == Backend::Legacy {
+            return invalid("flakes can't be overridden with the legacy backend");
+        }
+
+        if self.eval_store.is_some() && self.backend == Backend::Legacy {
+            return invalid("the eval store can't be set with the legacy backend");
+        }
+
+        if self.runner.is_some() && self.backend == Backend::Legacy {
+            return invalid("a runner can't be set with the legacy backend");
+        }
+
+        if let Some(root) = &self.store_root {
+            let root = root.display();
+            if !self.copy_to.is_empty() || self.sign_key_file.is_some() {
+                return invalid(&format!(
+                    "outputs in the store rooted at {root} can't be signed or copied"
+                ));
+            }
+            if self.out_link.is_some() || self.shared_out_link || self.gc_root {
+                return invalid(&format!(
+                    "outputs in the store rooted at {root} can't be linked or registered as GC roots"
+                ));
+            }
+            if self.output_format == OutputFormat::PrintOutPaths {
+                return invalid(&format!(
+                    "outputs in the store rooted at {root} can't be printed with --print-out-paths"
+                ));
+            }
+        }
+
+        if self.target.attr() == Some("") {
+            return invalid("empty attribute");
+        }
+
+        if let Some(level) = self.nice.filter(|level| !(-20..=19).contains(level)) {
+            return invalid(&format!("nice level {level} is not between -20 and 19"));
+        }
+
+        Ok(())
+    }
+
+    /// Check the nix daemon can be reached, and honors the nix settings of the configuration
+    ///
+    /// The daemon silently ignores some settings, like `builders`, `sandbox` or substituters
+    /// missing from `trusted-substituters`, for users missing from `trusted-users`.
+    /// Run by [`Config::build`] when using the daemon, fails with
+    /// [`Error::DaemonUnavailable`] or [`Error::RequiresTrustedUser`] for the first such setting.
+    /// Nothing is checked when the trust of the user isn't reported, before nix 2.14,
+    /// and substituters are only checked when the `trusted-substituters` could be read
+    pub fn preflight(&self) -> Result<()> {
+        crate::check_daemon()?;
+
+        let restricted = self.settings().into_iter().find_map(|(name, value)| {
+            let untrusted = match name.as_str() {
+                // the substituters can't be told apart when the trusted ones couldn't be read
+                "substituters" | "extra-substituters" => {
+                    match version::setting_list("trusted-substituters") {
+                        Ok(trusted) => value
+                            .split_whitespace()
+                            .any(|url| !trusted.iter().any(|t| t == url)),
+                        Err(_) => false,
+                    }
+                }
+                name => RESTRICTED_SETTINGS.contains(&name),
+            };
+            untrusted.then_some(name)
+        });
+
+        match restricted {
+            Some(name) if daemon::is_trusted_user() == Some(false) => {
+                Err(Error::RequiresTrustedUser(name))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Invoke `nix build` with the given configuration
+    ///
+    /// Building an identical configuration again in the same process reuses the derivations
+    /// of the first build, without invoking nix, like for helpers called from several places
+    pub fn build(&self) -> Result<Vec<Derivation>> {
+        self.build_with_report().map(|(derivations, _)| derivations)
+    }
+
+    /// Invoke `nix build` with the given configuration, returning the derivations
+    /// labelled by the target, see [`BuildOutputs`](crate::BuildOutputs)
+    pub fn build_outputs(&self) -> Result<BuildOutputs> {
+        let derivations = self.build()?;
+        Ok(BuildOutputs::new(vec![BuildOutput {
+            label: self.target.to_string(),
+            target: self.target.clone(),
+            derivations,
+        }]))
+    }
+
+    /// Invoke `nix build` with the given configuration, or skip the build if nix is not installed
+    ///
+    /// Returns `None` instead of failing with [`Error::NixNotAvailable`], after warning
+    /// with `cargo:warning` inside build scripts. Meant for crates using nix when it's
+    /// available, and falling back to another way of providing their dependencies otherwise
+    ///
+    /// # Example
+    /// ```no_run
+    /// # fn main() -> Result<(), nix_build::Error> {
+    /// match nix_build::Config::new().build_optional()? {
+    ///     Some(derivations) => {
+    ///         derivations[0].emit_link_search();
+    ///     }
+    ///     None => println!("cargo:rustc-link-lib=z"),
+    /// }
+    /// # Ok(()) }
+    /// ```
+    pub fn build_optional(&self) -> Result<Option<Vec<Derivation>>> {
+        match self.build() {
+            Ok(derivations) => Ok(Some(derivations)),
+            Err(Error::NixNotAvailable) => {
+                let message = format!(
+                    "nix is not available, skipping the build of {}",
+                    self.target
+                );
+                if self.is_cargo_integrated() {
+                    cargo::warning(&message);
+                } else {
+                    warn!("{message}");
+                }
+                Ok(None)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Invoke `nix build` with the given configuration, also returning the metrics of the build
+    ///
+    /// See [`BuildReport`]
+    pub fn build_with_report(&self) -> Result<(Vec<Derivation>, BuildReport)> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!("build", target = %self.target).entered();
+
+        self.validate()?;
+
+        let cwd = std::env::current_dir().unwrap();
+
+        let cargo = self.is_cargo_integrated();
+        if cargo {
+            self.emit_rerun_directives(&cwd);
+        }
+
+        // released when dropped, at the end of the build
+        let _lock = self.acquire_lock()?;
+
+        let cache_file = if self.cache {
+            Some(self.cache_file(self.fingerprint(&cwd)?)?)
+        } else {
+            None
+        };
+
+        let cached = cache_file
+            .as_ref()
+            .and_then(|file| Derivation::load(file).ok());
+
+        let tracked_file = cache_file.as_ref().map(|file| file.with_extension("deps"));
+
+        let mut report = BuildReport::default();
+        let derivations = match cached {
+            Some(derivations) => {
+                debug!("using cached result for {}", self.target);
+                report.cached = true;
+
+                let tracked = tracked_file
+                    .as_ref()
+                    .filter(|_| cargo)
+                    .and_then(|file| std::fs::read_to_string(file).ok())
+                    .unwrap_or_default();
+
+                for file in tracked.lines() {
+                    cargo::rerun_if_changed(Path::new(file));
+                }
+
+                derivations
+            }
+            None => {
+                let fresh = || -> Result<_> {
+                    let (derivations, tracked, invoked) = self.invoke(&cwd)?;
+                    self.check_outputs(&derivations)?;
+
+                    if let Some(key_file) = &self.sign_key_file {
+                        let outputs = derivations.iter().flat_map(|drv| drv.outputs.values());
+                        store::sign(outputs, key_file, false)?;
+                    }
+
+                    for store_uri in &self.copy_to {
+                        debug!("copying the outputs to {store_uri}");
+                        store::CopyTo::new(store_uri)
+                            .derivations(&derivations)
+                            .run()?;
+                    }
+
+                    for file in tracked.iter().filter(|_| cargo) {
+                        cargo::rerun_if_changed(file);
+                    }
+
+                    if let Some(file) = &cache_file {
+                        if let Some(dir) = file.parent() {
+                            std::fs::create_dir_all(dir)?;
+                        }
+                        Derivation::save(file, &derivations)?;
+                    }
+
+                    if let Some(file) = tracked_file.as_ref().filter(|_| self.track_dependencies) {
+                        let tracked: Vec<_> = tracked
+                            .iter()
+                            .map(|file| file.display().to_string())
+                            .collect();
+                        std::fs::write(file, tracked.join("\n"))?;
+                    }
+
+                    Ok((derivations, invoked))
+                };
+
+                let (derivations, invoked) = match self.memo_key(&cwd) {
+                    Some(key) => memo::memoized(key, || self.retrying(fresh))?,
+                    None => self.retrying(fresh)?,
+                };
+                report = invoked;
+                derivations
+            }
+        };
+
+        self.finish(&cwd, &derivations)?;
+        Ok((derivations, report))
+    }
+
+    /// Run `attempt` until it succeeds or fails with a non-transient error,
+    /// up to [`Config::retries`] more times
+    fn retrying<T>(&self, attempt: impl Fn() -> Result<T>) -> Result<T> {
+        let mut delay = Duration::from_secs(1);
+
+        for retry in 1.. {
+            match attempt() {
+                Err(err) if retry <= self.retries && err.is_transient() => {
+                    warn!(
+                        "transient failure of {}, retrying in {delay:?} ({retry}/{}): {err}",
+                        self.target, self.retries
+                    );
+                    std::thread::sleep(delay);
+                    delay *= 2;
+
+                    if let Some(process) = &self.process {
+                        process.check()?;
+                    }
+                }
+                result => return result,
+            }
+        }
+        unreachable!("retries are bounded")
+    }
+
+    /// Returns the key deduplicating the builds of identical configurations in this process
+    ///
+    /// Configurations with side effects on each build, like signing or events,
+    /// and the ones running their invocations through a runner are never deduplicated.
+    ///
+    /// Unlike the fingerprint persisted across runs, the key is made of the
+    /// arguments, nix settings and environment of the invocation alone, without spawning
+    /// nix or reading the local source, as they don't change within the process
+    fn memo_key(&self, cwd: &Path) -> Option<Fingerprint> {
+        let memoizable = self.sign_key_file.is_none()
+            && self.copy_to.is_empty()
+            && self.on_event.is_none()
+            && self.runner.is_none();
+        if !memoizable {
+            return None;
+        }
+
+        let mut hasher = Hasher::new();
+        hasher.write(version::version_output().unwrap_or_default());
+        hasher.write(cwd.as_os_str().as_encoded_bytes());
+        hasher.write(format!("{:?}", self.backend));
+        hasher.write([
+            u8::from(self.fallback),
+            u8::from(self.verify_outputs),
+            u8::from(self.clear_env),
+        ]);
+
+        for arg in self.args(self.output_format) {
+            hasher.write(arg.as_encoded_bytes());
+        }
+        // the nix settings, like substituters or the sandbox, are passed through `NIX_CONFIG`
+        for (name, value) in self.invocation_env() {
+            hasher
+                .write(name.as_encoded_bytes())
+                .write(value.as_encoded_bytes());
+        }
+        for (name, _) in self.env_vars.iter().filter(|(_, value)| value.is_none()) {
+            hasher.write(name.as_encoded_bytes());
+        }
+        for name in self.kept_env.iter().filter(|_| self.clear_env) {
+            hasher.write(name);
+        }
+
+        Some(hasher.finish())
+    }
+
+    /// Fail with [`Error::OutputMissing`] if any output of a fresh build is not a valid
+    /// path of the local store, see [`Config::verify_outputs`]
+    fn check_outputs(&self, derivations: &[Derivation]) -> Result<()> {
+        if !self.verify_outputs {
+            return Ok(());
+        }
+
+        if let Some(missing) = derivations.iter().find_map(Derivation::missing_output) {
+            return Err(Error::OutputMissing(missing.to_path_buf()));
+        }
+
+        let outputs: Vec<PathBuf> = derivations
+            .iter()
+            .flat_map(|drv| drv.outputs.values())
+            .map(|path| path.to_path_buf())
+            .collect();
+        // `nix path-info` would query the default store, bypassing the runner
+        if outputs.is_empty() || self.store_root.is_some() || self.runner.is_some() {
+            return Ok(());
+        }
+
+        // invalid paths are left out of the results
+        let valid: BTreeSet<PathBuf> = store::path_info::query(&outputs, false, false)?
+            .into_iter()
+            .map(|info| info.path)
+            .collect();
+        match outputs.into_iter().find(|path| !valid.contains(path)) {
+            Some(missing) => Err(Error::OutputMissing(missing)),
+            None => Ok(()),
+        }
+    }
+
+    /// Process the derivations built for this configuration, cached or not
+    fn finish(&self, cwd: &Path, derivations: &[Derivation]) -> Result<()> {
+        info!(
+            "built {} derivation(s) for {}",
+            derivations.len(),
+            self.target
+        );
+
+        if let Some(link) = self.out_link_path(cwd)? {
+            Self::create_roots(&link, derivations)?;
+        }
+
+        if self.write_manifest {
+            build_manifest::record(self, derivations)?;
+        }
+
+        meta::record_origin(self, derivations);
+        if let Some(policy) = &self.license_policy {
+            policy.check(&self.target.to_string(), &self.meta()?)?;
+        }
+
+        if self.rpath && self.is_cargo_integrated() {
+            for derivation in derivations {
+                derivation.emit_rpath();
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the key grouping the configurations that can be built by a single invocation
+    ///
+    /// Invalid configurations and configurations caching their results, tracking their
+    /// dependencies, signing and copying their outputs or handling events are never batched,
+    /// like the ones building a whole file or expression
+    pub(crate) fn batch_key(&self) -> Option<Vec<OsString>> {
+        let batchable = self.validate().is_ok()
+            && !self.cache
+            && !self.track_dependencies
+            && self.sign_key_file.is_none()
+            && self.copy_to.is_empty()
+            && self.on_event.is_none()
+            && self.outputs.is_empty()
+            && self.backend != Backend::Legacy
+            && self.output_format != OutputFormat::PrintOutPaths
+            && (self.target.attr().is_some() || self.target.is_flake());
+        if !batchable {
+            return None;
+        }
+
+        let mut template = self.clone();
+        template.target = self.target.without_attr();
+
+        let mut key = template.args(OutputFormat::Json);
+        key.extend(
+            self.settings()
+                .into_iter()
+                .map(|(name, value)| format!("{name} = {value}").into()),
+        );
+        key.push(
+            format!(
+                "{:?} {} {:?} {:?}",
+                self.lock, self.forward_warnings, self.log_file, self.nice
+            )
+            .into(),
+        );
+        Some(key)
+    }
+
+    /// Build configurations sharing the same [`Config::batch_key`] with a single `nix build`,
+    /// so the target is evaluated once
+    ///
+    /// Falls back to building each configuration separately if the batch fails,
+    /// to attribute the errors
+    pub(crate) fn build_batch(configs: &[&Config]) -> Vec<Result<Vec<Derivation>>> {
+        let separately = || configs.iter().map(|config| config.build()).collect();
+
+        let Some(first) = configs.first() else {
+            return vec![];
+        };
+
+        let cwd = std::env::current_dir().unwrap();
+        let batched = (|| -> Result<Option<Vec<Derivation>>> {
+            if first.is_cargo_integrated() {
+                for config in configs {
+                    config.emit_rerun_directives(&cwd);
+                }
+            }
+
+            let _lock = first.acquire_lock()?;
+            if first.store_root.is_none() && first.runner.is_none() {
+                first.preflight()?;
+            }
+            first.create_log_file()?;
+
+            let mut template = (*first).clone();
+            if !template.target.is_flake() {
+                template.target = template.target.without_attr();
+            }
+
+            let mut args = template.args(OutputFormat::Json);
+            for (i, config) in configs.iter().enumerate() {
+                match &config.target {
+                    Installable::Flake { .. } if i == 0 => {}
+                    Installable::Flake { .. } => args.push(config.target.to_string().into()),
+                    target => args.extend(target.attr().map(OsString::from)),
+                }
+            }
+
+            let (output, _) = first.run_nix(&cwd, args)?;
+            if !output.status.success() {
+                return Ok(None);
+            }
+
+            match build_json::parse(&output.stdout) {
+                Ok(derivations) => Ok(Some(first.physical_paths(derivations)?)),
+                Err(err) => {
+                    debug!("{err}");
+                    Ok(None)
+                }
+            }
+        })();
+
+        // one result is printed for each installable, in order
+        let derivations = match batched {
+            Ok(Some(derivations)) if derivations.len() == configs.len() => derivations,
+            _ => {
+                debug!("batched build failed, building separately");
+                return separately();
+            }
+        };
+
+        configs
+            .iter()
+            .zip(derivations)
+            .map(|(config, derivation)| {
+                let derivations = vec![derivation];
+                config.check_outputs(&derivations)?;
+                config.finish(&cwd, &derivations)?;
+                Ok(derivations)
+            })
+            .collect()
+    }
+}
+
+/// Block until the exclusive lock of `file` is acquired, released when the file is closed
+///
+/// Uses `flock`, as [`File::lock`] requires Rust 1.89. Files are not locked on other platforms
+pub(crate) fn lock_exclusive(file: &File) -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::io::AsRawFd;
+
+        loop {
+            // SAFETY: the file descriptor is valid as long as `file` is borrowed
+            if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } == 0 {
+                return Ok(());
+            }
+            let err = std::io::Error::last_os_error();
+            if err.kind() != std::io::ErrorKind::Interrupted {
+                return Err(err);
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = file;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn memo_key_includes_settings() {
+        let cwd = Path::new("/home/user/project");
+        let key = |configure: &dyn Fn(&mut Config)| {
+            let mut config = Config::new();
+            config.target_flake("nixpkgs#hello");
+            configure(&mut config);
+            config.memo_key(cwd).unwrap()
+        };
+
+        let plain = key(&|_| {});
+        assert_eq!(plain, key(&|_| {}));
+        assert_ne!(
+            plain,
+            key(&|config| {
+                config.substituter("https://cache.example.org");
+            })
+        );
+        assert_ne!(
+            plain,
+            key(&|config| {
+                config.fallback(true);
+            })
+        );
+        assert_ne!(
+            plain,
+            key(&|config| {
+                config.sandbox(SandboxMode::Relaxed);
+            })
+        );
+        assert_ne!(
+            plain,
+            key(&|config| {
+                config.allow_ifd(false);
+            })
+        );
     }
 }
@@ -0,0 +1,107 @@
+//! Preflight check of the nix daemon availability
+
+use std::{path::PathBuf, sync::OnceLock};
+
+use crate::{command, version, Error, Result};
+
+/// Default location of the nix daemon socket
+const DEFAULT_SOCKET: &str = "/nix/var/nix/daemon-socket/socket";
+
+/// Returns the path of the nix daemon socket
+///
+/// Honors the `NIX_DAEMON_SOCKET_PATH` environment variable
+fn socket_path() -> PathBuf {
+    std::env::var_os("NIX_DAEMON_SOCKET_PATH")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_SOCKET))
+}
+
+/// Returns a [`Error::DaemonUnavailable`] if the given nix error output
+/// indicates the daemon couldn't be reached
+pub(crate) fn unavailable(stderr: &str) -> Option<Error> {
+    let line = stderr
+        .lines()
+        .find(|line| line.contains("cannot connect to socket"))?;
+
+    let socket = line
+        .split('\'')
+        .nth(1)
+        .map(PathBuf::from)
+        .unwrap_or_else(socket_path);
+
+    let hint = if line.contains("Permission denied") {
+        "the current user is not allowed to connect to the nix daemon, \
+         check `allowed-users` in nix.conf"
+    } else if line.contains("Connection refused") {
+        "the nix daemon socket exists but the daemon is not running, \
+         restart it with `sudo systemctl restart nix-daemon` \
+         (or `sudo launchctl kickstart -k system/org.nixos.nix-daemon` on macOS)"
+    } else {
+        "the nix daemon is not running: for a multi-user install start it with \
+         `sudo systemctl start nix-daemon` (or `sudo launchctl load /Library/LaunchDaemons/org.nixos.nix-daemon.plist` on macOS), \
+         for a single-user install set `NIX_REMOTE=local`"
+    };
+
+    Some(Error::DaemonUnavailable {
+        socket,
+        hint: hint.to_owned(),
+    })
+}
+
+/// Checks the nix store used by default can be reached
+///
+/// Runs `nix store info` (`nix store ping` before nix 2.19), once per process.
+/// Fails with [`Error::DaemonUnavailable`] if the nix daemon can't be reached
+///
+/// # Example
+/// ```no_run
+/// # fn main() -> Result<(), nix_build::Error> {
+/// if let Err(err @ nix_build::Error::DaemonUnavailable { .. }) = nix_build::check_daemon() {
+///     println!("cargo:warning={err}");
+/// }
+/// # Ok(()) }
+/// ```
+pub fn check_daemon() -> Result<()> {
+    static UNAVAILABLE: OnceLock<Option<String>> = OnceLock::new();
+
+    command::nix()?;
+
+    let stderr = UNAVAILABLE.get_or_init(|| {
+        let subcommand = match version::detect() {
+            Some(version) if version.at_least(2, 19) => "info",
+            _ => "ping",
+        };
+
+        match command::nix_stdout(["store", subcommand]) {
+            Err(Error::CommandFailed { stderr, .. }) => {
+                unavailable(&stderr).is_some().then_some(stderr)
+            }
+            _ => None,
+        }
+    });
+
+    match stderr.as_deref().and_then(unavailable) {
+        Some(err) => Err(err),
+        None => Ok(()),
+    }
+}
+
+/// Returns whether the nix daemon trusts the current user, queried once per process
+///
+/// Unknown when the store doesn't report it, like before nix 2.14
+pub(crate) fn is_trusted_user() -> Option<bool> {
+    static TRUSTED: OnceLock<Option<bool>> = OnceLock::new();
+
+    *TRUSTED.get_or_init(|| {
+        let subcommand = match version::detect() {
+            Some(version) if version.at_least(2, 19) => "info",
+            _ => "ping",
+        };
+
+        let info: serde_json::Value = command::nix_json(["store", subcommand, "--json"]).ok()?;
+        match info.get("trusted")? {
+            serde_json::Value::Bool(trusted) => Some(*trusted),
+            trusted => trusted.as_u64().map(|trusted| trusted != 0),
+        }
+    })
+}
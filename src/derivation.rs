@@ -0,0 +1,383 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::{meta, store::StorePath, Config, Error, Installable, Result};
+
+/// Represents a nix build output derivation
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[serde(try_from = "RawDerivation")]
+pub struct Derivation {
+    #[serde(rename = "drvPath", alias = "drv_path")]
+    /// Derivation path
+    pub drv_path: StorePath,
+    /// List of outputs for this derivation
+    ///
+    /// Example outputs: `out`, `dev`
+    pub outputs: HashMap<String, StorePath>,
+    /// Realisations of the outputs of content-addressed derivations, by output name
+    ///
+    /// Only reported by the nix versions printing realisations in `nix build --json`,
+    /// see [`Config::ca_derivations`](crate::Config::ca_derivations)
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub realisations: HashMap<String, Realisation>,
+}
+
+/// Output of a content-addressed derivation, resolved once built
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Realisation {
+    /// Identifier of the output, like `sha256:<hash of the derivation>!out`
+    pub id: String,
+    /// Path of the output
+    pub out_path: StorePath,
+    /// Signatures of the realisation
+    #[serde(default)]
+    pub signatures: Vec<String>,
+    /// Realisations of the dependencies the output was built from, by identifier
+    #[serde(default)]
+    pub dependent_realisations: HashMap<String, String>,
+}
+
+impl Realisation {
+    /// Returns the hash of the derivation part of the identifier
+    pub fn drv_hash(&self) -> &str {
+        self.id.split_once('!').map_or(&self.id, |(hash, _)| hash)
+    }
+
+    /// Returns the output name part of the identifier, like `out`
+    pub fn output_name(&self) -> Option<&str> {
+        self.id.split_once('!').map(|(_, output)| output)
+    }
+}
+
+/// Output as printed by `nix build --json`, a plain path or a realisation
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum RawOutput {
+    Path(PathBuf),
+    #[serde(rename_all = "camelCase")]
+    Realisation {
+        id: String,
+        out_path: PathBuf,
+        #[serde(default)]
+        signatures: Vec<String>,
+        #[serde(default)]
+        dependent_realisations: HashMap<String, String>,
+    },
+}
+
+#[derive(serde::Deserialize)]
+struct RawDerivation {
+    #[serde(rename = "drvPath", alias = "drv_path")]
+    drv_path: StorePath,
+    outputs: HashMap<String, RawOutput>,
+    #[serde(default)]
+    realisations: HashMap<String, Realisation>,
+}
+
+impl TryFrom<RawDerivation> for Derivation {
+    type Error = Error;
+
+    fn try_from(raw: RawDerivation) -> Result<Self> {
+        let mut derivation = Derivation {
+            drv_path: raw.drv_path,
+            outputs: HashMap::new(),
+            realisations: raw.realisations,
+        };
+
+        for (name, output) in raw.outputs {
+            let path = match output {
+                RawOutput::Path(path) => StorePath::new(path)?,
+                RawOutput::Realisation {
+                    id,
+                    out_path,
+                    signatures,
+                    dependent_realisations,
+                } => {
+                    // realisations may print the base name of the path only
+                    let out_path = StorePath::new(derivation.drv_path.store_dir().join(out_path))?;
+                    derivation.realisations.insert(
+                        name.clone(),
+                        Realisation {
+                            id,
+                            out_path: out_path.clone(),
+                            signatures,
+                            dependent_realisations,
+                        },
+                    );
+                    out_path
+                }
+            };
+            derivation.outputs.insert(name, path);
+        }
+
+        Ok(derivation)
+    }
+}
+
+impl Derivation {
+    pub fn out(&self) -> Option<&StorePath> {
+        self.outputs.get("out")
+    }
+
+    /// Returns the realisation of the output `name`, if the derivation is content-addressed
+    pub fn realisation(&self, name: &str) -> Option<&Realisation> {
+        self.realisations.get(name)
+    }
+
+    /// Returns the first output path of this derivation that doesn't exist, if any
+    pub fn missing_output(&self) -> Option<&StorePath> {
+        self.outputs.values().find(|path| !path.exists())
+    }
+
+    /// Create symlinks to the outputs of this derivation in `dir`, replacing previous ones,
+    /// and return them by output
+    ///
+    /// Named `name` for the `out` output and `name-<output>` for the others, like out-links,
+    /// so generated code and later build steps can refer to a predictable path instead of
+    /// a store path. The links are not GC roots, see [`Config::gc_root`](crate::Config::gc_root)
+    ///
+    /// # Example
+    /// ```no_run
+    /// # fn main() -> Result<(), nix_build::Error> {
+    /// let derivations = nix_build::Config::new()
+    ///     .target_flake("nixpkgs#sqlite")
+    ///     .build()?;
+    ///
+    /// let out_dir = std::env::var("OUT_DIR").unwrap();
+    /// let links = derivations[0].link_into(&out_dir, "sqlite")?;
+    ///
+    /// // the headers stay at `$OUT_DIR/sqlite-dev/include` across rebuilds
+    /// let header = links["dev"].join("include/sqlite3.h");
+    /// # Ok(()) }
+    /// ```
+    pub fn link_into(&self, dir: impl AsRef<Path>, name: &str) -> Result<HashMap<String, PathBuf>> {
+        let dir = dir.as_ref();
+        std::fs::create_dir_all(dir)?;
+
+        let mut links = HashMap::new();
+        for (output, path) in &self.outputs {
+            let link = match output.as_str() {
+                "out" => dir.join(name),
+                output => dir.join(format!("{name}-{output}")),
+            };
+
+            // replaced atomically, as concurrent builds may read the previous link
+            let tmp = dir.join(format!(".{name}-{output}.{}", std::process::id()));
+            if tmp.symlink_metadata().is_ok() {
+                std::fs::remove_file(&tmp)?;
+            }
+            #[cfg(unix)]
+            std::os::unix::fs::symlink(path, &tmp)?;
+            #[cfg(windows)]
+            std::os::windows::fs::symlink_dir(path, &tmp)?;
+            std::fs::rename(&tmp, &link)?;
+
+            links.insert(output.clone(), link);
+        }
+
+        Ok(links)
+    }
+
+    /// Make sure the outputs of this derivation exist, realising them again when missing,
+    /// like after being garbage collected while reused from a cache
+    ///
+    /// The outputs are substituted or built again from the `.drv` file when it still exists,
+    /// otherwise the configuration that built the derivation in this process is built again.
+    /// The outputs of content-addressed derivations may change paths, so they're updated.
+    /// Fails with [`Error::OutputMissing`] if they can't be realised
+    ///
+    /// # Example
+    /// ```no_run
+    /// # fn main() -> Result<(), nix_build::Error> {
+    /// let cache = std::path::Path::new(&std::env::var("OUT_DIR").unwrap()).join("nix.json");
+    ///
+    /// // saved by a previous run of the build script
+    /// let json = std::fs::read(&cache)?;
+    /// let mut derivations: Vec<nix_build::Derivation> = serde_json::from_slice(&json).unwrap();
+    /// for derivation in &mut derivations {
+    ///     derivation.ensure_exists()?;
+    /// }
+    /// # Ok(()) }
+    /// ```
+    pub fn ensure_exists(&mut self) -> Result<()> {
+        let Some(missing) = self.missing_output().map(|path| path.to_path_buf()) else {
+            return Ok(());
+        };
+        debug!(
+            "output {} is missing, realising it again",
+            missing.display()
+        );
+
+        let realised = if self.drv_path.exists() {
+            let installable = Installable::drv(self.drv_path.to_path_buf())
+                .with_outputs(self.outputs.keys().cloned());
+            Config::new()
+                .target(installable)
+                .cargo_integration(false)
+                .build()?
+        } else {
+            meta::origin(&self.drv_path)
+                .ok_or_else(|| Error::OutputMissing(missing.clone()))?
+                .build()?
+        };
+
+        let derivation = realised
+            .into_iter()
+            .find(|derivation| derivation.drv_path == self.drv_path)
+            .ok_or_else(|| Error::OutputMissing(missing.clone()))?;
+        self.outputs.extend(derivation.outputs);
+        self.realisations.extend(derivation.realisations);
+
+        match self.missing_output() {
+            Some(missing) => Err(Error::OutputMissing(missing.to_path_buf())),
+            None => Ok(()),
+        }
+    }
+
+    /// Persist the given derivations as JSON to `path`
+    ///
+    /// The format matches the output of `nix build --json`
+    ///
+    /// # Example
+    /// ```no_run
+    /// # fn main() -> Result<(), nix_build::Error> {
+    /// use nix_build::Derivation;
+    ///
+    /// let cache = std::path::Path::new(&std::env::var("OUT_DIR").unwrap()).join("nix.json");
+    ///
+    /// let derivations = match Derivation::load(&cache) {
+    ///     Ok(derivations) => derivations,
+    ///     Err(_) => {
+    ///         let derivations = nix_build::build()?;
+    ///         Derivation::save(&cache, &derivations)?;
+    ///         derivations
+    ///     }
+    /// };
+    /// # Ok(()) }
+    /// ```
+    pub fn save(path: impl AsRef<Path>, derivations: &[Derivation]) -> Result<()> {
+        let json = serde_json::to_vec_pretty(derivations).map_err(std::io::Error::from)?;
+        std::fs::write(path, json)?;
+        Ok(())
+    }
+
+    /// Load derivations previously persisted with [`Derivation::save`]
+    ///
+    /// Fails with [`Error::OutputMissing`] if any of the output paths doesn't exist anymore,
+    /// for example after being garbage collected, see [`Derivation::ensure_exists`]
+    pub fn load(path: impl AsRef<Path>) -> Result<Vec<Derivation>> {
+        let json = std::fs::read(path)?;
+        let derivations: Vec<Derivation> =
+            serde_json::from_slice(&json).map_err(std::io::Error::from)?;
+
+        if let Some(missing) = derivations.iter().find_map(Derivation::missing_output) {
+            return Err(Error::OutputMissing(missing.to_path_buf()));
+        }
+
+        Ok(derivations)
+    }
+
+    /// Returns all the existing `subdir` found in the given outputs, in order
+    pub(crate) fn find_dirs<'a>(
+        &'a self,
+        outputs: &'a [&str],
+        subdir: &'a str,
+    ) -> impl Iterator<Item = PathBuf> + 'a {
+        outputs
+            .iter()
+            .filter_map(|output| self.outputs.get(*output))
+            .map(move |path| path.join(subdir))
+            .filter(|dir| dir.is_dir())
+    }
+
+    /// Returns the first existing `subdir` found in the given outputs, in order
+    fn find_dir(&self, outputs: &[&str], subdir: &str) -> Option<PathBuf> {
+        self.find_dirs(outputs, subdir).next()
+    }
+
+    /// Returns the `lib` directory of this derivation, if any
+    ///
+    /// Probes the `lib`, `out` and `dev` outputs, in this order
+    pub fn lib_dir(&self) -> Option<PathBuf> {
+        self.find_dir(&["lib", "out", "dev"], "lib")
+    }
+
+    /// Returns the `include` directory of this derivation, if any
+    ///
+    /// Probes the `dev`, `out` and `lib` outputs, in this order
+    pub fn include_dir(&self) -> Option<PathBuf> {
+        self.find_dir(&["dev", "out", "lib"], "include")
+    }
+
+    /// Returns the `bin` directory of this derivation, if any
+    ///
+    /// Probes the `bin`, `out` and `dev` outputs, in this order
+    pub fn bin_dir(&self) -> Option<PathBuf> {
+        self.find_dir(&["bin", "out", "dev"], "bin")
+    }
+
+    /// Returns the `pkg-config` directory of this derivation, if any
+    ///
+    /// Probes `lib/pkgconfig` and `share/pkgconfig` in the `dev`, `out` and `lib` outputs, in this order
+    pub fn pkg_config_dir(&self) -> Option<PathBuf> {
+        const OUTPUTS: &[&str] = &["dev", "out", "lib"];
+
+        self.find_dir(OUTPUTS, "lib/pkgconfig")
+            .or_else(|| self.find_dir(OUTPUTS, "share/pkgconfig"))
+    }
+
+    /// Returns the path to the program `name` found in the `bin` directory of any output
+    ///
+    /// The `bin` and `out` outputs are probed first
+    pub fn bin(&self, name: &str) -> Result<PathBuf> {
+        let mut outputs: Vec<&str> = vec!["bin", "out"];
+        let mut rest: Vec<&str> = self
+            .outputs
+            .keys()
+            .map(String::as_str)
+            .filter(|output| !outputs.contains(output))
+            .collect();
+        rest.sort_unstable();
+        outputs.extend(rest);
+
+        let searched: Vec<PathBuf> = outputs
+            .iter()
+            .filter_map(|output| self.outputs.get(*output))
+            .map(|path| path.join("bin").join(name))
+            .collect();
+
+        searched
+            .iter()
+            .find(|path| path.is_file())
+            .cloned()
+            .ok_or_else(|| Error::MissingBinary {
+                name: name.to_owned(),
+                searched,
+            })
+    }
+
+    /// Returns a [`Command`] to run the program `name` provided by this derivation
+    ///
+    /// See [`Derivation::bin`]
+    ///
+    /// # Example
+    /// ```no_run
+    /// # fn main() -> Result<(), nix_build::Error> {
+    /// let derivations = nix_build::Config::new()
+    ///     .target_flake("nixpkgs#protobuf")
+    ///     .build()?;
+    ///
+    /// let status = derivations[0]
+    ///     .command("protoc")?
+    ///     .args(["--rust_out=src", "foo.proto"])
+    ///     .status()?;
+    /// # Ok(()) }
+    /// ```
+    pub fn command(&self, name: &str) -> Result<Command> {
+        self.bin(name).map(Command::new)
+    }
+}
@@ -0,0 +1,127 @@
+use std::{collections::HashMap, ffi::OsString, path::PathBuf, process::Command};
+
+use crate::{command, version, Config, Error, Installable, Result};
+
+/// Environment of a development shell, like one defined with `mkShell`
+#[derive(Debug, Clone, Default)]
+pub struct DevEnv {
+    /// Exported environment variables
+    pub variables: HashMap<String, String>,
+}
+
+impl DevEnv {
+    /// Apply the environment to the given command
+    pub fn apply<'c>(&self, cmd: &'c mut Command) -> &'c mut Command {
+        cmd.envs(&self.variables)
+    }
+
+    /// Returns the value of the variable `name`, if exported
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.variables.get(name).map(String::as_str)
+    }
+
+    fn from_print_dev_env(json: &[u8]) -> Result<Self> {
+        #[derive(serde::Deserialize)]
+        struct PrintDevEnv {
+            variables: HashMap<String, Variable>,
+        }
+
+        #[derive(serde::Deserialize)]
+        struct Variable {
+            r#type: String,
+            value: serde_json::Value,
+        }
+
+        let env: PrintDevEnv = serde_json::from_slice(json).map_err(|_| Error::UnknownOutput)?;
+
+        let variables = env
+            .variables
+            .into_iter()
+            .filter(|(_, var)| var.r#type == "exported")
+            .filter_map(|(name, var)| match var.value {
+                serde_json::Value::String(value) => Some((name, value)),
+                _ => None,
+            })
+            .collect();
+
+        Ok(Self { variables })
+    }
+
+    fn from_env0(output: &[u8]) -> Self {
+        let variables = String::from_utf8_lossy(output)
+            .split('\0')
+            .filter_map(|entry| entry.split_once('='))
+            .map(|(name, value)| (name.to_owned(), value.to_owned()))
+            .collect();
+
+        Self { variables }
+    }
+}
+
+impl Config {
+    /// Realize the development environment of the target, returning its variables
+    ///
+    /// Uses `nix print-dev-env`, so both flake `devShells` and classic `shell.nix`
+    /// files are supported. When that fails for a file target, `nix-shell --run env`
+    /// is used instead
+    ///
+    /// # Example
+    /// ```no_run
+    /// # fn main() -> Result<(), nix_build::Error> {
+    /// let env = nix_build::Config::new().target_file("shell.nix").dev_env()?;
+    ///
+    /// let status = env
+    ///     .apply(&mut std::process::Command::new("cmake"))
+    ///     .arg("--version")
+    ///     .status()?;
+    /// # Ok(()) }
+    /// ```
+    pub fn dev_env(&self) -> Result<DevEnv> {
+        let supported = version::detect_capabilities()
+            .map_or(true, |capabilities| capabilities.print_dev_env_json);
+
+        if let (false, Installable::File { file, .. }) = (supported, &self.target) {
+            return self.legacy_dev_env(file.clone());
+        }
+
+        let mut args: Vec<OsString> = vec!["print-dev-env".into(), "--json".into()];
+        args.extend(self.installable_args());
+
+        let mut cmd = command::nix()?;
+        cmd.args(args).args(version::experimental_features_args());
+        self.configure(&mut cmd);
+
+        match command::run(&mut cmd) {
+            Ok(json) => DevEnv::from_print_dev_env(&json),
+            Err(err) => match &self.target {
+                Installable::File { file, .. } => {
+                    debug!("print-dev-env failed, falling back to nix-shell: {err:?}");
+                    self.legacy_dev_env(file.clone())
+                }
+                _ => Err(err),
+            },
+        }
+    }
+
+    fn legacy_dev_env(&self, file: PathBuf) -> Result<DevEnv> {
+        let mut cmd = command::legacy("nix-shell")?;
+        cmd.arg(file);
+
+        if let Some(attr) = self.target.attr() {
+            cmd.args(["-A", attr]);
+        }
+
+        for (key, val) in &self.arg_exprs {
+            cmd.args(["--arg", key, val]);
+        }
+
+        for (key, val) in &self.arg_strs {
+            cmd.args(["--argstr", key, val]);
+        }
+
+        cmd.args(["--run", "env -0"]);
+        self.configure(&mut cmd);
+
+        command::run(&mut cmd).map(|output| DevEnv::from_env0(&output))
+    }
+}
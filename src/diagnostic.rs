@@ -0,0 +1,219 @@
+//! Structured diagnostics parsed from the nix error output
+
+use std::{fmt, path::PathBuf};
+
+/// Position in a nix file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    /// File path, or a placeholder like `«string»` for expressions
+    pub file: PathBuf,
+    /// Line, starting from 1
+    pub line: u32,
+    /// Column, starting from 1
+    pub column: u32,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file.display(), self.line, self.column)
+    }
+}
+
+impl Location {
+    /// Parse a position like `/path/to/default.nix:3:5`
+    fn parse(position: &str) -> Option<Self> {
+        let mut parts = position.rsplitn(3, ':');
+        let column = parts.next()?.parse().ok()?;
+        let line = parts.next()?.parse().ok()?;
+        let file = parts.next().filter(|file| !file.is_empty())?;
+
+        Some(Self {
+            file: PathBuf::from(file),
+            line,
+            column,
+        })
+    }
+}
+
+/// Frame of an evaluation trace, like `while evaluating the attribute 'foo'`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceFrame {
+    pub message: String,
+    pub location: Option<Location>,
+}
+
+/// Evaluation error reported by nix
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub message: String,
+    /// Where the error occurred, if reported
+    pub location: Option<Location>,
+    /// Evaluation trace, in the order reported by nix
+    pub trace: Vec<TraceFrame>,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.location {
+            Some(location) => write!(f, "{location}: {}", self.message),
+            None => f.write_str(&self.message),
+        }
+    }
+}
+
+/// Item of the error output the last `at` position refers to
+enum Current {
+    None,
+    Frame,
+    Error,
+}
+
+impl Diagnostic {
+    /// Parse the first error found in the given nix error output
+    ///
+    /// # Example
+    /// ```
+    /// # use nix_build::Diagnostic;
+    /// let stderr = "\
+    /// error: undefined variable 'foo'
+    ///
+    ///        at /src/default.nix:3:7:
+    ///
+    ///             2| {
+    ///             3|   a = foo;
+    ///              |       ^
+    /// ";
+    ///
+    /// let diagnostic = Diagnostic::parse(stderr).unwrap();
+    /// assert_eq!(diagnostic.message, "undefined variable 'foo'");
+    /// assert_eq!(diagnostic.location.unwrap().line, 3);
+    /// ```
+    pub fn parse(stderr: &str) -> Option<Self> {
+        let mut diagnostic: Option<Diagnostic> = None;
+        let mut trace = vec![];
+        let mut current = Current::None;
+
+        for line in stderr.lines().map(str::trim) {
+            if let Some(message) = line.strip_prefix("error:").map(str::trim) {
+                // the header preceding the trace frames is empty
+                if message.is_empty() {
+                    continue;
+                }
+                if diagnostic.is_some() {
+                    current = Current::None;
+                    continue;
+                }
+
+                // nix before 2.4 reports the position on the same line
+                let (message, location) = match message.rsplit_once(" at ") {
+                    Some((head, position)) => match Location::parse(position) {
+                        Some(location) => (head, Some(location)),
+                        None => (message, None),
+                    },
+                    None => (message, None),
+                };
+
+                diagnostic = Some(Diagnostic {
+                    message: message.to_owned(),
+                    location,
+                    trace: vec![],
+                });
+                current = Current::Error;
+            } else if let Some(message) = line.strip_prefix('…') {
+                trace.push(TraceFrame {
+                    message: message.trim().to_owned(),
+                    location: None,
+                });
+                current = Current::Frame;
+            } else if let Some(location) = line
+                .strip_prefix("at ")
+                // like `at /src/default.nix:3:7:` or `at «none»:0: (source not available)`
+                .and_then(|position| position.split(": ").next())
+                .and_then(|position| Location::parse(position.trim_end_matches(':')))
+            {
+                let slot = match current {
+                    Current::Frame => trace.last_mut().map(|frame| &mut frame.location),
+                    Current::Error => diagnostic.as_mut().map(|error| &mut error.location),
+                    Current::None => None,
+                };
+                if let Some(slot) = slot {
+                    slot.get_or_insert(location);
+                }
+            }
+        }
+
+        diagnostic.map(|mut diagnostic| {
+            diagnostic.trace = trace;
+            diagnostic
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn location(file: &str, line: u32, column: u32) -> Option<Location> {
+        Some(Location {
+            file: PathBuf::from(file),
+            line,
+            column,
+        })
+    }
+
+    #[test]
+    fn evaluation_trace() {
+        let stderr = "\
+error:
+       … while calling the 'derivationStrict' builtin
+
+         at /builtin/derivation.nix:9:12: (source not available)
+
+       … while evaluating derivation 'hello-2.12.1'
+         whose name attribute is located at /nix/store/2l6ww0f0lb4kxs3bqhdzpljzjxvhc6kb-source/pkgs/stdenv/generic/make-derivation.nix:353:7
+
+       … while evaluating attribute 'buildInputs' of derivation 'hello-2.12.1'
+
+         at /home/user/project/default.nix:6:3:
+
+            5|   src = ./.;
+            6|   buildInputs = [ fooo ];
+             |   ^
+            7| }
+
+       error: undefined variable 'fooo'
+
+       at /home/user/project/default.nix:6:19:
+
+            5|   src = ./.;
+            6|   buildInputs = [ fooo ];
+             |                   ^
+            7| }
+";
+        let diagnostic = Diagnostic::parse(stderr).unwrap();
+        assert_eq!(diagnostic.message, "undefined variable 'fooo'");
+        assert_eq!(
+            diagnostic.location,
+            location("/home/user/project/default.nix", 6, 19)
+        );
+        assert_eq!(
+            diagnostic.trace,
+            [
+                TraceFrame {
+                    message: "while calling the 'derivationStrict' builtin".to_owned(),
+                    location: location("/builtin/derivation.nix", 9, 12),
+                },
+                TraceFrame {
+                    message: "while evaluating derivation 'hello-2.12.1'".to_owned(),
+                    location: None,
+                },
+                TraceFrame {
+                    message:
+                        "while evaluating attribute 'buildInputs' of derivation 'hello-2.12.1'"
+                            .to_owned(),
+                    location: location("/home/user/project/default.nix", 6, 3),
+                },
+            ]
+        );
+    }
+}
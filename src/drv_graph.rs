@@ -0,0 +1,145 @@
+//! Build-time dependency graphs of derivations
+
+use std::path::{Path, PathBuf};
+
+use crate::{drv_info, out_paths, Derivation, Result};
+
+/// Derivation in a [`DependencyGraph`]
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GraphNode {
+    /// Path of the derivation
+    pub drv_path: PathBuf,
+    /// Name of the derivation, like `openssl-3.0.13`
+    pub name: String,
+}
+
+/// Dependency of a derivation on another in a [`DependencyGraph`]
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct GraphEdge {
+    /// Path of the dependent derivation
+    pub from: PathBuf,
+    /// Path of the derivation depended on
+    pub to: PathBuf,
+    /// Outputs of the derivation depended on used by the build, like `out` or `dev`
+    pub outputs: Vec<String>,
+}
+
+/// Build-time dependency graph of a derivation, see [`Derivation::graph`]
+///
+/// Nodes and edges are sorted by path
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct DependencyGraph {
+    /// Path of the derivation the graph was computed for
+    pub root: PathBuf,
+    /// Derivations needed to build the root, including itself
+    pub nodes: Vec<GraphNode>,
+    /// Dependencies between the derivations
+    pub edges: Vec<GraphEdge>,
+}
+
+/// Quote `s` as a DOT string
+fn dot_quoted(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+impl DependencyGraph {
+    /// Returns the derivations the derivation at `drv_path` depends on directly
+    pub fn dependencies(&self, drv_path: impl AsRef<Path>) -> Vec<&GraphNode> {
+        let drv_path = drv_path.as_ref();
+        self.edges
+            .iter()
+            .filter(|edge| edge.from == drv_path)
+            .filter_map(|edge| self.nodes.iter().find(|node| node.drv_path == edge.to))
+            .collect()
+    }
+
+    /// Render the graph in the DOT language of Graphviz, with dependents pointing to
+    /// their dependencies
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph dependencies {\n    rankdir=LR;\n");
+
+        for node in &self.nodes {
+            dot.push_str(&format!(
+                "    {} [label={}];\n",
+                dot_quoted(&node.drv_path.to_string_lossy()),
+                dot_quoted(&node.name)
+            ));
+        }
+
+        for edge in &self.edges {
+            dot.push_str(&format!(
+                "    {} -> {}",
+                dot_quoted(&edge.from.to_string_lossy()),
+                dot_quoted(&edge.to.to_string_lossy())
+            ));
+            // most dependencies are on the default output only
+            if edge.outputs != ["out"] {
+                dot.push_str(&format!(" [label={}]", dot_quoted(&edge.outputs.join(","))));
+            }
+            dot.push_str(";\n");
+        }
+
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Render the graph as JSON, with the `root`, `nodes` and `edges` fields
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("dependency graphs are serializable")
+    }
+}
+
+impl Derivation {
+    /// Returns the build-time dependency graph of this derivation, from
+    /// `nix derivation show --recursive`
+    ///
+    /// Only derivations are included, not the sources they depend on
+    ///
+    /// # Example
+    /// ```no_run
+    /// # fn main() -> Result<(), nix_build::Error> {
+    /// let derivations = nix_build::Config::new()
+    ///     .target_flake("nixpkgs#curl")
+    ///     .build()?;
+    ///
+    /// let graph = derivations[0].graph()?;
+    /// std::fs::write("docs/curl-dependencies.dot", graph.to_dot())?;
+    /// # Ok(()) }
+    /// ```
+    pub fn graph(&self) -> Result<DependencyGraph> {
+        let infos = drv_info::show_all(&self.drv_path, true)?;
+
+        let nodes = infos
+            .iter()
+            .map(|(drv_path, info)| GraphNode {
+                drv_path: drv_path.clone(),
+                name: match info.name.is_empty() {
+                    false => info.name.clone(),
+                    // the name is only printed since nix 2.18
+                    true => out_paths::store_name(drv_path)
+                        .unwrap_or_default()
+                        .trim_end_matches(".drv")
+                        .to_owned(),
+                },
+            })
+            .collect();
+
+        let edges = infos
+            .iter()
+            .flat_map(|(drv_path, info)| {
+                info.input_drvs.iter().map(|(input, outputs)| GraphEdge {
+                    from: drv_path.clone(),
+                    to: input.clone(),
+                    outputs: outputs.clone(),
+                })
+            })
+            .collect();
+
+        Ok(DependencyGraph {
+            root: self.drv_path.to_path_buf(),
+            nodes,
+            edges,
+        })
+    }
+}
@@ -0,0 +1,186 @@
+//! Contents of `.drv` files, from `nix derivation show`
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    ffi::OsStr,
+    path::{Path, PathBuf},
+};
+
+use crate::{command, store::StorePath, version, Derivation, Error, Result};
+
+/// Contents of a store derivation, see [`Derivation::show`]
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DerivationInfo {
+    /// Name of the derivation, like `openssl-3.0.13`
+    #[serde(default)]
+    pub name: String,
+    /// System the derivation is built for, like `x86_64-linux`
+    pub system: String,
+    /// Program run to build the derivation, usually `bash`
+    pub builder: PathBuf,
+    /// Arguments passed to the builder
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Environment of the builder, with the attributes of the derivation
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// Derivations the derivation depends on, with the names of the outputs used
+    #[serde(default, deserialize_with = "input_drvs")]
+    pub input_drvs: BTreeMap<PathBuf, Vec<String>>,
+    /// Store paths that are not built, like sources, the derivation depends on
+    #[serde(default)]
+    pub input_srcs: Vec<PathBuf>,
+    /// Outputs of the derivation, by name
+    #[serde(default)]
+    pub outputs: BTreeMap<String, DerivationOutput>,
+}
+
+/// Output declared by a [`DerivationInfo`]
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DerivationOutput {
+    /// Path of the output, unknown until built for content-addressed derivations
+    #[serde(default)]
+    pub path: Option<PathBuf>,
+    /// Hash algorithm of fixed-output and content-addressed derivations, like `sha256`
+    #[serde(default)]
+    pub hash_algo: Option<String>,
+    /// Expected hash of fixed-output derivations
+    #[serde(default)]
+    pub hash: Option<String>,
+}
+
+impl DerivationInfo {
+    /// Returns the value of the environment variable `name` of the builder
+    pub fn env_var(&self, name: &str) -> Option<&str> {
+        self.env.get(name).map(String::as_str)
+    }
+
+    /// Returns the flags passed to `./configure` by the standard builder, from `configureFlags`
+    ///
+    /// Flags are split on whitespace. Derivations with structured attributes
+    /// keep them in the `__json` variable instead, and return no flags
+    pub fn configure_flags(&self) -> Vec<&str> {
+        self.env_var("configureFlags")
+            .map(|flags| flags.split_whitespace().collect())
+            .unwrap_or_default()
+    }
+
+    /// Make the store paths absolute, as newer versions of nix print their base names only
+    fn with_store_dir(mut self, store_dir: &Path) -> Self {
+        let absolute = |path: PathBuf| match path.is_absolute() {
+            true => path,
+            false => store_dir.join(path),
+        };
+
+        self.input_drvs = std::mem::take(&mut self.input_drvs)
+            .into_iter()
+            .map(|(path, outputs)| (absolute(path), outputs))
+            .collect();
+        self.input_srcs = std::mem::take(&mut self.input_srcs)
+            .into_iter()
+            .map(absolute)
+            .collect();
+        for output in self.outputs.values_mut() {
+            output.path = output.path.take().map(absolute);
+        }
+        self
+    }
+}
+
+/// Input derivation, printed as a list of outputs before nix 2.18
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum RawInputDrv {
+    Outputs(Vec<String>),
+    Object {
+        #[serde(default)]
+        outputs: Vec<String>,
+    },
+}
+
+fn input_drvs<'de, D>(
+    deserializer: D,
+) -> std::result::Result<BTreeMap<PathBuf, Vec<String>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let raw: BTreeMap<PathBuf, RawInputDrv> = serde::Deserialize::deserialize(deserializer)?;
+
+    Ok(raw
+        .into_iter()
+        .map(|(path, input)| match input {
+            RawInputDrv::Outputs(outputs) | RawInputDrv::Object { outputs } => (path, outputs),
+        })
+        .collect())
+}
+
+/// `nix derivation show` prints the derivations keyed by path, wrapped in newer versions
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum RawShow {
+    Wrapped {
+        derivations: HashMap<PathBuf, DerivationInfo>,
+    },
+    Map(HashMap<PathBuf, DerivationInfo>),
+}
+
+/// Returns the contents of the derivation at `drv_path`, and of the derivations
+/// it depends on when `recursive`, keyed by absolute path
+pub(crate) fn show_all(
+    drv_path: &StorePath,
+    recursive: bool,
+) -> Result<BTreeMap<PathBuf, DerivationInfo>> {
+    // `nix derivation show` replaced `nix show-derivation` in 2.15
+    let subcommand: &[&str] = match version::detect() {
+        Some(version) if !version.at_least(2, 15) => &["show-derivation"],
+        _ => &["derivation", "show"],
+    };
+
+    let mut args: Vec<&OsStr> = subcommand.iter().map(OsStr::new).collect();
+    if recursive {
+        args.push(OsStr::new("--recursive"));
+    }
+    args.push(drv_path.as_os_str());
+
+    let infos = match command::nix_json(args)? {
+        RawShow::Wrapped { derivations } | RawShow::Map(derivations) => derivations,
+    };
+
+    let store_dir = drv_path.store_dir();
+    Ok(infos
+        .into_iter()
+        .map(|(path, info)| {
+            let path = match path.is_absolute() {
+                true => path,
+                false => store_dir.join(path),
+            };
+            (path, info.with_store_dir(store_dir))
+        })
+        .collect())
+}
+
+impl Derivation {
+    /// Returns the contents of the `.drv` file of this derivation, from `nix derivation show`
+    ///
+    /// Useful to check how a dependency was built before linking against it
+    ///
+    /// # Example
+    /// ```no_run
+    /// # fn main() -> Result<(), nix_build::Error> {
+    /// let derivations = nix_build::Config::new()
+    ///     .target_flake("nixpkgs#curl")
+    ///     .build()?;
+    ///
+    /// let info = derivations[0].show()?;
+    /// assert!(info.configure_flags().contains(&"--with-openssl"));
+    /// # Ok(()) }
+    /// ```
+    pub fn show(&self) -> Result<DerivationInfo> {
+        show_all(&self.drv_path, false)?
+            .into_values()
+            .next()
+            .ok_or(Error::UnknownOutput)
+    }
+}
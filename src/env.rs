@@ -0,0 +1,114 @@
+//! Configuration overrides read from environment variables
+
+use crate::{Config, Error, Result};
+
+/// Target to build: a flake installable if it contains `#` or `:`, a .nix file otherwise
+const TARGET: &str = "NIX_BUILD_TARGET";
+
+/// Whether to evaluate impurely, `1`/`true` or `0`/`false`
+const IMPURE: &str = "NIX_BUILD_IMPURE";
+
+/// Nix settings as `name=value`, separated by `;` or newlines
+const OPTIONS: &str = "NIX_BUILD_OPTIONS";
+
+/// Extra binary caches, separated by whitespace
+const SUBSTITUTERS: &str = "NIX_BUILD_SUBSTITUTERS";
+
+/// System to build for, like `aarch64-linux`
+const SYSTEM: &str = "NIX_BUILD_SYSTEM";
+
+/// Returns the value of the environment variable `name`, if set and not empty
+fn var(name: &str) -> Option<String> {
+    std::env::var(name)
+        .ok()
+        .filter(|value| !value.trim().is_empty())
+}
+
+/// Parse a boolean set in the environment variable `name`
+fn parse_bool(name: &str, value: &str) -> Result<bool> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Ok(true),
+        "0" | "false" | "no" | "off" => Ok(false),
+        _ => Err(Error::InvalidConfig(format!(
+            "{name} must be a boolean, got `{value}`"
+        ))),
+    }
+}
+
+impl Config {
+    /// Create a new nix build [`Config`] with the overrides set in the environment
+    ///
+    /// See [`Config::env_overrides`]
+    pub fn from_env() -> Result<Self> {
+        let mut config = Self::new();
+        config.env_overrides()?;
+        Ok(config)
+    }
+
+    /// Apply the overrides set in the environment, so the build can be tweaked
+    /// without patching the build script
+    ///
+    /// | Variable | Override |
+    /// |----------|----------|
+    /// | `NIX_BUILD_TARGET` | target, a flake if it contains `#` or `:`, a file otherwise |
+    /// | `NIX_BUILD_IMPURE` | [`Config::impure`], `1`/`true` or `0`/`false` |
+    /// | `NIX_BUILD_OPTIONS` | [`Config::option`] for each `name=value`, separated by `;` or newlines |
+    /// | `NIX_BUILD_SUBSTITUTERS` | [`Config::substituter`] for each URL, separated by whitespace |
+    /// | `NIX_BUILD_SYSTEM` | [`Config::system`] |
+    ///
+    /// The variables are tracked, see [`Config::track_env`].
+    /// Fails with [`Error::InvalidConfig`] if a variable can't be parsed
+    ///
+    /// # Example
+    /// ```no_run
+    /// # fn main() -> Result<(), nix_build::Error> {
+    /// use nix_build::Config;
+    ///
+    /// let derivations = Config::new()
+    ///     .target_flake("nixpkgs#openssl")
+    ///     .env_overrides()?
+    ///     .build()?;
+    /// # Ok(()) }
+    /// ```
+    pub fn env_overrides(&mut self) -> Result<&mut Self> {
+        for name in [TARGET, IMPURE, OPTIONS, SUBSTITUTERS, SYSTEM] {
+            self.track_env(name);
+        }
+
+        if let Some(target) = var(TARGET) {
+            if target.contains(['#', ':']) {
+                self.target_flake(&target);
+            } else {
+                self.target_file(target);
+            }
+        }
+
+        if let Some(impure) = var(IMPURE) {
+            self.impure(parse_bool(IMPURE, &impure)?);
+        }
+
+        if let Some(options) = var(OPTIONS) {
+            for option in options.split([';', '\n']).map(str::trim) {
+                if option.is_empty() {
+                    continue;
+                }
+                let (name, value) = option.split_once('=').ok_or_else(|| {
+                    Error::InvalidConfig(format!("{OPTIONS} entry `{option}` is not name=value"))
+                })?;
+                self.option(name.trim(), value.trim());
+            }
+        }
+
+        if let Some(substituters) = var(SUBSTITUTERS) {
+            for url in substituters.split_whitespace() {
+                self.substituter(url);
+            }
+        }
+
+        if let Some(system) = var(SYSTEM) {
+            self.system(system.trim());
+        }
+
+        Ok(self)
+    }
+}
@@ -0,0 +1,79 @@
+//! Evaluation cache of flakes, kept by nix in its cache directory
+
+use std::path::{Path, PathBuf};
+
+use crate::{command, Error, Result};
+
+/// Returns the cache directory of nix, like `~/.cache/nix`
+fn nix_cache_dir() -> Option<PathBuf> {
+    let non_empty = |name: &str| std::env::var_os(name).filter(|value| !value.is_empty());
+
+    non_empty("NIX_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| non_empty("XDG_CACHE_HOME").map(|dir| Path::new(&dir).join("nix")))
+        .or_else(|| non_empty("HOME").map(|home| Path::new(&home).join(".cache/nix")))
+}
+
+/// Clear the evaluation cache of the flake `flake_ref`, returning the removed files
+///
+/// The flake is locked again with `nix flake metadata --refresh`, so a branch that was
+/// force-pushed resolves to its new revision, and the cached evaluations of that revision
+/// are removed. Nix versions not reporting the fingerprint of flakes, before 2.20,
+/// don't allow to find them, and the evaluation caches of every flake are removed instead
+///
+/// # Example
+/// ```no_run
+/// # fn main() -> Result<(), nix_build::Error> {
+/// nix_build::purge_eval_cache("github:example/sdk/main")?;
+///
+/// let derivations = nix_build::Config::new()
+///     .target_flake("github:example/sdk/main#default")
+///     .build()?;
+/// # Ok(()) }
+/// ```
+pub fn purge_eval_cache(flake_ref: &str) -> Result<Vec<PathBuf>> {
+    #[derive(serde::Deserialize)]
+    struct Metadata {
+        fingerprint: Option<String>,
+    }
+
+    let metadata: Metadata =
+        command::nix_json(["flake", "metadata", "--refresh", "--json", flake_ref])?;
+    let cache_dir = nix_cache_dir().ok_or(Error::MissingEnvVar("HOME"))?;
+
+    let mut removed = vec![];
+    if !cache_dir.is_dir() {
+        return Ok(removed);
+    }
+
+    // versions of the cache are in separate directories, like `eval-cache-v5`
+    for dir in std::fs::read_dir(&cache_dir)? {
+        let dir = dir?.path();
+        let is_eval_cache = dir
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.starts_with("eval-cache-v"));
+        if !is_eval_cache || !dir.is_dir() {
+            continue;
+        }
+
+        for file in std::fs::read_dir(&dir)? {
+            let file = file?.path();
+            // databases are named after the fingerprint, with their journal next to them
+            let matches = match (&metadata.fingerprint, file.file_name()) {
+                (Some(fingerprint), Some(name)) => name
+                    .to_string_lossy()
+                    .starts_with(&format!("{fingerprint}.sqlite")),
+                (None, Some(name)) => name.to_string_lossy().contains(".sqlite"),
+                (_, None) => false,
+            };
+            if matches {
+                debug!("removing evaluation cache {}", file.display());
+                std::fs::remove_file(&file)?;
+                removed.push(file);
+            }
+        }
+    }
+
+    Ok(removed)
+}
@@ -0,0 +1,66 @@
+//! Facts evaluated with nix, exposed to the crate as compile-time environment variables
+
+use std::collections::BTreeMap;
+
+use crate::{cargo, Config, Error, Result};
+
+impl Config {
+    /// Evaluate the nix expressions `vars`, by variable name, and emit each result as
+    /// `cargo:rustc-env=NAME=value`, to read it with `env!` in the crate
+    ///
+    /// The target is bound to `target` in the expressions, like `target.version`.
+    /// Results are converted with `builtins.toString`, and evaluated with a single
+    /// `nix eval`. Directives are only emitted when the cargo integration is enabled,
+    /// see [`Config::cargo_integration`]. Returns the values by name
+    ///
+    /// # Example
+    /// ```no_run
+    /// # fn main() -> Result<(), nix_build::Error> {
+    /// nix_build::Config::new()
+    ///     .target_flake("nixpkgs#openssl")
+    ///     .emit_eval_env(&[
+    ///         ("NIX_OPENSSL_VERSION", "target.version"),
+    ///         ("NIX_OPENSSL_OUTPUTS", "builtins.concatStringsSep \",\" target.outputs"),
+    ///     ])?;
+    ///
+    /// // in the crate: `const OPENSSL_VERSION: &str = env!("NIX_OPENSSL_VERSION");`
+    /// # Ok(()) }
+    /// ```
+    pub fn emit_eval_env(&self, vars: &[(&str, &str)]) -> Result<BTreeMap<String, String>> {
+        self.validate()?;
+
+        if let Some((name, _)) = vars
+            .iter()
+            .find(|(name, _)| name.is_empty() || name.contains(['=', '\n', ' ']))
+        {
+            return Err(Error::InvalidConfig(format!(
+                "`{name}` is not a valid environment variable name"
+            )));
+        }
+
+        let attrs: Vec<String> = vars
+            .iter()
+            .map(|(name, expr)| format!("{name:?} = builtins.toString ({expr});"))
+            .collect();
+        let apply = format!("target: {{ {} }}", attrs.join(" "));
+
+        // outputs can't be selected when evaluating an installable
+        let mut config = self.clone();
+        config.target = self.target.clone().with_outputs(Vec::<String>::new());
+        let values: BTreeMap<String, String> = config.eval_json(&apply)?;
+
+        if let Some((name, _)) = values.iter().find(|(_, value)| value.contains('\n')) {
+            return Err(Error::InvalidConfig(format!(
+                "the value of `{name}` spans several lines, which cargo doesn't support"
+            )));
+        }
+
+        if self.is_cargo_integrated() {
+            for (name, value) in &values {
+                cargo::rustc_env(name, value);
+            }
+        }
+
+        Ok(values)
+    }
+}
@@ -1,4 +1,6 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
+
+use crate::{Error, Result};
 
 const GITHUB_LOCKED_NIXPKGS: &str = std::include_str!("exprs/github_locked_nixpkgs.nix");
 
@@ -20,3 +22,410 @@ in
 "##
     )
 }
+
+/// Returns `value` quoted as a nix string literal
+fn string_literal(value: &str) -> String {
+    let escaped = value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace("${", "\\${");
+
+    format!("\"{escaped}\"")
+}
+
+/// Returns an expression adding a file named `name` with the given contents to the store,
+/// evaluating to its path
+///
+/// Meant to pass content generated by the build script, like headers or patches,
+/// to nix expressions. The file can't refer to other store paths, see `builtins.toFile`
+///
+/// # Example
+/// ```no_run
+/// # fn main() -> Result<(), nix_build::Error> {
+/// let patch = nix_build::exprs::write_text("version.patch", "--- a/version.h\n+++ b/version.h\n");
+///
+/// let derivations = nix_build::Config::new()
+///     .target_expr("{patch}: (import <nixpkgs> {}).zlib.overrideAttrs (old: { patches = [ patch ]; })")
+///     .arg_expr("patch", &patch)
+///     .build()?;
+/// # Ok(()) }
+/// ```
+pub fn write_text(name: &str, contents: &str) -> String {
+    format!(
+        "builtins.toFile {} {}",
+        string_literal(name),
+        string_literal(contents)
+    )
+}
+
+/// Returns a fixed-output derivation fetching the file at `url`
+///
+/// The file is prefetched to capture its hash, see [`crate::prefetch_url`].
+/// The expression uses the `fetchurl` bundled with nix, so it doesn't depend on nixpkgs
+///
+/// # Example
+/// ```no_run
+/// # fn main() -> Result<(), nix_build::Error> {
+/// let sdk = nix_build::exprs::fetch_fixed("https://example.com/sdk-1.0.tar.gz")?;
+///
+/// let derivations = nix_build::Config::new().target_expr(&sdk).build()?;
+/// # Ok(()) }
+/// ```
+pub fn fetch_fixed(url: &str) -> Result<String> {
+    let prefetched = crate::prefetch_url(url)?;
+
+    Ok(format!(
+        "import <nix/fetchurl.nix> {{ url = {}; hash = {}; }}",
+        string_literal(url),
+        string_literal(&prefetched.hash)
+    ))
+}
+
+/// Apple SDK of nixpkgs providing the macOS frameworks, see [`darwin_deps_with`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DarwinSdk {
+    /// The default SDK of nixpkgs before 24.11, `darwin.apple_sdk`
+    ///
+    /// Its frameworks are kept as empty stubs by newer nixpkgs
+    #[default]
+    Legacy,
+    /// The macOS 11 SDK of nixpkgs before 24.11, `darwin.apple_sdk_11_0`
+    Legacy11,
+    /// The SDK packages of nixpkgs 24.11 onward, `apple-sdk` or `apple-sdk_<version>`
+    /// like `apple-sdk_14`, which provide all the frameworks
+    AppleSdk(Option<u32>),
+}
+
+impl DarwinSdk {
+    /// Returns the reference to the framework `name` in the package set `pkgs`,
+    /// or to the whole SDK for [`DarwinSdk::AppleSdk`]
+    pub fn framework(&self, pkgs: &str, name: &str) -> String {
+        let name = string_literal(name);
+        match self {
+            Self::Legacy => format!("{pkgs}.darwin.apple_sdk.frameworks.{name}"),
+            Self::Legacy11 => format!("{pkgs}.darwin.apple_sdk_11_0.frameworks.{name}"),
+            Self::AppleSdk(None) => format!("{pkgs}.apple-sdk"),
+            Self::AppleSdk(Some(version)) => format!("{pkgs}.apple-sdk_{version}"),
+        }
+    }
+}
+
+/// Returns a function of the nixpkgs package set returning the given macOS frameworks,
+/// or an empty list on other systems
+///
+/// Uses the default SDK of nixpkgs before 24.11, see [`darwin_deps_with`] to pick another one
+///
+/// # Example
+/// ```no_run
+/// # fn main() -> Result<(), nix_build::Error> {
+/// let frameworks = nix_build::exprs::darwin_deps(&["Security", "CoreFoundation"]);
+///
+/// let derivations = nix_build::Config::new()
+///     .target_expr(&format!(
+///         "let pkgs = import <nixpkgs> {{}}; in pkgs.mkShell {{ buildInputs = ({frameworks}) pkgs; }}"
+///     ))
+///     .build()?;
+/// # Ok(()) }
+/// ```
+pub fn darwin_deps(frameworks: &[&str]) -> String {
+    darwin_deps_with(DarwinSdk::default(), frameworks)
+}
+
+/// Returns a function of the nixpkgs package set returning the given macOS frameworks
+/// of `sdk`, or an empty list on other systems
+///
+/// The SDK packages of nixpkgs 24.11 onward are returned once, whatever the frameworks
+pub fn darwin_deps_with(sdk: DarwinSdk, frameworks: &[&str]) -> String {
+    let deps: Vec<String> = match sdk {
+        DarwinSdk::AppleSdk(_) => vec![sdk.framework("pkgs", "")],
+        _ => frameworks
+            .iter()
+            .map(|name| sdk.framework("pkgs", name))
+            .collect(),
+    };
+
+    format!(
+        "pkgs: pkgs.lib.optionals pkgs.stdenv.hostPlatform.isDarwin [ {} ]",
+        deps.join(" ")
+    )
+}
+
+/// Nix builder of a [`RustPackage`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum RustBuilder {
+    /// `rustPlatform.buildRustPackage` of nixpkgs
+    #[default]
+    RustPlatform,
+    /// `buildPackage` of [crane](https://github.com/ipetkov/crane), from the given flake
+    ///
+    /// The flake is fetched with `builtins.getFlake`, which requires an impure evaluation
+    /// unless the reference is locked
+    Crane { flake: String },
+}
+
+/// Expression packaging a rust crate with nix, like the crate being built
+///
+/// The crate source is filtered to leave out `target`, `.git` and `result` links,
+/// or with `cleanCargoSource` for crane.
+/// Dependencies are vendored from `Cargo.lock`, unless a `cargoHash` is given,
+/// while dependencies fetched from git need a hash for nixpkgs' `cargoLock.outputHashes`
+///
+/// # Example
+/// ```no_run
+/// # fn main() -> Result<(), nix_build::Error> {
+/// use nix_build::exprs::RustPackage;
+///
+/// let package = RustPackage::current()?.expr();
+/// let derivations = nix_build::Config::new().target_expr(&package).build()?;
+/// # Ok(()) }
+/// ```
+#[derive(Debug, Clone)]
+pub struct RustPackage {
+    src: PathBuf,
+    name: String,
+    version: String,
+    package: Option<String>,
+    builder: RustBuilder,
+    nixpkgs: String,
+    cargo_hash: Option<String>,
+}
+
+impl RustPackage {
+    /// Package the crates of the cargo project in `src`, the directory of `Cargo.lock`
+    pub fn new(src: impl Into<PathBuf>, name: &str, version: &str) -> Self {
+        Self {
+            src: src.into(),
+            name: name.to_owned(),
+            version: version.to_owned(),
+            package: None,
+            builder: RustBuilder::default(),
+            nixpkgs: "import <nixpkgs> {}".to_owned(),
+            cargo_hash: None,
+        }
+    }
+
+    /// Package the crate being built, from the variables set by cargo
+    ///
+    /// The source is the closest directory of the crate manifest with a `Cargo.lock`,
+    /// like the workspace root, and only the crate is built
+    pub fn current() -> Result<Self> {
+        let var = |name: &'static str| std::env::var(name).map_err(|_| Error::MissingEnvVar(name));
+
+        let manifest_dir = PathBuf::from(var("CARGO_MANIFEST_DIR")?);
+        let src = manifest_dir
+            .ancestors()
+            .find(|dir| dir.join("Cargo.lock").is_file())
+            .unwrap_or(&manifest_dir);
+
+        let name = var("CARGO_PKG_NAME")?;
+        let mut package = Self::new(src, &name, &var("CARGO_PKG_VERSION")?);
+        package.package(&name);
+        Ok(package)
+    }
+
+    /// Only build the crate `name` of the workspace
+    pub fn package(&mut self, name: &str) -> &mut Self {
+        self.package = Some(name.to_owned());
+        self
+    }
+
+    /// Build the package with `builder`
+    pub fn builder(&mut self, builder: RustBuilder) -> &mut Self {
+        self.builder = builder;
+        self
+    }
+
+    /// Use the nixpkgs set evaluated by `expr`, like [`nixpkgs_from_flake`]
+    pub fn nixpkgs(&mut self, expr: &str) -> &mut Self {
+        self.nixpkgs = expr.to_owned();
+        self
+    }
+
+    /// Vendor the dependencies as the fixed-output derivation of hash `hash`,
+    /// the `cargoHash` of nixpkgs, instead of reading `Cargo.lock`
+    ///
+    /// Ignored by crane, which always reads `Cargo.lock`
+    pub fn cargo_hash(&mut self, hash: &str) -> &mut Self {
+        self.cargo_hash = Some(hash.to_owned());
+        self
+    }
+
+    /// Returns the expression building the package
+    pub fn expr(&self) -> String {
+        let src = format!(
+            r#"builtins.path {{
+        path = {};
+        name = "source";
+        filter = path: type: !(builtins.elem (baseNameOf path) [ "target" ".git" "result" ]);
+    }}"#,
+            string_literal(&self.src.display().to_string())
+        );
+        let pname = string_literal(&self.name);
+        let version = string_literal(&self.version);
+        let package = self.package.as_deref().map(string_literal);
+
+        let build = match &self.builder {
+            RustBuilder::RustPlatform => {
+                let vendor = match &self.cargo_hash {
+                    Some(hash) => format!("cargoHash = {};", string_literal(hash)),
+                    None => "cargoLock.lockFile = src + \"/Cargo.lock\";".to_owned(),
+                };
+                let flags = package.map_or(String::new(), |package| {
+                    format!("cargoBuildFlags = [ \"-p\" {package} ];")
+                });
+                format!(
+                    "pkgs.rustPlatform.buildRustPackage {{ pname = {pname}; version = {version}; inherit src; {vendor} {flags} }}"
+                )
+            }
+            RustBuilder::Crane { flake } => {
+                let args = package.map_or(String::new(), |package| {
+                    format!("cargoExtraArgs = \"-p \" + {package};")
+                });
+                format!(
+                    "let craneLib = (builtins.getFlake {}).mkLib pkgs; in craneLib.buildPackage {{ pname = {pname}; version = {version}; src = craneLib.cleanCargoSource src; {args} }}",
+                    string_literal(flake)
+                )
+            }
+        };
+
+        format!(
+            r#"
+let
+    pkgs = {};
+    src = {src};
+in
+    {build}
+"#,
+            self.nixpkgs
+        )
+    }
+}
+
+/// Expression wrapping a built library into a derivation providing a `pkg-config` file,
+/// for libraries that don't ship one
+///
+/// The generated `lib/pkgconfig/<name>.pc` points at the `lib` directory of the `lib` output
+/// of the library, or its default output, and at the `include` directory of its `dev` output,
+/// or its default output
+///
+/// # Example
+/// ```no_run
+/// # fn main() -> Result<(), nix_build::Error> {
+/// use nix_build::{exprs::PkgConfigWrapper, PkgConfig};
+///
+/// let wrapper = PkgConfigWrapper::new("lua", "5.4")
+///     .lib("lua")
+///     .expr("(import <nixpkgs> {}).lua5_4");
+/// let derivations = nix_build::Config::new().target_expr(&wrapper).build()?;
+///
+/// let lua = PkgConfig::new().derivations(&derivations).probe("lua")?;
+/// # Ok(()) }
+/// ```
+#[derive(Debug, Clone)]
+pub struct PkgConfigWrapper {
+    name: String,
+    version: String,
+    description: String,
+    requires: Vec<String>,
+    libs: Vec<String>,
+    cflags: Vec<String>,
+    nixpkgs: String,
+}
+
+impl PkgConfigWrapper {
+    /// Describe the `pkg-config` module `name` at version `version`
+    pub fn new(name: &str, version: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            version: version.to_owned(),
+            description: name.to_owned(),
+            requires: vec![],
+            libs: vec![],
+            cflags: vec![],
+            nixpkgs: "import <nixpkgs> {}".to_owned(),
+        }
+    }
+
+    /// Set the description of the module, its name by default
+    pub fn description(&mut self, description: &str) -> &mut Self {
+        self.description = description.to_owned();
+        self
+    }
+
+    /// Add the `pkg-config` module `module` to the requirements, like `zlib >= 1.2`
+    pub fn requires(&mut self, module: &str) -> &mut Self {
+        self.requires.push(module.to_owned());
+        self
+    }
+
+    /// Link the library `name`, without the `lib` prefix nor the extension
+    pub fn lib(&mut self, name: &str) -> &mut Self {
+        self.libs.push(format!("-l{name}"));
+        self
+    }
+
+    /// Add the raw linker flag `flag`, like `-pthread`
+    pub fn libs_flag(&mut self, flag: &str) -> &mut Self {
+        self.libs.push(flag.to_owned());
+        self
+    }
+
+    /// Add the raw compiler flag `flag`, like `-DFOO_STATIC`
+    pub fn cflag(&mut self, flag: &str) -> &mut Self {
+        self.cflags.push(flag.to_owned());
+        self
+    }
+
+    /// Use the nixpkgs set evaluated by `expr`, like [`nixpkgs_from_flake`]
+    pub fn nixpkgs(&mut self, expr: &str) -> &mut Self {
+        self.nixpkgs = expr.to_owned();
+        self
+    }
+
+    /// Returns the expression wrapping the library evaluated by `library`
+    pub fn expr(&self, library: &str) -> String {
+        let mut libs = vec!["-L${libdir}".to_owned()];
+        libs.extend(self.libs.iter().cloned());
+        let mut cflags = vec!["-I${includedir}".to_owned()];
+        cflags.extend(self.cflags.iter().cloned());
+
+        let mut lines = vec![
+            "\"libdir=${library.lib or library}/lib\"".to_owned(),
+            "\"includedir=${library.dev or library}/include\"".to_owned(),
+            string_literal(""),
+            string_literal(&format!("Name: {}", self.name)),
+            string_literal(&format!("Description: {}", self.description)),
+            string_literal(&format!("Version: {}", self.version)),
+        ];
+        if !self.requires.is_empty() {
+            lines.push(string_literal(&format!(
+                "Requires: {}",
+                self.requires.join(", ")
+            )));
+        }
+        lines.push(string_literal(&format!("Libs: {}", libs.join(" "))));
+        lines.push(string_literal(&format!("Cflags: {}", cflags.join(" "))));
+        lines.push(string_literal(""));
+
+        format!(
+            r#"
+let
+    pkgs = {};
+    library = {library};
+in
+    pkgs.runCommand {} {{
+        pcName = {};
+        pc = builtins.concatStringsSep "\n" [ {} ];
+        passAsFile = [ "pc" ];
+    }} ''
+        mkdir -p $out/lib/pkgconfig
+        cp "$pcPath" "$out/lib/pkgconfig/$pcName.pc"
+    ''
+"#,
+            self.nixpkgs,
+            string_literal(&format!("{}-pkg-config", self.name)),
+            string_literal(&self.name),
+            lines.join(" ")
+        )
+    }
+}
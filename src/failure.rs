@@ -0,0 +1,285 @@
+//! Classification of nix failures from their error output
+
+use std::path::PathBuf;
+
+use crate::{Diagnostic, Error};
+
+/// Returns the first single-quoted string in `line`
+fn quoted(line: &str) -> Option<&str> {
+    line.split('\'').nth(1)
+}
+
+/// Returns the error message found in the given nix error output,
+/// starting from the first `error:` line
+fn message(stderr: &str) -> String {
+    let start = stderr.find("error:").unwrap_or_default();
+    stderr[start..].trim().to_owned()
+}
+
+/// Returns the value following `label` on any line, like `got: sha256-...`
+fn labelled<'a>(stderr: &'a str, labels: &[&str]) -> Option<&'a str> {
+    stderr.lines().find_map(|line| {
+        let line = line.trim();
+        labels
+            .iter()
+            .find_map(|label| line.strip_prefix(label))
+            .map(|value| value.trim().trim_end_matches(','))
+    })
+}
+
+/// Returns the build log lines quoted by nix after `last N log lines:`
+fn log_tail(stderr: &str) -> String {
+    let quoted: Vec<&str> = stderr
+        .lines()
+        .skip_while(|line| !line.contains("log lines:"))
+        .filter_map(|line| line.trim_start().strip_prefix('>'))
+        .map(|line| line.strip_prefix(' ').unwrap_or(line))
+        .collect();
+
+    if quoted.is_empty() {
+        message(stderr)
+    } else {
+        quoted.join("\n")
+    }
+}
+
+/// Messages of the network failures worth retrying, lowercased
+const TRANSIENT_PATTERNS: &[&str] = &[
+    "timed out",
+    "timeout was reached",
+    "connection reset",
+    "connection refused",
+    "couldn't resolve host",
+    "could not resolve host",
+    "failure when receiving data",
+    "http error 429",
+    "http error 500",
+    "http error 502",
+    "http error 503",
+    "http error 504",
+    "unexpected eof",
+];
+
+/// Returns whether the given error output describes a network failure, likely to succeed
+/// when retried
+pub(crate) fn is_transient(stderr: &str) -> bool {
+    let stderr = stderr.to_lowercase();
+    TRANSIENT_PATTERNS
+        .iter()
+        .any(|pattern| stderr.contains(pattern))
+}
+
+/// Returns the binary caches that failed, in order, from the warnings printed by nix
+fn substituter_urls(stderr: &str) -> Vec<String> {
+    let mut urls: Vec<String> = vec![];
+
+    for line in stderr.lines() {
+        let url = if line.contains("disabling binary cache '") {
+            quoted(line).map(str::to_owned)
+        } else if line.contains("unable to download '") {
+            // keep the cache of the downloaded file, like `https://cache.nixos.org`
+            quoted(line).and_then(|url| {
+                let (scheme, rest) = url.split_once("://")?;
+                let host = rest.split('/').next()?;
+                Some(format!("{scheme}://{host}"))
+            })
+        } else {
+            None
+        };
+
+        if let Some(url) = url.filter(|url| !urls.contains(url)) {
+            urls.push(url);
+        }
+    }
+
+    urls
+}
+
+/// Classify the failure described in the given nix error output
+pub(crate) fn classify(stderr: &str) -> Error {
+    // nix suggests building from source when the substitutes failed
+    if stderr.contains("try '--fallback'") {
+        return Error::SubstituterUnavailable {
+            urls: substituter_urls(stderr),
+        };
+    }
+
+    // nix refuses to build during evaluation when `allow-import-from-derivation` is disabled
+    if let Some(line) = stderr
+        .lines()
+        .find(|line| line.contains("'allow-import-from-derivation' is disabled"))
+    {
+        return Error::ImportFromDerivation {
+            drv: quoted(line)
+                .map(|drv| drv.split('^').next().unwrap_or(drv))
+                .map(PathBuf::from),
+        };
+    }
+
+    if stderr.contains("hash mismatch") {
+        if let Some((expected, got)) =
+            labelled(stderr, &["specified:", "wanted:"]).zip(labelled(stderr, &["got:"]))
+        {
+            return Error::HashMismatch {
+                expected: expected.to_owned(),
+                got: got.to_owned(),
+            };
+        }
+    }
+
+    let builder = stderr
+        .lines()
+        .find(|line| line.contains("builder for '") || line.contains("Cannot build '"));
+    if let Some(line) = builder {
+        return Error::BuildError {
+            drv: quoted(line).map(PathBuf::from),
+            log_tail: log_tail(stderr),
+            log_file: None,
+        };
+    }
+
+    let substitution = ["substitute", "unable to download", "cannot download"];
+    if stderr.lines().any(|line| {
+        line.trim_start().starts_with("error:")
+            && substitution.iter().any(|pattern| line.contains(pattern))
+    }) {
+        return Error::SubstitutionError {
+            message: message(stderr),
+        };
+    }
+
+    Error::EvalError {
+        message: message(stderr),
+        diagnostic: Diagnostic::parse(stderr),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Location;
+
+    #[test]
+    fn builder_failure() {
+        let stderr = "\
+error: builder for '/nix/store/vkb3h6vb2nhcbmmp7zs1w2n0x1i8sv3l-hello-2.12.1.drv' failed with exit code 2;
+       last 3 log lines:
+       > checking for gcc... gcc
+       > hello.c:3:1: error: expected ';' before '}' token
+       > make: *** [Makefile:1034: hello.o] Error 1
+       For full logs, run 'nix log /nix/store/vkb3h6vb2nhcbmmp7zs1w2n0x1i8sv3l-hello-2.12.1.drv'.
+";
+        let Error::BuildError { drv, log_tail, .. } = classify(stderr) else {
+            panic!("not a build error");
+        };
+        assert_eq!(
+            drv,
+            Some(PathBuf::from(
+                "/nix/store/vkb3h6vb2nhcbmmp7zs1w2n0x1i8sv3l-hello-2.12.1.drv"
+            ))
+        );
+        assert_eq!(
+            log_tail,
+            "checking for gcc... gcc\n\
+             hello.c:3:1: error: expected ';' before '}' token\n\
+             make: *** [Makefile:1034: hello.o] Error 1"
+        );
+    }
+
+    #[test]
+    fn hash_mismatch() {
+        let stderr = "\
+error: hash mismatch in fixed-output derivation '/nix/store/0qg6bsvrp6wnzqjdkvjkv0yh0ar3fqk5-source.drv':
+         specified: sha256-AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=
+            got:    sha256-47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=
+";
+        let Error::HashMismatch { expected, got } = classify(stderr) else {
+            panic!("not a hash mismatch");
+        };
+        assert_eq!(
+            expected,
+            "sha256-AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA="
+        );
+        assert_eq!(got, "sha256-47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=");
+    }
+
+    #[test]
+    fn unavailable_substituters() {
+        let stderr = "\
+warning: error: unable to download 'https://cache.nixos.org/vkb3h6vb2nhcbmmp7zs1w2n0x1i8sv3l.narinfo': Couldn't resolve host name (6)
+warning: disabling binary cache 'https://cache.example.org' for 60 seconds
+error: some substitutes for the outputs of derivation '/nix/store/vkb3h6vb2nhcbmmp7zs1w2n0x1i8sv3l-hello-2.12.1.drv' failed (usually happens due to networking issues); try '--fallback' to build derivation from source
+";
+        let Error::SubstituterUnavailable { urls } = classify(stderr) else {
+            panic!("not an unavailable substituter");
+        };
+        assert_eq!(
+            urls,
+            ["https://cache.nixos.org", "https://cache.example.org"]
+        );
+        assert!(is_transient(stderr));
+    }
+
+    #[test]
+    fn import_from_derivation() {
+        let stderr = "\
+error: cannot build '/nix/store/9w0bd6yv5m0gxvwryjfxvjvr6ch0bqsm-generated.drv^out' during evaluation because the option 'allow-import-from-derivation' is disabled
+";
+        let Error::ImportFromDerivation { drv } = classify(stderr) else {
+            panic!("not an import from derivation");
+        };
+        assert_eq!(
+            drv,
+            Some(PathBuf::from(
+                "/nix/store/9w0bd6yv5m0gxvwryjfxvjvr6ch0bqsm-generated.drv"
+            ))
+        );
+    }
+
+    #[test]
+    fn substitution_failure() {
+        let stderr = "\
+error: unable to download 'https://example.org/archive.tar.gz': HTTP error 404
+";
+        let Error::SubstitutionError { message } = classify(stderr) else {
+            panic!("not a substitution error");
+        };
+        assert!(message.starts_with("error: unable to download"));
+        assert!(!is_transient(stderr));
+    }
+
+    #[test]
+    fn evaluation_failure() {
+        let stderr = "\
+warning: Git tree '/home/user/project' is dirty
+error: undefined variable 'fooo'
+
+       at /home/user/project/flake.nix:9:17:
+
+            8|     packages.x86_64-linux.default =
+            9|       let pkgs = fooo; in pkgs.hello;
+             |                  ^
+           10|   };
+";
+        let Error::EvalError {
+            message,
+            diagnostic,
+        } = classify(stderr)
+        else {
+            panic!("not an evaluation error");
+        };
+        assert!(message.starts_with("error: undefined variable 'fooo'"));
+
+        let diagnostic = diagnostic.expect("no diagnostic");
+        assert_eq!(diagnostic.message, "undefined variable 'fooo'");
+        assert_eq!(
+            diagnostic.location,
+            Some(Location {
+                file: PathBuf::from("/home/user/project/flake.nix"),
+                line: 9,
+                column: 17,
+            })
+        );
+        assert!(diagnostic.trace.is_empty());
+    }
+}
@@ -0,0 +1,102 @@
+//! Stable hashing used to identify a build configuration across runs
+
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use crate::NIX_STORE_DIR;
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// FNV-1a hasher
+///
+/// Unlike [`std::hash::DefaultHasher`] the result is guaranteed
+/// to be the same across Rust releases, so it can be persisted
+pub(crate) struct Hasher(u64);
+
+impl Hasher {
+    pub(crate) fn new() -> Self {
+        Self(FNV_OFFSET_BASIS)
+    }
+
+    fn write_raw(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    /// Hash the given bytes, prefixed by their length to avoid ambiguities between fields
+    pub(crate) fn write(&mut self, bytes: impl AsRef<[u8]>) -> &mut Self {
+        let bytes = bytes.as_ref();
+        self.write_raw(&(bytes.len() as u64).to_le_bytes());
+        self.write_raw(bytes);
+        self
+    }
+
+    /// Hash the files under the directory `dir` recursively, with their relative paths
+    ///
+    /// Hidden entries, like `.git`, `target` directories and the `excluded` paths are skipped.
+    /// Symlinks are hashed by their target, and the ones into the store, like out-links,
+    /// are skipped too
+    pub(crate) fn write_tree(&mut self, dir: &Path, excluded: &[PathBuf]) -> &mut Self {
+        self.write_tree_at(dir, Path::new(""), excluded);
+        self
+    }
+
+    fn write_tree_at(&mut self, root: &Path, relative: &Path, excluded: &[PathBuf]) {
+        let Ok(entries) = std::fs::read_dir(root.join(relative)) else {
+            return;
+        };
+        let mut names: Vec<_> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.file_name())
+            .filter(|name| !name.as_encoded_bytes().starts_with(b".") && name != "target")
+            .collect();
+        names.sort();
+
+        for name in names {
+            let relative = relative.join(name);
+            let path = root.join(&relative);
+            let link = std::fs::read_link(&path).ok();
+            if excluded.contains(&path)
+                || link
+                    .as_ref()
+                    .is_some_and(|target| target.starts_with(NIX_STORE_DIR))
+            {
+                continue;
+            }
+
+            self.write(relative.as_os_str().as_encoded_bytes());
+            if let Some(target) = link {
+                self.write(target.as_os_str().as_encoded_bytes());
+            } else if path.is_dir() {
+                self.write_tree_at(root, &relative, excluded);
+            } else {
+                self.write(std::fs::read(&path).unwrap_or_default());
+            }
+        }
+    }
+
+    pub(crate) fn finish(&self) -> Fingerprint {
+        Fingerprint(self.0)
+    }
+}
+
+/// Identifies a build configuration
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct Fingerprint(u64);
+
+impl Fingerprint {
+    pub(crate) fn as_u64(&self) -> u64 {
+        self.0
+    }
+}
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
@@ -0,0 +1,116 @@
+//! Checks of flakes, like `nix flake check`
+
+use crate::{command, failure, BuildSet, Config, Error, FlakeRef, Result};
+
+/// Outcome of a check of a flake, see [`flake_check`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheckResult {
+    /// Name of the check, the attribute in `checks.<system>`
+    pub name: String,
+    /// Whether the check built successfully
+    pub passed: bool,
+    /// Build log of the failed check, or the error preventing it from being built
+    pub log: String,
+}
+
+/// Results of [`flake_check`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlakeCheck {
+    /// System the checks were built for, like `x86_64-linux`
+    pub system: String,
+    /// Results of the checks, sorted by name
+    pub checks: Vec<CheckResult>,
+}
+
+impl FlakeCheck {
+    /// Returns whether all the checks passed
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(|check| check.passed)
+    }
+
+    /// Returns the checks that failed
+    pub fn failures(&self) -> impl Iterator<Item = &CheckResult> {
+        self.checks.iter().filter(|check| !check.passed)
+    }
+}
+
+/// Returns the log of a failed build
+fn failure_log(err: &Error) -> String {
+    match err {
+        Error::BuildError { log_tail, .. } => log_tail.clone(),
+        err => err.to_string(),
+    }
+}
+
+/// Check the flake `flake_ref`, like `nix flake check`, reporting the result of each check
+///
+/// The outputs of the flake are first evaluated and validated with
+/// `nix flake check --no-build`, failing with [`Error::EvalError`] when they're not
+/// consistent. The `checks` of the current system are then built concurrently, each
+/// passing or failing with its build log, as `nix flake check` doesn't report them separately
+///
+/// # Example
+/// ```no_run
+/// # fn main() -> Result<(), nix_build::Error> {
+/// let result = nix_build::flake_check("./nix")?;
+///
+/// for check in result.failures() {
+///     println!("cargo:warning=check {} failed:\n{}", check.name, check.log);
+/// }
+/// assert!(result.passed());
+/// # Ok(()) }
+/// ```
+pub fn flake_check(flake_ref: &str) -> Result<FlakeCheck> {
+    let flake = FlakeRef::parse(flake_ref)?.without_attr().to_string();
+
+    command::nix_stdout(["flake", "check", "--no-build", &flake]).map_err(|err| match err {
+        Error::CommandFailed { stderr, .. } => failure::classify(&stderr),
+        err => err,
+    })?;
+
+    let system: String = Config::new()
+        .target_expr("builtins.currentSystem")
+        .impure(true)
+        .eval_json("system: system")?;
+
+    let names: Vec<String> = match Config::new()
+        .target_flake(format!("{flake}#checks.{system}"))
+        .eval_json("checks: builtins.attrNames checks")
+    {
+        Ok(names) => names,
+        Err(Error::EvalError { message, .. }) if message.contains("does not provide attribute") => {
+            vec![]
+        }
+        Err(err) => return Err(err),
+    };
+
+    let mut set = BuildSet::new();
+    for name in &names {
+        let mut config = Config::new();
+        config
+            .target_flake(format!("{flake}#checks.{system}.\"{name}\""))
+            .cargo_integration(false);
+        set.add(name.clone(), &config);
+    }
+
+    let failures = match set.build() {
+        Ok(_) => vec![],
+        Err(Error::BuildSetFailed(failures)) => failures,
+        Err(err) => return Err(err),
+    };
+
+    let mut checks: Vec<CheckResult> = names
+        .into_iter()
+        .map(|name| {
+            let failure = failures.iter().find(|(label, _)| *label == name);
+            CheckResult {
+                passed: failure.is_none(),
+                log: failure.map(|(_, err)| failure_log(err)).unwrap_or_default(),
+                name,
+            }
+        })
+        .collect();
+    checks.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(FlakeCheck { system, checks })
+}
@@ -0,0 +1,372 @@
+//! Typed flake references
+
+use std::{fmt, path::Path, str::FromStr};
+
+use crate::{Error, Result};
+
+/// Types of flake references understood by nix, as in `github:owner/repo`
+const KNOWN_TYPES: [&str; 23] = [
+    "flake",
+    "path",
+    "github",
+    "gitlab",
+    "sourcehut",
+    "git",
+    "git+http",
+    "git+https",
+    "git+ssh",
+    "git+file",
+    "hg+http",
+    "hg+https",
+    "hg+ssh",
+    "hg+file",
+    "tarball+http",
+    "tarball+https",
+    "tarball+file",
+    "file+http",
+    "file+https",
+    "file+file",
+    "http",
+    "https",
+    "file",
+];
+
+/// Reference to a flake, optionally with the attribute selected in it
+///
+/// Parsed leniently from strings, see [`FlakeRef::parse`] to also validate it
+///
+/// # Example
+/// ```
+/// use nix_build::FlakeRef;
+///
+/// let nixpkgs = FlakeRef::github("NixOS", "nixpkgs")
+///     .with_ref("nixos-24.05")
+///     .with_attr("openssl");
+/// assert_eq!(nixpkgs.to_string(), "github:NixOS/nixpkgs/nixos-24.05#openssl");
+///
+/// let local: FlakeRef = "path:./nix?dir=native#lib".parse().unwrap();
+/// assert_eq!(local.dir(), Some("native"));
+/// assert_eq!(local.attr(), Some("lib"));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FlakeRef {
+    kind: Option<String>,
+    location: String,
+    params: Vec<(String, String)>,
+    attr: Option<String>,
+}
+
+impl FlakeRef {
+    /// Reference the flake `repo` of `owner` on GitHub
+    pub fn github(owner: &str, repo: &str) -> Self {
+        Self::with_kind("github", format!("{owner}/{repo}"))
+    }
+
+    /// Reference the flake `repo` of `owner` on GitLab
+    pub fn gitlab(owner: &str, repo: &str) -> Self {
+        Self::with_kind("gitlab", format!("{owner}/{repo}"))
+    }
+
+    /// Reference the flake in the local directory `path`
+    pub fn path(path: impl AsRef<Path>) -> Self {
+        Self::with_kind("path", path.as_ref().display().to_string())
+    }
+
+    /// Reference the flake in the git repository at `url`, like `ssh://git@host/repo`
+    pub fn git(url: &str) -> Self {
+        match url.split_once("://") {
+            Some((scheme, rest)) => Self::with_kind(&format!("git+{scheme}"), format!("//{rest}")),
+            None => Self::with_kind("git+file", url.to_owned()),
+        }
+    }
+
+    /// Reference the flake registered as `id`, like `nixpkgs`
+    pub fn indirect(id: &str) -> Self {
+        Self {
+            kind: None,
+            location: id.to_owned(),
+            params: vec![],
+            attr: None,
+        }
+    }
+
+    fn with_kind(kind: &str, location: String) -> Self {
+        Self {
+            kind: Some(kind.to_owned()),
+            location,
+            params: vec![],
+            attr: None,
+        }
+    }
+
+    /// Parse and validate a flake reference, like `github:owner/repo/ref?dir=sub#attr`
+    ///
+    /// Fails with [`Error::InvalidFlakeRef`] if the reference is malformed
+    pub fn parse(flake_ref: &str) -> Result<Self> {
+        let parsed = Self::from(flake_ref);
+        parsed.validate().map_err(|reason| Error::InvalidFlakeRef {
+            flake_ref: flake_ref.to_owned(),
+            reason,
+        })?;
+        Ok(parsed)
+    }
+
+    /// Returns why the reference is malformed, if it is
+    fn validate(&self) -> std::result::Result<(), String> {
+        if self.location.is_empty() {
+            return Err("empty location".to_owned());
+        }
+        if self.attr.as_deref() == Some("") {
+            return Err("empty attribute".to_owned());
+        }
+        if let Some((_, param)) = self.params.iter().find(|(name, _)| name.is_empty()) {
+            return Err(format!("malformed parameter `{param}`"));
+        }
+
+        let Some(kind) = self.kind.as_deref() else {
+            if self.is_local() {
+                return Ok(());
+            }
+            let id = self.location.split('/').next().unwrap_or_default();
+            let valid_id = id.starts_with(|c: char| c.is_ascii_alphabetic())
+                && id
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+            return if valid_id {
+                Ok(())
+            } else {
+                Err(format!("invalid flake id `{id}`"))
+            };
+        };
+
+        if !KNOWN_TYPES.contains(&kind) {
+            return Err(format!("unknown type `{kind}`"));
+        }
+
+        match kind {
+            "github" | "gitlab" | "sourcehut" => {
+                let segments: Vec<&str> = self.location.split('/').collect();
+                if !(2..=3).contains(&segments.len()) || segments.iter().any(|s| s.is_empty()) {
+                    return Err(format!("expected {kind}:owner/repo[/ref]"));
+                }
+                if segments.len() == 3
+                    && (self.param("ref").is_some() || self.param("rev").is_some())
+                {
+                    return Err("the ref is set both in the path and as a parameter".to_owned());
+                }
+            }
+            "path" | "flake" => {}
+            _ if kind.contains("+file") || kind == "file" => {}
+            _ if !self.location.starts_with("//") => {
+                return Err(format!("expected {kind}://host/path"));
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// Returns the type of the reference, like `github`, unless it's a path or a flake id
+    pub fn kind(&self) -> Option<&str> {
+        self.kind.as_deref()
+    }
+
+    /// Returns the location of the flake, between the type and the parameters
+    pub fn location(&self) -> &str {
+        &self.location
+    }
+
+    /// Returns the value of the parameter `name`
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns the subdirectory of the flake, the `dir` parameter
+    pub fn dir(&self) -> Option<&str> {
+        self.param("dir")
+    }
+
+    /// Returns the commit the flake is pinned to, the `rev` parameter
+    pub fn rev(&self) -> Option<&str> {
+        self.param("rev")
+    }
+
+    /// Returns the branch or tag of the flake, from the `ref` parameter or the location
+    /// of `github:owner/repo/ref` like references
+    pub fn reference(&self) -> Option<&str> {
+        self.param("ref").or_else(|| match self.kind.as_deref() {
+            Some("github" | "gitlab" | "sourcehut") => self.location.splitn(3, '/').nth(2),
+            None if !self.is_local() => self.location.split_once('/').map(|(_, rest)| rest),
+            _ => None,
+        })
+    }
+
+    /// Returns the owner of `github:owner/repo` like references
+    pub fn owner(&self) -> Option<&str> {
+        self.forge_segment(0)
+    }
+
+    /// Returns the repository of `github:owner/repo` like references
+    pub fn repo(&self) -> Option<&str> {
+        self.forge_segment(1)
+    }
+
+    fn forge_segment(&self, n: usize) -> Option<&str> {
+        match self.kind.as_deref() {
+            Some("github" | "gitlab" | "sourcehut") => self.location.split('/').nth(n),
+            _ => None,
+        }
+    }
+
+    /// Returns the attribute selected in the flake, the fragment after `#`
+    pub fn attr(&self) -> Option<&str> {
+        self.attr.as_deref()
+    }
+
+    /// Returns whether the flake is in a local directory
+    pub fn is_local(&self) -> bool {
+        match self.kind.as_deref() {
+            Some("path" | "git+file") => true,
+            None => self.location.starts_with(['.', '/']),
+            _ => false,
+        }
+    }
+
+    /// Returns the reference without the selected attribute
+    pub fn without_attr(&self) -> Self {
+        Self {
+            attr: None,
+            ..self.clone()
+        }
+    }
+
+    /// Set the parameter `name` to `value`
+    pub fn with_param(mut self, name: &str, value: &str) -> Self {
+        match self.params.iter_mut().find(|(key, _)| key == name) {
+            Some((_, old)) => *old = value.to_owned(),
+            None => self.params.push((name.to_owned(), value.to_owned())),
+        }
+        self
+    }
+
+    /// Pin the flake to the branch or tag `reference`
+    ///
+    /// Appended to the location of `github:owner/repo` like references and flake ids,
+    /// set as the `ref` parameter otherwise
+    pub fn with_ref(mut self, reference: &str) -> Self {
+        match self.kind.as_deref() {
+            Some("github" | "gitlab" | "sourcehut") if self.location.split('/').count() == 2 => {
+                self.location = format!("{}/{reference}", self.location);
+                self
+            }
+            None if !self.is_local() && !self.location.contains('/') => {
+                self.location = format!("{}/{reference}", self.location);
+                self
+            }
+            _ => self.with_param("ref", reference),
+        }
+    }
+
+    /// Pin the flake to the commit `rev`
+    pub fn with_rev(self, rev: &str) -> Self {
+        self.with_param("rev", rev)
+    }
+
+    /// Select the flake in the subdirectory `dir`
+    pub fn with_dir(self, dir: &str) -> Self {
+        self.with_param("dir", dir)
+    }
+
+    /// Select the attribute `attr` of the flake, like `packages.x86_64-linux.default`
+    pub fn with_attr(mut self, attr: &str) -> Self {
+        self.attr = Some(attr.to_owned());
+        self
+    }
+}
+
+impl From<&str> for FlakeRef {
+    fn from(flake_ref: &str) -> Self {
+        let (flake_ref, attr) = match flake_ref.split_once('#') {
+            Some((flake_ref, attr)) => (flake_ref, Some(attr.to_owned())),
+            None => (flake_ref, None),
+        };
+        let (flake_ref, query) = flake_ref.split_once('?').unwrap_or((flake_ref, ""));
+
+        let params = query
+            .split('&')
+            .filter(|param| !param.is_empty())
+            .map(|param| {
+                let (name, value) = param.split_once('=').unwrap_or(("", param));
+                (name.to_owned(), value.to_owned())
+            })
+            .collect();
+
+        // `./path`, `/path` and flake ids like `nixpkgs/branch` have no type
+        let typed = flake_ref
+            .split_once(':')
+            .filter(|(kind, _)| !kind.is_empty() && !kind.contains(['/', '.']));
+        let (kind, location) = match typed {
+            Some((kind, location)) => (Some(kind.to_owned()), location),
+            None => (None, flake_ref),
+        };
+
+        Self {
+            kind,
+            location: location.to_owned(),
+            params,
+            attr,
+        }
+    }
+}
+
+impl From<String> for FlakeRef {
+    fn from(flake_ref: String) -> Self {
+        Self::from(flake_ref.as_str())
+    }
+}
+
+impl From<&String> for FlakeRef {
+    fn from(flake_ref: &String) -> Self {
+        Self::from(flake_ref.as_str())
+    }
+}
+
+impl From<&FlakeRef> for FlakeRef {
+    fn from(flake_ref: &FlakeRef) -> Self {
+        flake_ref.clone()
+    }
+}
+
+impl FromStr for FlakeRef {
+    type Err = Error;
+
+    fn from_str(flake_ref: &str) -> Result<Self> {
+        Self::parse(flake_ref)
+    }
+}
+
+impl fmt::Display for FlakeRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(kind) = &self.kind {
+            write!(f, "{kind}:")?;
+        }
+        f.write_str(&self.location)?;
+
+        for (i, (name, value)) in self.params.iter().enumerate() {
+            let separator = if i == 0 { '?' } else { '&' };
+            match name.as_str() {
+                // kept as parsed, rejected by validation
+                "" => write!(f, "{separator}{value}")?,
+                name => write!(f, "{separator}{name}={value}")?,
+            }
+        }
+
+        if let Some(attr) = &self.attr {
+            write!(f, "#{attr}")?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,67 @@
+//! Hashing with `nix hash`, as used by fixed-output derivations
+
+use std::{ffi::OsString, path::Path};
+
+use crate::{command, version, Result};
+
+/// Hash algorithm supported by nix
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HashAlgo {
+    Md5,
+    Sha1,
+    #[default]
+    Sha256,
+    Sha512,
+}
+
+impl HashAlgo {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Md5 => "md5",
+            Self::Sha1 => "sha1",
+            Self::Sha256 => "sha256",
+            Self::Sha512 => "sha512",
+        }
+    }
+}
+
+/// Run `nix hash <mode>` on `path`, returning the SRI hash
+fn hash(mode: &str, path: &Path, algo: HashAlgo) -> Result<String> {
+    // `--type` has been deprecated in favor of `--algo` in 2.19
+    let flag = match version::detect() {
+        Some(version) if version.at_least(2, 19) => "--algo",
+        _ => "--type",
+    };
+
+    let args: [OsString; 6] = [
+        "hash".into(),
+        mode.into(),
+        "--sri".into(),
+        flag.into(),
+        algo.as_str().into(),
+        path.into(),
+    ];
+
+    let stdout = command::nix_stdout(args)?;
+    Ok(String::from_utf8_lossy(&stdout).trim().to_owned())
+}
+
+/// Returns the SRI hash of the content of the file at `path`, like `fetchurl` expects
+///
+/// # Example
+/// ```no_run
+/// # fn main() -> Result<(), nix_build::Error> {
+/// use nix_build::HashAlgo;
+///
+/// let hash = nix_build::hash_file("vendor/sdk-1.0.tar.gz", HashAlgo::Sha256)?;
+/// assert!(hash.starts_with("sha256-"));
+/// # Ok(()) }
+/// ```
+pub fn hash_file(path: impl AsRef<Path>, algo: HashAlgo) -> Result<String> {
+    hash("file", path.as_ref(), algo)
+}
+
+/// Returns the SRI hash of the NAR serialization of `path`, like recursive fetchers expect
+pub fn hash_path(path: impl AsRef<Path>, algo: HashAlgo) -> Result<String> {
+    hash("path", path.as_ref(), algo)
+}
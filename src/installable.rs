@@ -0,0 +1,237 @@
+//! Targets of the `nix` subcommands
+
+use std::{
+    ffi::OsString,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use crate::FlakeRef;
+
+/// Package selected by the `nix` subcommands, like the target of a [`Config`](crate::Config)
+///
+/// Strings convert to flake installables, like `nixpkgs#openssl^dev`
+///
+/// # Example
+/// ```no_run
+/// # fn main() -> Result<(), nix_build::Error> {
+/// use nix_build::{Config, Installable, Run};
+///
+/// let protobuf = Installable::flake("nixpkgs#protobuf").with_outputs(["out"]);
+///
+/// let derivations = Config::new().target(protobuf.clone()).build()?;
+/// let status = Run::new(protobuf).arg("--version").status()?;
+/// # Ok(()) }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Installable {
+    /// Attribute of a flake, the fragment of `flake_ref`, with the outputs to select
+    ///
+    /// All the default outputs are selected if `outputs` is empty
+    Flake {
+        flake_ref: FlakeRef,
+        outputs: Vec<String>,
+    },
+    /// The .nix `file`, or its attribute `attr`
+    File { file: PathBuf, attr: Option<String> },
+    /// The nix expression `expr`, or its attribute `attr`
+    Expr { expr: String, attr: Option<String> },
+    /// The store derivation `path`, already instantiated, with the outputs to select
+    ///
+    /// All the outputs are selected if `outputs` is empty
+    Drv { path: PathBuf, outputs: Vec<String> },
+}
+
+impl Default for Installable {
+    fn default() -> Self {
+        Self::file("default.nix")
+    }
+}
+
+impl Installable {
+    /// Select the flake output `flake_ref`, like `nixpkgs#openssl`
+    ///
+    /// Outputs following `^`, like in `nixpkgs#openssl^dev,out`, are split into `outputs`
+    pub fn flake(flake_ref: impl Into<FlakeRef>) -> Self {
+        let flake_ref = flake_ref.into();
+        let split = flake_ref.attr().and_then(|attr| {
+            let (attr, outputs) = attr.split_once('^')?;
+            Some((
+                attr.to_owned(),
+                outputs.split(',').map(str::to_owned).collect(),
+            ))
+        });
+
+        match split {
+            Some((attr, outputs)) => Self::Flake {
+                flake_ref: flake_ref.with_attr(&attr),
+                outputs,
+            },
+            None => Self::Flake {
+                flake_ref,
+                outputs: vec![],
+            },
+        }
+    }
+
+    /// Select the .nix file `file`
+    pub fn file(file: impl Into<PathBuf>) -> Self {
+        Self::File {
+            file: file.into(),
+            attr: None,
+        }
+    }
+
+    /// Select the nix expression `expr`
+    pub fn expr(expr: &str) -> Self {
+        Self::Expr {
+            expr: expr.to_owned(),
+            attr: None,
+        }
+    }
+
+    /// Select the store derivation `path`, like `/nix/store/<hash>-openssl-3.0.13.drv`
+    pub fn drv(path: impl Into<PathBuf>) -> Self {
+        Self::Drv {
+            path: path.into(),
+            outputs: vec![],
+        }
+    }
+
+    /// Select the attribute `attr`, replacing the fragment of flake references
+    ///
+    /// Ignored for store derivations
+    pub fn with_attr(self, attr: &str) -> Self {
+        match self {
+            Self::Flake { flake_ref, outputs } => Self::Flake {
+                flake_ref: flake_ref.with_attr(attr),
+                outputs,
+            },
+            Self::File { file, .. } => Self::File {
+                file,
+                attr: Some(attr.to_owned()),
+            },
+            Self::Expr { expr, .. } => Self::Expr {
+                expr,
+                attr: Some(attr.to_owned()),
+            },
+            drv @ Self::Drv { .. } => drv,
+        }
+    }
+
+    /// Select the given outputs of a flake output or store derivation, like `dev`,
+    /// or `*` for all of them
+    ///
+    /// Ignored for files and expressions
+    pub fn with_outputs<I, S>(mut self, outputs: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        if let Self::Flake { outputs: old, .. } | Self::Drv { outputs: old, .. } = &mut self {
+            *old = outputs.into_iter().map(Into::into).collect();
+        }
+        self
+    }
+
+    /// Returns the selected attribute, if any
+    pub fn attr(&self) -> Option<&str> {
+        match self {
+            Self::Flake { flake_ref, .. } => flake_ref.attr(),
+            Self::File { attr, .. } | Self::Expr { attr, .. } => attr.as_deref(),
+            Self::Drv { .. } => None,
+        }
+    }
+
+    /// Returns whether a flake output is selected
+    pub fn is_flake(&self) -> bool {
+        matches!(self, Self::Flake { .. })
+    }
+
+    /// Returns the installable without the selected attribute and outputs
+    pub(crate) fn without_attr(&self) -> Self {
+        match self {
+            Self::Flake { flake_ref, .. } => Self::flake(flake_ref.without_attr()),
+            Self::File { file, .. } => Self::file(file.clone()),
+            Self::Expr { expr, .. } => Self::expr(expr),
+            Self::Drv { path, .. } => Self::drv(path.clone()),
+        }
+    }
+
+    /// Returns the flake installable, like `nixpkgs#openssl^dev`
+    pub(crate) fn flake_installable(flake_ref: &FlakeRef, outputs: &[String]) -> String {
+        match outputs {
+            [] => flake_ref.to_string(),
+            outputs => format!("{flake_ref}^{}", outputs.join(",")),
+        }
+    }
+
+    /// Returns the derivation installable, like `/nix/store/<hash>-openssl-3.0.13.drv^*`
+    pub(crate) fn drv_installable(path: &Path, outputs: &[String]) -> String {
+        match outputs {
+            [] => format!("{}^*", path.display()),
+            outputs => format!("{}^{}", path.display(), outputs.join(",")),
+        }
+    }
+
+    /// Returns the arguments selecting the installable
+    pub(crate) fn args(&self) -> Vec<OsString> {
+        let mut args: Vec<OsString> = vec![];
+
+        match self {
+            Self::Flake { flake_ref, outputs } => {
+                args.push(Self::flake_installable(flake_ref, outputs).into());
+            }
+            Self::File { file, attr } => {
+                args.extend(["-f".into(), file.into()]);
+                args.extend(attr.iter().map(OsString::from));
+            }
+            Self::Expr { expr, attr } => {
+                args.extend(["--expr".into(), expr.into()]);
+                args.extend(attr.iter().map(OsString::from));
+            }
+            Self::Drv { path, outputs } => {
+                args.push(Self::drv_installable(path, outputs).into());
+            }
+        }
+
+        args
+    }
+}
+
+impl fmt::Display for Installable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Flake { flake_ref, outputs } => {
+                f.write_str(&Self::flake_installable(flake_ref, outputs))
+            }
+            Self::File { file, .. } => write!(f, "{}", file.display()),
+            Self::Expr { expr, .. } => write!(f, "expression `{expr}`"),
+            Self::Drv { path, outputs } => f.write_str(&Self::drv_installable(path, outputs)),
+        }
+    }
+}
+
+impl From<FlakeRef> for Installable {
+    fn from(flake_ref: FlakeRef) -> Self {
+        Self::flake(flake_ref)
+    }
+}
+
+impl From<&str> for Installable {
+    fn from(installable: &str) -> Self {
+        Self::flake(installable)
+    }
+}
+
+impl From<String> for Installable {
+    fn from(installable: String) -> Self {
+        Self::flake(installable)
+    }
+}
+
+impl From<&Installable> for Installable {
+    fn from(installable: &Installable) -> Self {
+        installable.clone()
+    }
+}
@@ -0,0 +1,46 @@
+//! Evaluation of the target to store derivations, without building them
+
+use std::path::PathBuf;
+
+use crate::{store::StorePath, Config, Installable, Result};
+
+/// Nix function returning the derivation paths of the target, a derivation or a list of them
+const DRV_PATHS_EXPR: &str =
+    "target: map (drv: drv.drvPath) (if builtins.isList target then target else [ target ])";
+
+impl Config {
+    /// Evaluate the target to the paths of its store derivations, without building them
+    ///
+    /// Uses `nix eval`, or `nix-instantiate` with the legacy backend. The derivations can be
+    /// built later, possibly elsewhere, with [`realise`](crate::realise).
+    /// Meant to fail early on evaluation errors, before the expensive builds
+    ///
+    /// # Example
+    /// ```no_run
+    /// # fn main() -> Result<(), nix_build::Error> {
+    /// let drv_paths = nix_build::Config::new()
+    ///     .target_flake("nixpkgs#openssl")
+    ///     .instantiate()?;
+    ///
+    /// for drv_path in drv_paths {
+    ///     let derivations = nix_build::realise(drv_path)?;
+    /// }
+    /// # Ok(()) }
+    /// ```
+    pub fn instantiate(&self) -> Result<Vec<StorePath>> {
+        self.validate()?;
+
+        let drv_paths: Vec<PathBuf> = match &self.target {
+            Installable::Drv { path, .. } => vec![path.clone()],
+            _ if self.uses_legacy() => self.instantiate_legacy()?,
+            _ => {
+                // outputs can't be selected when evaluating an installable
+                let mut config = self.clone();
+                config.target = self.target.clone().with_outputs(Vec::<String>::new());
+                config.eval_json(DRV_PATHS_EXPR)?
+            }
+        };
+
+        drv_paths.into_iter().map(StorePath::new).collect()
+    }
+}
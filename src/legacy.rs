@@ -0,0 +1,145 @@
+//! Build backend using the legacy `nix-instantiate` and `nix-store` programs
+
+use std::{collections::HashMap, path::PathBuf};
+
+use crate::{
+    command, failure, store::StorePath, Config, Derivation, Diagnostic, Error, Installable, Result,
+};
+
+/// Program used to build derivations
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Backend {
+    /// Use the unified `nix` CLI, falling back to the legacy programs
+    /// when it's not available or doesn't support the required features
+    #[default]
+    Auto,
+    /// Always use the unified `nix` CLI
+    Unified,
+    /// Always use the legacy `nix-instantiate` and `nix-store` programs
+    ///
+    /// Flake targets are not supported
+    Legacy,
+}
+
+/// Returns whether the given `nix build` error output indicates an unsupported nix version
+pub(crate) fn is_unsupported(stderr: &str) -> bool {
+    stderr.contains("unrecognised flag")
+        || stderr.contains("unrecognized flag")
+        || stderr.contains("experimental Nix feature")
+}
+
+/// Parse the outputs of a derivation stored in the ATerm format
+///
+/// The file starts with `Derive([("out","/nix/store/...-name","",""),...],`
+fn parse_drv_outputs(drv: &str) -> Option<HashMap<String, StorePath>> {
+    let mut rest = drv.strip_prefix("Derive([")?;
+    let mut outputs = HashMap::new();
+
+    while let Some(tuple) = rest.strip_prefix("(\"") {
+        let (name, tuple) = tuple.split_once("\",\"")?;
+        let (path, tuple) = tuple.split_once('"')?;
+        outputs.insert(name.to_owned(), StorePath::new(path).ok()?);
+
+        let (_, tuple) = tuple.split_once(')')?;
+        rest = tuple.strip_prefix(',').unwrap_or(tuple);
+    }
+
+    rest.starts_with(']').then_some(outputs)
+}
+
+impl Config {
+    /// Returns whether the target can be built with the legacy programs
+    pub(crate) fn supports_legacy(&self) -> bool {
+        !self.target.is_flake()
+            && self.flake_overrides.is_empty()
+            && self.eval_store.is_none()
+            && self.runner.is_none()
+    }
+
+    /// Build the target with `nix-instantiate` and `nix-store --realise`
+    pub(crate) fn build_legacy(&self) -> Result<Vec<Derivation>> {
+        let (drvs, selected) = match self.selected_target() {
+            Installable::Drv { path, outputs } => (vec![path], outputs),
+            _ => (self.instantiate_legacy()?, self.outputs.clone()),
+        };
+        let all = selected.is_empty() || selected.iter().any(|output| output == "*");
+
+        let mut realise = command::legacy("nix-store")?;
+        self.configure(&mut realise);
+        realise.arg("--realise");
+        for drv in &drvs {
+            match all {
+                true => realise.arg(drv),
+                // only realise the selected outputs, like `<drv>!dev`
+                false => realise.arg(format!("{}!{}", drv.display(), selected.join(","))),
+            };
+        }
+        command::run(&mut realise).map_err(|err| match err {
+            Error::CommandFailed { stderr, .. } => failure::classify(&stderr),
+            err => err,
+        })?;
+
+        drvs.into_iter()
+            .map(|drv_path| {
+                let content = std::fs::read_to_string(self.physical_path(&drv_path))?;
+                let mut outputs = parse_drv_outputs(&content).ok_or(Error::UnknownOutput)?;
+                if !all {
+                    outputs.retain(|name, _| selected.contains(name));
+                }
+
+                Ok(Derivation {
+                    drv_path: StorePath::new(drv_path)?,
+                    outputs,
+                    realisations: HashMap::new(),
+                })
+            })
+            .collect()
+    }
+
+    /// Instantiate the target with `nix-instantiate`, returning the derivation paths
+    pub(crate) fn instantiate_legacy(&self) -> Result<Vec<PathBuf>> {
+        let mut instantiate = command::legacy("nix-instantiate")?;
+        self.configure(&mut instantiate);
+
+        match &self.target {
+            Installable::File { file, .. } => {
+                instantiate.arg(file);
+            }
+            Installable::Expr { expr, .. } => {
+                instantiate.args(["-E", expr]);
+            }
+            installable @ (Installable::Flake { .. } | Installable::Drv { .. }) => {
+                return Err(Error::EvalError {
+                    message: format!("cannot instantiate {installable} without the nix CLI"),
+                    diagnostic: None,
+                })
+            }
+        }
+
+        if let Some(attr) = self.target.attr() {
+            instantiate.args(["-A", attr]);
+        }
+
+        for (key, val) in &self.arg_exprs {
+            instantiate.args(["--arg", key, val]);
+        }
+
+        for (key, val) in &self.arg_strs {
+            instantiate.args(["--argstr", key, val]);
+        }
+
+        let stdout = command::run(&mut instantiate).map_err(|err| match err {
+            Error::CommandFailed { stderr, .. } => Error::EvalError {
+                message: stderr.trim().to_owned(),
+                diagnostic: Diagnostic::parse(&stderr),
+            },
+            err => err,
+        })?;
+        let drvs = String::from_utf8_lossy(&stdout)
+            .lines()
+            .map(|line| line.split_once('!').map_or(line, |(drv, _)| drv))
+            .map(PathBuf::from)
+            .collect();
+        Ok(drvs)
+    }
+}
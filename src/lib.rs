@@ -1,22 +1,414 @@
-use std::path::PathBuf;
+use std::{fmt, path::PathBuf};
+
+#[macro_use]
+mod logging;
 
 mod config;
-pub use config::{Config, Derivation};
+pub use config::{Config, DetectedTarget};
+
+mod builder;
+pub use builder::ConfigBuilder;
+
+mod spec;
+pub use spec::ConfigSpec;
+
+mod flake_ref;
+pub use flake_ref::FlakeRef;
+
+mod flake_check;
+pub use flake_check::{flake_check, CheckResult, FlakeCheck};
+
+mod installable;
+pub use installable::Installable;
+
+mod daemon;
+pub use daemon::check_daemon;
+
+mod diagnostic;
+pub use diagnostic::{Diagnostic, Location, TraceFrame};
+
+mod dev_env;
+pub use dev_env::DevEnv;
+
+mod legacy;
+pub use legacy::Backend;
+
+mod out_paths;
+pub use out_paths::OutputFormat;
+
+mod version;
+pub use version::{capabilities, nix_version, Capabilities, Implementation, NixVersion};
+
+mod meta;
+pub use meta::{meta_for, License, LicensePolicy, Meta};
+
+mod hash;
+pub use hash::{hash_file, hash_path, HashAlgo};
+
+mod eval_cache;
+pub use eval_cache::purge_eval_cache;
+
+mod prefetch;
+pub use prefetch::{prefetch_flake, prefetch_url, Prefetched};
+
+mod run;
+pub use run::Run;
+
+mod shell;
+pub use shell::Shell;
+
+mod profile;
+pub use profile::{Profile, ProfileElement};
+
+mod sandbox;
+pub use sandbox::SandboxMode;
+
+mod build_log;
+pub use build_log::{BuildEvent, BuildReport, BuiltDerivation};
+
+mod build_outputs;
+pub use build_outputs::{BuildOutput, BuildOutputs};
+
+mod build_plan;
+pub use build_plan::BuildPlan;
+
+mod cache_check;
+pub use cache_check::CacheStatus;
+
+mod build_set;
+pub use build_set::BuildSet;
+
+mod build_manifest;
+pub use build_manifest::{BuildManifest, ManifestEntry};
+
+mod spawn;
+pub use spawn::{BuildHandle, DropPolicy};
+
+mod runner;
+pub use runner::{CommandRunner, NixRunner};
+
+mod replay;
+pub use replay::{RecordReplay, ReplayMode};
+
+mod registry;
+pub use registry::{registry_add, registry_list, registry_pin, RegistryEntry, RegistryScope};
+
+mod search;
+pub use search::{search, SearchResult};
+
+mod sbom;
+pub use sbom::{Sbom, SbomComponent, SbomFormat};
+
+mod derivation;
+pub use derivation::{Derivation, Realisation};
+
+mod drv_info;
+pub use drv_info::{DerivationInfo, DerivationOutput};
+
+mod drv_graph;
+pub use drv_graph::{DependencyGraph, GraphEdge, GraphNode};
+
+mod link;
+pub use link::{Library, LinkKind, Linker};
+
+mod pkg_config;
+pub use pkg_config::{PkgConfig, PkgConfigLibrary};
+
+mod resolver;
+pub use resolver::{Resolution, Resolver, Strategy};
+
+mod tool_env;
+pub use tool_env::ToolEnv;
+
+mod vendor;
+pub use vendor::Vendored;
+
+mod wasm;
+pub use wasm::{WasmCross, WasmTarget};
+
+mod build_json;
+mod cargo;
+mod command;
+mod env;
+mod eval_env;
+mod failure;
+mod fingerprint;
+mod instantiate;
+mod manifest;
+mod memo;
+mod nom;
+mod tracking;
+
+#[cfg(feature = "bindgen")]
+mod bindgen;
+
+#[cfg(feature = "cc")]
+mod cc;
+
+#[cfg(feature = "indicatif")]
+mod progress;
+
+#[cfg(feature = "cachix")]
+mod cachix;
+#[cfg(feature = "cachix")]
+pub use cachix::Cachix;
 
 /// Collection of Nix expressions useful for package configuration
 pub mod exprs;
 
+pub mod store;
+
+pub mod testing;
+
 #[derive(Debug)]
 pub enum Error {
     NixNotAvailable,
-    BuildFailed,
+    /// The nix expression failed to evaluate
+    ///
+    /// `diagnostic` points at the offending nix code, when reported
+    EvalError {
+        message: String,
+        diagnostic: Option<Diagnostic>,
+    },
+    /// The builder of the derivation `drv` failed
+    ///
+    /// `log_tail` holds the last lines of the build log,
+    /// `log_file` the full log when [`Config::log_file`] is set
+    BuildError {
+        drv: Option<PathBuf>,
+        log_tail: String,
+        log_file: Option<PathBuf>,
+    },
+    /// Store paths couldn't be substituted from a binary cache
+    SubstitutionError {
+        message: String,
+    },
+    /// The binary caches at `urls` couldn't be reached, and the outputs weren't built
+    /// from source instead, see [`Config::fallback`]
+    SubstituterUnavailable {
+        urls: Vec<String>,
+    },
+    /// The evaluation needed to build the derivation `drv`, importing from it,
+    /// which is disabled by [`Config::allow_ifd`]
+    ImportFromDerivation {
+        drv: Option<PathBuf>,
+    },
+    /// A fixed-output derivation produced an unexpected hash
+    HashMismatch {
+        expected: String,
+        got: String,
+    },
     UnknownOutput,
+    /// The results printed by `nix build --json` have an unknown shape
+    ///
+    /// `nix_version` is the detected version of nix, if any
+    UnrecognizedBuildJson {
+        nix_version: Option<NixVersion>,
+        reason: String,
+    },
+    /// None of the licenses of `package` is allowed by the [`LicensePolicy`]
+    LicenseDenied {
+        package: String,
+        licenses: Vec<String>,
+    },
+    /// None of `flake.nix`, `default.nix` and `shell.nix` was found
+    NoTargetFound,
+    /// The program was not found in any of the searched paths
+    MissingBinary {
+        name: String,
+        searched: Vec<PathBuf>,
+    },
+    /// The output path doesn't exist
+    OutputMissing(PathBuf),
+    /// The path is not shaped like a store path, `/nix/store/<hash>-<name>`
+    InvalidStorePath(PathBuf),
+    /// The required environment variable is not set
+    MissingEnvVar(&'static str),
+    /// An auxiliary program failed
+    CommandFailed {
+        program: String,
+        stderr: String,
+    },
+    /// The nix daemon couldn't be reached through `socket`
+    ///
+    /// `hint` suggests how to fix the installation
+    DaemonUnavailable {
+        socket: PathBuf,
+        hint: String,
+    },
+    /// The nix daemon ignores the nix setting for users missing from `trusted-users`,
+    /// see [`Config::preflight`]
+    RequiresTrustedUser(String),
+    /// Some builds of a [`BuildSet`] failed, with the error of each by label
+    BuildSetFailed(Vec<(String, Error)>),
+    /// None of the strategies of a [`Resolver`] succeeded, with the error of each
+    ResolveFailed(Vec<(Strategy, Error)>),
+    /// The configuration combines settings that can't be used together, see [`Config::validate`]
+    InvalidConfig(String),
+    /// The flake reference is malformed
+    InvalidFlakeRef {
+        flake_ref: String,
+        reason: String,
+    },
+    /// The build was killed with [`BuildHandle::kill`]
+    Cancelled,
+    Io(std::io::Error),
+}
+
+impl Error {
+    /// Returns whether the error is a network failure of a binary cache or fetcher,
+    /// likely to succeed when retried, see [`Config::retries`]
+    ///
+    /// Failing builders and evaluation errors are never transient
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Self::SubstituterUnavailable { .. } => true,
+            Self::SubstitutionError { message } | Self::EvalError { message, .. } => {
+                failure::is_transient(message)
+            }
+            Self::CommandFailed { stderr, .. } => failure::is_transient(stderr),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NixNotAvailable => f.write_str("nix is not available"),
+            Self::EvalError { message, .. } => write!(f, "nix evaluation failed: {message}"),
+            Self::BuildError {
+                drv,
+                log_tail,
+                log_file,
+            } => {
+                match drv {
+                    Some(drv) => write!(f, "builder for {} failed", drv.display())?,
+                    None => f.write_str("nix build failed")?,
+                }
+                write!(f, ":\n{log_tail}")?;
+                if let Some(file) = log_file {
+                    write!(f, "\nfull log in {}", file.display())?;
+                }
+                Ok(())
+            }
+            Self::SubstitutionError { message } => write!(f, "nix substitution failed: {message}"),
+            Self::SubstituterUnavailable { urls } if urls.is_empty() => f.write_str(
+                "binary caches unavailable, enable Config::fallback to build from source",
+            ),
+            Self::SubstituterUnavailable { urls } => write!(
+                f,
+                "binary caches unavailable: {}, enable Config::fallback to build from source",
+                urls.join(", ")
+            ),
+            Self::ImportFromDerivation { drv: Some(drv) } => write!(
+                f,
+                "evaluation imports from the derivation {}, which is not allowed",
+                drv.display()
+            ),
+            Self::ImportFromDerivation { drv: None } => {
+                f.write_str("evaluation imports from a derivation, which is not allowed")
+            }
+            Self::HashMismatch { expected, got } => {
+                write!(f, "hash mismatch: expected {expected}, got {got}")
+            }
+            Self::UnknownOutput => f.write_str("unrecognized nix output"),
+            Self::UnrecognizedBuildJson {
+                nix_version: Some(version),
+                reason,
+            } => write!(
+                f,
+                "unrecognized nix build JSON, nix version {version}: {reason}"
+            ),
+            Self::UnrecognizedBuildJson {
+                nix_version: None,
+                reason,
+            } => write!(
+                f,
+                "unrecognized nix build JSON, unknown nix version: {reason}"
+            ),
+            Self::LicenseDenied { package, licenses } if licenses.is_empty() => {
+                write!(f, "{package} has no known license")
+            }
+            Self::LicenseDenied { package, licenses } => write!(
+                f,
+                "{package} is licensed under {}, which is not allowed",
+                licenses.join(", ")
+            ),
+            Self::NoTargetFound => {
+                f.write_str("none of flake.nix, default.nix and shell.nix was found")
+            }
+            Self::MissingBinary { name, searched } => {
+                write!(f, "program `{name}` not found, searched:")?;
+                for path in searched {
+                    write!(f, " {}", path.display())?;
+                }
+                Ok(())
+            }
+            Self::OutputMissing(path) => write!(f, "output {} doesn't exist", path.display()),
+            Self::InvalidStorePath(path) => write!(f, "{} is not a store path", path.display()),
+            Self::MissingEnvVar(name) => write!(f, "environment variable {name} is not set"),
+            Self::CommandFailed { program, stderr } => {
+                write!(f, "{program} failed: {}", stderr.trim_end())
+            }
+            Self::DaemonUnavailable { socket, hint } => write!(
+                f,
+                "cannot connect to the nix daemon at {}: {hint}",
+                socket.display()
+            ),
+            Self::RequiresTrustedUser(setting) => write!(
+                f,
+                "the nix setting `{setting}` is ignored by the nix daemon for untrusted users, \
+                 add the user to `trusted-users` in nix.conf"
+            ),
+            Self::BuildSetFailed(failures) => {
+                write!(f, "{} builds failed", failures.len())?;
+                for (label, err) in failures {
+                    write!(f, "\n{label}: {err}")?;
+                }
+                Ok(())
+            }
+            Self::ResolveFailed(failures) if failures.is_empty() => {
+                f.write_str("no strategy to resolve the dependency")
+            }
+            Self::ResolveFailed(failures) => {
+                f.write_str("all strategies to resolve the dependency failed")?;
+                for (strategy, err) in failures {
+                    write!(f, "\n{strategy}: {err}")?;
+                }
+                Ok(())
+            }
+            Self::InvalidConfig(reason) => write!(f, "invalid nix build configuration: {reason}"),
+            Self::InvalidFlakeRef { flake_ref, reason } => {
+                write!(f, "invalid flake reference `{flake_ref}`: {reason}")
+            }
+            Self::Cancelled => f.write_str("nix build was cancelled"),
+            Self::Io(err) => write!(f, "I/O error: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
 }
 
 type Result<T> = std::result::Result<T, Error>;
 
 const NIX_BIN_NAME: &str = "nix";
 
+const NIX_STORE_DIR: &str = "/nix/store";
+
 /// Returns the path to the found `nix` program
 ///
 /// Will prioritize the `NIX` environment variable if set
@@ -43,12 +435,74 @@ pub fn is_nix_available() -> Option<PathBuf> {
 ///
 /// # fn main() -> Result<(), nix::Error> {
 /// let derivations = nix::build()?; // will build ./default.nix
-/// let libfoo = derivations[0].output().expect("to have an 'out' derivation");
 ///
-/// println!("cargo:rustc-link-search=native={}", libfoo.display());
-/// println!("cargo:rustc-link-lib=static=foo");
+/// derivations[0]
+///     .emit_link_search()
+///     .emit_link_lib("foo", nix::LinkKind::Static);
 /// # Ok(()) }
 /// ```
 pub fn build() -> Result<Vec<Derivation>> {
     Config::new().build()
 }
+
+/// Builds the outputs of the store derivation `drv_path`, already instantiated
+///
+/// Like for tools evaluating the derivations separately, see [`Installable::drv`]
+///
+/// # Example
+/// ```no_run
+/// # fn main() -> Result<(), nix_build::Error> {
+/// let drv_path = std::fs::read_to_string("openssl.drvpath")?;
+/// let derivations = nix_build::realise(drv_path.trim())?;
+///
+/// derivations[0].emit_link_search();
+/// # Ok(()) }
+/// ```
+pub fn realise(drv_path: impl Into<PathBuf>) -> Result<Vec<Derivation>> {
+    Config::new().target(Installable::drv(drv_path)).build()
+}
+
+/// Builds the target detected in the working directory with default options
+///
+/// Returns which target has been detected along with the resulting derivations.
+/// See [`Config::auto`]
+pub fn build_auto() -> Result<(DetectedTarget, Vec<Derivation>)> {
+    let (config, detected) = Config::auto()?;
+    Ok((detected, config.build()?))
+}
+
+/// Builds, or substitutes, `installable` and returns the path to its program `name`
+///
+/// The result is cached across runs of build scripts, under `$OUT_DIR`.
+/// See [`Derivation::bin`] for the searched directories
+///
+/// # Example
+/// ```no_run
+/// # fn main() -> Result<(), nix_build::Error> {
+/// let protoc = nix_build::tool("nixpkgs#protobuf", "protoc")?;
+///
+/// std::env::set_var("PROTOC", protoc);
+/// # Ok(()) }
+/// ```
+pub fn tool(installable: impl Into<Installable>, name: &str) -> Result<PathBuf> {
+    let derivations = Config::new()
+        .target(installable)
+        .cache(std::env::var_os("OUT_DIR").is_some())
+        .build()?;
+
+    let mut searched = vec![];
+    for derivation in &derivations {
+        match derivation.bin(name) {
+            Ok(path) => return Ok(path),
+            Err(Error::MissingBinary {
+                searched: paths, ..
+            }) => searched.extend(paths),
+            Err(err) => return Err(err),
+        }
+    }
+
+    Err(Error::MissingBinary {
+        name: name.to_owned(),
+        searched,
+    })
+}
@@ -0,0 +1,428 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+use crate::{cargo, Derivation};
+
+/// Outputs probed for framework directories, in order
+const FRAMEWORK_OUTPUTS: &[&str] = &["out", "lib", "dev"];
+
+/// Conventional framework directories, relative to an output
+const FRAMEWORK_DIRS: &[&str] = &["Library/Frameworks", "Frameworks"];
+
+/// Kind of library to link against, as understood by `cargo:rustc-link-lib`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LinkKind {
+    /// Static library (`.a`)
+    Static,
+    /// Dynamic library (`.so`, `.dylib`)
+    Dylib,
+    /// macOS framework (`.framework`)
+    Framework,
+}
+
+impl LinkKind {
+    /// Returns the name of the kind as expected by cargo
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Static => "static",
+            Self::Dylib => "dylib",
+            Self::Framework => "framework",
+        }
+    }
+}
+
+impl Derivation {
+    /// Emit `cargo:rustc-link-search=native=` for the `lib` directories of this derivation
+    ///
+    /// All of the `lib`, `out` and `dev` outputs are considered
+    ///
+    /// # Example
+    /// ```no_run
+    /// # fn main() -> Result<(), nix_build::Error> {
+    /// use nix_build::LinkKind;
+    ///
+    /// let derivations = nix_build::build()?;
+    /// derivations[0]
+    ///     .emit_link_search()
+    ///     .emit_link_lib("foo", LinkKind::Static);
+    /// # Ok(()) }
+    /// ```
+    pub fn emit_link_search(&self) -> &Self {
+        for dir in self.find_dirs(&["lib", "out", "dev"], "lib") {
+            cargo::rustc_link_search("native", &dir);
+        }
+        self
+    }
+
+    /// Emit `cargo:rustc-link-lib=` for the library `name` with the given `kind`
+    ///
+    /// `name` should not include the `lib` prefix nor the extension
+    pub fn emit_link_lib(&self, name: &str, kind: LinkKind) -> &Self {
+        cargo::rustc_link_lib(kind, name);
+        self
+    }
+
+    /// Returns all the framework directories of this derivation
+    ///
+    /// Probes `Library/Frameworks` and `Frameworks` in the `out`, `lib` and `dev` outputs
+    pub fn framework_dirs(&self) -> Vec<PathBuf> {
+        FRAMEWORK_DIRS
+            .iter()
+            .flat_map(|subdir| self.find_dirs(FRAMEWORK_OUTPUTS, subdir))
+            .collect()
+    }
+
+    /// Returns the frameworks found in the framework directories of this derivation
+    ///
+    /// Frameworks are named without the `.framework` extension, like `Foo` for `Foo.framework`
+    pub fn frameworks(&self) -> Vec<Library> {
+        let mut frameworks: Vec<_> = self
+            .framework_dirs()
+            .into_iter()
+            .filter_map(|dir| std::fs::read_dir(dir).ok())
+            .flatten()
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter_map(|path| {
+                let name = path.file_name()?.to_str()?.strip_suffix(".framework")?;
+
+                Some(Library {
+                    name: name.to_owned(),
+                    kind: LinkKind::Framework,
+                    path,
+                })
+            })
+            .collect();
+
+        frameworks.sort_by(|a, b| a.name.cmp(&b.name));
+        frameworks.dedup_by(|a, b| a.name == b.name);
+        frameworks
+    }
+
+    /// Emit `cargo:rustc-link-search=framework=` for the framework directories of this derivation
+    pub fn emit_framework_search(&self) -> &Self {
+        for dir in self.framework_dirs() {
+            cargo::rustc_link_search("framework", &dir);
+        }
+        self
+    }
+
+    /// Emit `cargo:rustc-link-lib=framework=` for the framework `name`
+    ///
+    /// `name` should not include the `.framework` extension
+    pub fn emit_link_framework(&self, name: &str) -> &Self {
+        cargo::rustc_link_lib(LinkKind::Framework, name);
+        self
+    }
+
+    /// Emit `cargo:rustc-link-arg=-Wl,-rpath,` for the `lib` directories of this derivation
+    ///
+    /// Store paths are not part of the default loader search path,
+    /// so binaries linking against dynamic libraries of this derivation
+    /// would otherwise fail to start.
+    ///
+    /// When targeting macOS or iOS the framework directories are included too.
+    /// The `-Wl,-rpath,` form is understood by both GNU/LLVM linkers and Apple's `ld64`
+    pub fn emit_rpath(&self) -> &Self {
+        let apple = matches!(cargo::target_os().as_deref(), Some("macos" | "ios"));
+
+        let frameworks = if apple { self.framework_dirs() } else { vec![] };
+
+        for dir in self
+            .find_dirs(&["lib", "out", "dev"], "lib")
+            .chain(frameworks)
+        {
+            cargo::rustc_link_arg(format_args!("-Wl,-rpath,{}", dir.display()));
+        }
+        self
+    }
+
+    /// Emit `links` metadata for downstream build scripts
+    ///
+    /// Prints `cargo:root=`, `cargo:include=`, `cargo:lib=`, `cargo:bin=`
+    /// and `cargo:pkgconfig=` for the directories found in this derivation,
+    /// which downstream crates receive as `DEP_<LINKS>_ROOT`, `DEP_<LINKS>_INCLUDE` and so on.
+    ///
+    /// Each key is prefixed with `<prefix>_` if `prefix` is given
+    ///
+    /// # Example
+    /// ```no_run
+    /// # fn main() -> Result<(), nix_build::Error> {
+    /// let derivations = nix_build::build()?;
+    ///
+    /// // downstream receives `DEP_FOO_ROOT`, `DEP_FOO_INCLUDE`...
+    /// derivations[0].emit_metadata(None);
+    /// # Ok(()) }
+    /// ```
+    pub fn emit_metadata(&self, prefix: Option<&str>) -> &Self {
+        let key = |name: &str| match prefix {
+            Some(prefix) => format!("{prefix}_{name}"),
+            None => name.to_owned(),
+        };
+
+        let root = self.out().or_else(|| self.outputs.values().next());
+
+        let entries = [
+            ("root", root.map(|path| path.to_path_buf())),
+            ("include", self.include_dir()),
+            ("lib", self.lib_dir()),
+            ("bin", self.bin_dir()),
+            ("pkgconfig", self.pkg_config_dir()),
+        ];
+
+        for (name, path) in entries {
+            if let Some(path) = path {
+                cargo::directive(&key(name), path.display());
+            }
+        }
+        self
+    }
+
+    /// Discover and link every library and framework found in this derivation
+    ///
+    /// Shorthand for [`Linker::link`] with the default settings
+    pub fn link_all(&self) -> Vec<Library> {
+        Linker::new(self).link()
+    }
+}
+
+/// Library discovered in a derivation output
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Library {
+    /// Name of the library, without `lib` prefix and extension
+    pub name: String,
+    /// Kind of the library
+    pub kind: LinkKind,
+    /// Path to the library file
+    pub path: PathBuf,
+}
+
+impl Library {
+    /// Parse a library file name, like `libfoo.a` or `libfoo.so`
+    ///
+    /// Versioned shared objects like `libfoo.so.1`, and versioned dylibs like `libfoo.1.dylib`
+    /// next to a `libfoo.dylib`, are ignored, as the unversioned symlink is linked instead
+    fn from_path(path: PathBuf) -> Option<Self> {
+        let file_name = path.file_name()?.to_str()?;
+        let stem = file_name.strip_prefix("lib")?;
+
+        let (name, kind) = if let Some(name) = stem.strip_suffix(".a") {
+            (name, LinkKind::Static)
+        } else if let Some(name) = stem
+            .strip_suffix(".so")
+            .or_else(|| stem.strip_suffix(".dylib"))
+        {
+            (name, LinkKind::Dylib)
+        } else {
+            return None;
+        };
+
+        if name.is_empty() {
+            return None;
+        }
+
+        if kind == LinkKind::Dylib && file_name.ends_with(".dylib") {
+            // like `libz.1.3.1.dylib`, only known to be versioned if `libz.dylib` exists,
+            // as names like `libpython3.11.dylib` end with a version too
+            let mut unversioned = name;
+            while let Some((rest, version)) = unversioned.rsplit_once('.') {
+                if version.is_empty() || !version.bytes().all(|b| b.is_ascii_digit()) {
+                    break;
+                }
+                unversioned = rest;
+            }
+
+            if unversioned != name
+                && path
+                    .with_file_name(format!("lib{unversioned}.dylib"))
+                    .exists()
+            {
+                return None;
+            }
+        }
+
+        Some(Self {
+            name: name.to_owned(),
+            kind,
+            path,
+        })
+    }
+}
+
+/// Discovers the libraries of a [`Derivation`] and emits the appropriate link directives
+///
+/// # Example
+/// ```no_run
+/// # fn main() -> Result<(), nix_build::Error> {
+/// use nix_build::Linker;
+///
+/// let derivations = nix_build::build()?;
+/// Linker::new(&derivations[0])
+///     .deny(&["foo_test"])
+///     .prefer_static(true)
+///     .link();
+/// # Ok(()) }
+/// ```
+pub struct Linker<'a> {
+    derivation: &'a Derivation,
+    allow: Option<Vec<String>>,
+    deny: Vec<String>,
+    prefer_static: bool,
+}
+
+impl<'a> Linker<'a> {
+    /// Create a new [`Linker`] for the given derivation
+    ///
+    /// By default all libraries are linked, preferring dynamic libraries
+    /// when both kinds are available
+    pub fn new(derivation: &'a Derivation) -> Self {
+        Self {
+            derivation,
+            allow: None,
+            deny: vec![],
+            prefer_static: false,
+        }
+    }
+
+    /// Only link libraries with the given names
+    ///
+    /// Can be called multiple times to extend the list
+    pub fn allow(&mut self, names: &[&str]) -> &mut Self {
+        self.allow
+            .get_or_insert_with(Vec::new)
+            .extend(names.iter().map(|name| (*name).to_owned()));
+        self
+    }
+
+    /// Never link libraries with the given names
+    ///
+    /// Takes precedence over [`Linker::allow`]
+    pub fn deny(&mut self, names: &[&str]) -> &mut Self {
+        self.deny
+            .extend(names.iter().map(|name| (*name).to_owned()));
+        self
+    }
+
+    /// Set to prefer static libraries when both kinds are available
+    pub fn prefer_static(&mut self, prefer_static: bool) -> &mut Self {
+        self.prefer_static = prefer_static;
+        self
+    }
+
+    fn is_wanted(&self, name: &str) -> bool {
+        let allowed = self
+            .allow
+            .as_ref()
+            .map(|allow| allow.iter().any(|allowed| allowed == name))
+            .unwrap_or(true);
+
+        allowed && !self.deny.iter().any(|denied| denied == name)
+    }
+
+    /// Returns the libraries that would be linked, sorted by name
+    ///
+    /// Frameworks are included after the libraries
+    pub fn libraries(&self) -> Vec<Library> {
+        let mut found: BTreeMap<String, Library> = BTreeMap::new();
+
+        let files = self
+            .derivation
+            .find_dirs(&["lib", "out", "dev"], "lib")
+            .filter_map(|dir| std::fs::read_dir(dir).ok())
+            .flatten()
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path());
+
+        for lib in files.filter_map(Library::from_path) {
+            if !self.is_wanted(&lib.name) {
+                continue;
+            }
+
+            let preferred = if self.prefer_static {
+                LinkKind::Static
+            } else {
+                LinkKind::Dylib
+            };
+
+            match found.get(&lib.name) {
+                Some(existing) if existing.kind == preferred || lib.kind != preferred => {}
+                _ => {
+                    found.insert(lib.name.clone(), lib);
+                }
+            }
+        }
+
+        let frameworks = self
+            .derivation
+            .frameworks()
+            .into_iter()
+            .filter(|framework| self.is_wanted(&framework.name));
+
+        found.into_values().chain(frameworks).collect()
+    }
+
+    /// Emit the link directives for the discovered libraries
+    ///
+    /// Returns the libraries that have been linked
+    pub fn link(&self) -> Vec<Library> {
+        let libraries = self.libraries();
+
+        if libraries.iter().any(|lib| lib.kind != LinkKind::Framework) {
+            self.derivation.emit_link_search();
+        }
+
+        if libraries.iter().any(|lib| lib.kind == LinkKind::Framework) {
+            self.derivation.emit_framework_search();
+        }
+
+        for lib in &libraries {
+            cargo::rustc_link_lib(lib.kind, &lib.name);
+        }
+
+        libraries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn versioned_dylibs_are_skipped() {
+        let dir = std::env::temp_dir().join(format!(
+            "nix-build-link-versioned-dylibs-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in [
+            "libz.dylib",
+            "libz.1.dylib",
+            "libz.1.3.1.dylib",
+            "libpython3.11.dylib",
+        ] {
+            std::fs::write(dir.join(name), "").unwrap();
+        }
+
+        let names: Vec<_> = [
+            "libz.dylib",
+            "libz.1.dylib",
+            "libz.1.3.1.dylib",
+            "libpython3.11.dylib",
+            "libz.so.1",
+        ]
+        .into_iter()
+        .map(|file| Library::from_path(dir.join(file)).map(|lib| lib.name))
+        .collect();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            names,
+            [
+                Some("z".to_owned()),
+                None,
+                None,
+                Some("python3.11".to_owned()),
+                None
+            ]
+        );
+    }
+}
@@ -0,0 +1,43 @@
+//! Diagnostics forwarded to `log` and/or `tracing`, when the respective features are enabled
+
+macro_rules! event {
+    ($level:ident, $($arg:tt)+) => {{
+        #[cfg(feature = "log")]
+        ::log::$level!(target: "nix_build", $($arg)+);
+        #[cfg(feature = "tracing")]
+        ::tracing::$level!(target: "nix_build", $($arg)+);
+        #[cfg(not(any(feature = "log", feature = "tracing")))]
+        let _ = format_args!($($arg)+);
+    }};
+}
+
+macro_rules! error {
+    ($($arg:tt)+) => { event!(error, $($arg)+) };
+}
+
+macro_rules! warn {
+    ($($arg:tt)+) => { event!(warn, $($arg)+) };
+}
+
+macro_rules! info {
+    ($($arg:tt)+) => { event!(info, $($arg)+) };
+}
+
+macro_rules! debug {
+    ($($arg:tt)+) => { event!(debug, $($arg)+) };
+}
+
+macro_rules! trace {
+    ($($arg:tt)+) => { event!(trace, $($arg)+) };
+}
+
+/// Forward a line of nix output, with a level depending on its prefix
+pub(crate) fn nix_line(line: &str) {
+    if let Some(message) = line.strip_prefix("error: ") {
+        error!("nix: {message}");
+    } else if let Some(message) = line.strip_prefix("warning: ") {
+        warn!("nix: {message}");
+    } else {
+        trace!("nix: {line}");
+    }
+}
@@ -0,0 +1,105 @@
+//! Configuration read from the `[package.metadata.nix]` table of the crate being built
+
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use serde::Deserialize;
+
+use crate::{cargo, command, Config, ConfigSpec, Error, Result};
+
+/// Package reported by `cargo metadata`
+#[derive(Deserialize)]
+struct Package {
+    name: String,
+    #[serde(default)]
+    metadata: Option<PackageMetadata>,
+}
+
+/// The `[package.metadata]` table
+#[derive(Deserialize)]
+struct PackageMetadata {
+    nix: Option<serde_json::Value>,
+}
+
+/// Output of `cargo metadata`
+#[derive(Deserialize)]
+struct Metadata {
+    packages: Vec<Package>,
+}
+
+/// Returns the `[package.metadata.nix]` table of the package `name` in `manifest`, if any
+fn read(manifest: &Path, name: &str) -> Result<Option<ConfigSpec>> {
+    let cargo = std::env::var_os("CARGO").ok_or(Error::MissingEnvVar("CARGO"))?;
+    let stdout = command::run(
+        Command::new(cargo)
+            .args([
+                "metadata",
+                "--format-version",
+                "1",
+                "--no-deps",
+                "--offline",
+            ])
+            .arg("--manifest-path")
+            .arg(manifest),
+    )?;
+    let metadata: Metadata = serde_json::from_slice(&stdout).map_err(|_| Error::UnknownOutput)?;
+
+    let table = metadata
+        .packages
+        .into_iter()
+        .find(|package| package.name == name)
+        .and_then(|package| package.metadata?.nix);
+
+    table
+        .map(|table| {
+            serde_json::from_value(table).map_err(|err| {
+                Error::InvalidConfig(format!("invalid [package.metadata.nix] table: {err}"))
+            })
+        })
+        .transpose()
+}
+
+impl Config {
+    /// Create a new nix build [`Config`] from the `[package.metadata.nix]` table
+    /// of the crate being built
+    ///
+    /// Must be called from a build script. The table is a [`ConfigSpec`], with relative paths
+    /// resolved from the crate's directory. The default configuration is returned if the table
+    /// is missing. Fails with [`Error::InvalidConfig`] if the table can't be parsed
+    ///
+    /// ```toml
+    /// [package.metadata.nix]
+    /// flake = "nixpkgs#openssl" # or `file = "openssl.nix"`, or `expr = "..."`
+    /// attribute = "dev"         # for file and expression targets
+    /// args = { pkgs = "import <nixpkgs> {}" }
+    /// argstrs = { name = "openssl" }
+    /// impure = false
+    /// system = "x86_64-linux"
+    /// out-link = "result"
+    /// ```
+    ///
+    /// # Example
+    /// ```no_run
+    /// # fn main() -> Result<(), nix_build::Error> {
+    /// let derivations = nix_build::Config::from_cargo_metadata()?.build()?;
+    /// # Ok(()) }
+    /// ```
+    pub fn from_cargo_metadata() -> Result<Self> {
+        let dir = PathBuf::from(
+            std::env::var_os("CARGO_MANIFEST_DIR")
+                .ok_or(Error::MissingEnvVar("CARGO_MANIFEST_DIR"))?,
+        );
+        let name =
+            std::env::var("CARGO_PKG_NAME").map_err(|_| Error::MissingEnvVar("CARGO_PKG_NAME"))?;
+        let manifest = dir.join("Cargo.toml");
+
+        if cargo::is_build_script() {
+            cargo::rerun_if_changed(&manifest);
+        }
+
+        let spec = read(&manifest, &name)?.unwrap_or_default();
+        Self::from_spec(&spec.relative_to(&dir))
+    }
+}
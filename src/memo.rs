@@ -0,0 +1,49 @@
+//! Deduplication of identical builds within the process
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use crate::{fingerprint::Fingerprint, BuildReport, Derivation, Result};
+
+type Slot = Arc<Mutex<Option<Vec<Derivation>>>>;
+
+fn builds() -> &'static Mutex<HashMap<Fingerprint, Slot>> {
+    static BUILDS: OnceLock<Mutex<HashMap<Fingerprint, Slot>>> = OnceLock::new();
+    BUILDS.get_or_init(Default::default)
+}
+
+/// Run `build` once for the configurations with the same fingerprint `key`,
+/// sharing its derivations afterwards
+///
+/// Concurrent builds of the same key wait for the first one. Failed builds are not
+/// remembered, nor the builds whose outputs were garbage collected since
+pub(crate) fn memoized(
+    key: Fingerprint,
+    build: impl FnOnce() -> Result<(Vec<Derivation>, BuildReport)>,
+) -> Result<(Vec<Derivation>, BuildReport)> {
+    let slot = builds()
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .entry(key)
+        .or_default()
+        .clone();
+    let mut slot = slot.lock().unwrap_or_else(|err| err.into_inner());
+
+    if let Some(derivations) = slot
+        .as_ref()
+        .filter(|derivations| derivations.iter().all(|drv| drv.missing_output().is_none()))
+    {
+        debug!("reusing the derivations built earlier in this process");
+        let report = BuildReport {
+            cached: true,
+            ..Default::default()
+        };
+        return Ok((derivations.clone(), report));
+    }
+
+    let (derivations, report) = build()?;
+    *slot = Some(derivations.clone());
+    Ok((derivations, report))
+}
@@ -0,0 +1,224 @@
+//! Package metadata evaluated from `meta`, and license policies
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Mutex, OnceLock},
+};
+
+use crate::{command, Config, Derivation, Error, Installable, Result};
+
+/// Nix function normalizing the `meta` attribute of a derivation
+const META_EXPR: &str = r#"drv:
+let
+  meta = drv.meta or { };
+  first = value: if builtins.isList value then (if value == [ ] then null else builtins.head value) else value;
+  license = value:
+    if builtins.isAttrs value then {
+      spdxId = value.spdxId or null;
+      shortName = value.shortName or null;
+      fullName = value.fullName or null;
+      free = value.free or null;
+    } else {
+      spdxId = null;
+      shortName = value;
+      fullName = null;
+      free = null;
+    };
+  licenses = meta.license or [ ];
+in {
+  pname = drv.pname or null;
+  version = drv.version or null;
+  description = meta.description or null;
+  homepage = first (meta.homepage or null);
+  licenses = map license (if builtins.isList licenses then licenses else [ licenses ]);
+}"#;
+
+/// License of a package, from `meta.license`
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct License {
+    /// SPDX identifier, like `MIT`
+    pub spdx_id: Option<String>,
+    /// Short name used by nixpkgs, like `mit`
+    pub short_name: Option<String>,
+    /// Full name, like `MIT License`
+    pub full_name: Option<String>,
+    /// Whether it's a free software license
+    pub free: Option<bool>,
+}
+
+impl License {
+    /// Returns the SPDX identifier, or the short name if it has none
+    pub fn id(&self) -> Option<&str> {
+        self.spdx_id.as_deref().or(self.short_name.as_deref())
+    }
+}
+
+/// Metadata of a package, from its `meta` attribute
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
+pub struct Meta {
+    /// Package name, like `openssl`
+    pub pname: Option<String>,
+    /// Package version, like `3.2.1`
+    pub version: Option<String>,
+    /// Revision of the flake providing the package, like the nixpkgs commit
+    #[serde(default)]
+    pub revision: Option<String>,
+    pub description: Option<String>,
+    pub homepage: Option<String>,
+    pub licenses: Vec<License>,
+}
+
+/// Configurations that built each derivation in this process, to evaluate their metadata
+fn origins() -> &'static Mutex<HashMap<PathBuf, Config>> {
+    static ORIGINS: OnceLock<Mutex<HashMap<PathBuf, Config>>> = OnceLock::new();
+    ORIGINS.get_or_init(Default::default)
+}
+
+/// Remember `config` built the given derivations
+pub(crate) fn record_origin(config: &Config, derivations: &[Derivation]) {
+    let mut origins = origins().lock().unwrap_or_else(|err| err.into_inner());
+    for derivation in derivations {
+        origins.insert(derivation.drv_path.to_path_buf(), config.clone());
+    }
+}
+
+/// Returns the configuration that built the derivation at `drv_path` in this process, if any
+pub(crate) fn origin(drv_path: &Path) -> Option<Config> {
+    origins()
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .get(drv_path)
+        .cloned()
+}
+
+impl Config {
+    /// Evaluate the `meta` attribute of the target
+    ///
+    /// # Example
+    /// ```no_run
+    /// # fn main() -> Result<(), nix_build::Error> {
+    /// let meta = nix_build::Config::new()
+    ///     .target_flake("nixpkgs#openssl")
+    ///     .meta()?;
+    ///
+    /// for license in &meta.licenses {
+    ///     println!("cargo:warning=openssl is licensed under {:?}", license.id());
+    /// }
+    /// # Ok(()) }
+    /// ```
+    pub fn meta(&self) -> Result<Meta> {
+        let mut meta: Meta = self.eval_json(META_EXPR)?;
+
+        if let Installable::Flake { flake_ref, .. } = &self.target {
+            meta.revision = flake_revision(&flake_ref.without_attr().to_string());
+        }
+
+        Ok(meta)
+    }
+}
+
+/// Returns the locked revision of `flake`, if any
+pub(crate) fn flake_revision(flake: &str) -> Option<String> {
+    #[derive(serde::Deserialize)]
+    struct Metadata {
+        revision: Option<String>,
+    }
+
+    command::nix_json::<Metadata, _, _>(["flake", "metadata", "--json", flake])
+        .ok()?
+        .revision
+}
+
+/// Evaluate the metadata of the package at `installable`, like the flake output `nixpkgs#openssl`
+///
+/// # Example
+/// ```no_run
+/// # fn main() -> Result<(), nix_build::Error> {
+/// let meta = nix_build::meta_for("nixpkgs#openssl")?;
+///
+/// println!(
+///     "cargo:rustc-env=OPENSSL_BUILT_AGAINST=openssl {} from nixpkgs rev {}",
+///     meta.version.unwrap_or_default(),
+///     meta.revision.unwrap_or_default(),
+/// );
+/// # Ok(()) }
+/// ```
+pub fn meta_for(installable: impl Into<Installable>) -> Result<Meta> {
+    Config::new().target(installable).meta()
+}
+
+impl Derivation {
+    /// Evaluate the `meta` attribute of the attribute this derivation was built from
+    ///
+    /// Only available for derivations built by a [`Config`] in the current process,
+    /// fails with [`Error::UnknownOutput`] otherwise
+    pub fn meta(&self) -> Result<Meta> {
+        let config = origin(&self.drv_path).ok_or(Error::UnknownOutput)?;
+
+        config.meta()
+    }
+}
+
+/// Set of licenses allowed to be built
+///
+/// # Example
+/// ```no_run
+/// # use nix_build::{Config, LicensePolicy};
+/// let mut policy = LicensePolicy::new();
+/// policy.allow("MIT").allow("Apache-2.0").allow("BSD-3-Clause");
+///
+/// // fails with `Error::LicenseDenied` if openssl isn't licensed under any of the above
+/// let derivations = Config::new()
+///     .target_flake("nixpkgs#openssl")
+///     .license_policy(policy)
+///     .build();
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct LicensePolicy {
+    allowed: Vec<String>,
+    allow_unknown: bool,
+}
+
+impl LicensePolicy {
+    /// Create an empty [`LicensePolicy`], denying every license
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allow the license with the given SPDX identifier, or nixpkgs short name
+    pub fn allow(&mut self, license: impl Into<String>) -> &mut Self {
+        self.allowed.push(license.into());
+        self
+    }
+
+    /// Set to allow packages without any license in their metadata
+    pub fn allow_unknown(&mut self, allow: bool) -> &mut Self {
+        self.allow_unknown = allow;
+        self
+    }
+
+    /// Check the licenses of the package `name`
+    ///
+    /// Packages with multiple licenses are allowed if any of them is.
+    /// Fails with [`Error::LicenseDenied`] otherwise
+    pub fn check(&self, name: &str, meta: &Meta) -> Result<()> {
+        let allowed = |license: &License| {
+            [&license.spdx_id, &license.short_name]
+                .into_iter()
+                .flatten()
+                .any(|id| self.allowed.iter().any(|allowed| allowed == id))
+        };
+
+        if meta.licenses.is_empty() && self.allow_unknown || meta.licenses.iter().any(allowed) {
+            return Ok(());
+        }
+
+        let licenses: Vec<&str> = meta.licenses.iter().filter_map(License::id).collect();
+        Err(Error::LicenseDenied {
+            package: name.to_owned(),
+            licenses: licenses.into_iter().map(str::to_owned).collect(),
+        })
+    }
+}
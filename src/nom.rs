@@ -0,0 +1,53 @@
+//! Rendering of builds with [nix-output-monitor](https://github.com/maralorn/nix-output-monitor)
+
+use std::{
+    io::Write,
+    process::{Child, ChildStdin, Command, Stdio},
+};
+
+/// Running `nom --json`, fed with the structured log of nix
+pub(crate) struct Monitor {
+    child: Child,
+    stdin: Option<ChildStdin>,
+}
+
+impl Monitor {
+    /// Spawn `nom --json` rendering on stderr, if it's found in `PATH`
+    pub(crate) fn spawn() -> Option<Self> {
+        let nom = which::which("nom").ok()?;
+
+        let mut child = Command::new(nom)
+            .arg("--json")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .spawn()
+            .inspect_err(|err| debug!("couldn't spawn nom: {err}"))
+            .ok()?;
+        let stdin = child.stdin.take();
+
+        Some(Self { child, stdin })
+    }
+
+    /// Forward a line of the structured log
+    ///
+    /// Stops forwarding if `nom` exits early
+    pub(crate) fn line(&mut self, line: &[u8]) {
+        let Some(stdin) = &mut self.stdin else {
+            return;
+        };
+
+        if stdin
+            .write_all(line)
+            .and_then(|_| stdin.write_all(b"\n"))
+            .is_err()
+        {
+            self.stdin = None;
+        }
+    }
+
+    /// Wait for `nom` to render the end of the log
+    pub(crate) fn finish(mut self) {
+        drop(self.stdin.take());
+        let _ = self.child.wait();
+    }
+}
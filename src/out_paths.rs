@@ -0,0 +1,152 @@
+//! Reconstruction of [`Derivation`]s from plain output paths
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    store::{self, PathInfo, StorePath},
+    Derivation, Error, Result,
+};
+
+/// Format used to retrieve the results of `nix build`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Use `--json`, falling back to `--print-out-paths` when the JSON can't be understood
+    /// and the detected nix version supports it
+    #[default]
+    Auto,
+    /// Always use `--json`
+    Json,
+    /// Always use `--print-out-paths`, querying the derivers with `nix path-info`
+    PrintOutPaths,
+}
+
+impl OutputFormat {
+    pub(crate) fn flag(&self) -> &'static str {
+        match self {
+            Self::Auto | Self::Json => "--json",
+            Self::PrintOutPaths => "--print-out-paths",
+        }
+    }
+}
+
+/// Store path name, without the hash
+pub(crate) fn store_name(path: &Path) -> Option<&str> {
+    let (_, name) = path.file_name()?.to_str()?.split_once('-')?;
+    Some(name)
+}
+
+/// Infer the output name of `path` built from derivation `drv`
+///
+/// Outputs other than `out` are named `<name>-<output>`
+fn output_name(drv: &Path, path: &Path) -> String {
+    let name = store_name(drv).map(|name| name.trim_end_matches(".drv"));
+
+    match (name, store_name(path)) {
+        (Some(name), Some(path_name)) if path_name != name => path_name
+            .strip_prefix(name)
+            .and_then(|output| output.strip_prefix('-'))
+            .unwrap_or("out")
+            .to_owned(),
+        _ => "out".to_owned(),
+    }
+}
+
+/// Reconstruct the derivations of the output paths printed by `nix build --print-out-paths`
+pub(crate) fn derivations(stdout: &[u8]) -> Result<Vec<Derivation>> {
+    let paths: Vec<PathBuf> = String::from_utf8_lossy(stdout)
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(PathBuf::from)
+        .collect();
+
+    from_path_infos(store::path_info::query(&paths, false, false)?)
+}
+
+/// Group the output paths described by `infos` by the derivation that built them, in order
+fn from_path_infos(infos: Vec<PathInfo>) -> Result<Vec<Derivation>> {
+    let mut derivations: Vec<Derivation> = vec![];
+    for info in infos {
+        let path = StorePath::new(info.path)?;
+        // paths substituted without their deriver can't be attributed
+        let drv_path = StorePath::new(info.deriver.ok_or(Error::UnknownOutput)?)?;
+        let output = output_name(&drv_path, &path);
+
+        match derivations.iter_mut().find(|drv| drv.drv_path == drv_path) {
+            Some(derivation) => {
+                derivation.outputs.insert(output, path);
+            }
+            None => derivations.push(Derivation {
+                drv_path,
+                outputs: HashMap::from([(output, path)]),
+                realisations: HashMap::new(),
+            }),
+        }
+    }
+
+    Ok(derivations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Output of `nix path-info --json` for the paths printed by `nix build --print-out-paths`
+    fn path_infos(json: &str) -> Vec<PathInfo> {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn multiple_outputs() {
+        let infos = path_infos(
+            r#"[
+                {"path":"/nix/store/0c8ybjvkn0jxx2xy7yv5c3d7zg2g3yy2-openssl-3.0.13-bin","deriver":"/nix/store/vkb3h6vb2nhcbmmp7zs1w2n0x1i8sv3l-openssl-3.0.13.drv","narSize":1029120,"references":[],"valid":true},
+                {"path":"/nix/store/2l6ww0f0lb4kxs3bqhdzpljzjxvhc6kb-openssl-3.0.13-dev","deriver":"/nix/store/vkb3h6vb2nhcbmmp7zs1w2n0x1i8sv3l-openssl-3.0.13.drv","narSize":2519512,"references":[],"valid":true},
+                {"path":"/nix/store/9w0bd6yv5m0gxvwryjfxvjvr6ch0bqsm-openssl-3.0.13","deriver":"/nix/store/vkb3h6vb2nhcbmmp7zs1w2n0x1i8sv3l-openssl-3.0.13.drv","narSize":6502464,"references":[],"valid":true},
+                {"path":"/nix/store/3y6hzxhfmxp2l8sd3b1n4smr5vcx4mdv-zlib-1.3.1","deriver":"/nix/store/5xf4fi1l4qq7kv7b3kpyx4ha1qbg0lmb-zlib-1.3.1.drv","narSize":143512,"references":[],"valid":true}
+            ]"#,
+        );
+
+        let derivations = from_path_infos(infos).unwrap();
+        assert_eq!(derivations.len(), 2);
+
+        let openssl = &derivations[0];
+        let mut outputs: Vec<&str> = openssl.outputs.keys().map(String::as_str).collect();
+        outputs.sort_unstable();
+        assert_eq!(outputs, ["bin", "dev", "out"]);
+        assert_eq!(openssl.outputs["dev"].name(), "openssl-3.0.13-dev");
+        assert_eq!(openssl.outputs["out"].name(), "openssl-3.0.13");
+
+        assert_eq!(derivations[1].outputs["out"].name(), "zlib-1.3.1");
+    }
+
+    #[test]
+    fn names_ending_like_outputs() {
+        let drv = Path::new("/nix/store/vkb3h6vb2nhcbmmp7zs1w2n0x1i8sv3l-gcc-lib.drv");
+        let path = |name: &str| {
+            PathBuf::from(format!(
+                "/nix/store/0c8ybjvkn0jxx2xy7yv5c3d7zg2g3yy2-{name}"
+            ))
+        };
+        assert_eq!(output_name(drv, &path("gcc-lib")), "out");
+        assert_eq!(output_name(drv, &path("gcc-lib-dev")), "dev");
+
+        let drv = Path::new("/nix/store/vkb3h6vb2nhcbmmp7zs1w2n0x1i8sv3l-libfoo-dev-1.0.drv");
+        assert_eq!(output_name(drv, &path("libfoo-dev-1.0")), "out");
+        assert_eq!(output_name(drv, &path("libfoo-dev-1.0-lib")), "lib");
+
+        // paths not named after their derivation, like fetched sources
+        let drv = Path::new("/nix/store/vkb3h6vb2nhcbmmp7zs1w2n0x1i8sv3l-foo-src.drv");
+        assert_eq!(output_name(drv, &path("source")), "out");
+    }
+
+    #[test]
+    fn unknown_deriver() {
+        let infos = path_infos(
+            r#"[{"path":"/nix/store/3y6hzxhfmxp2l8sd3b1n4smr5vcx4mdv-zlib-1.3.1","deriver":null,"narSize":143512,"references":[],"valid":true}]"#,
+        );
+        assert!(matches!(from_path_infos(infos), Err(Error::UnknownOutput)));
+    }
+}
@@ -0,0 +1,170 @@
+use std::{
+    ffi::{OsStr, OsString},
+    path::PathBuf,
+    process::Command,
+};
+
+use crate::{Derivation, Error, Result};
+
+const PKG_CONFIG_BIN_NAME: &str = "pkg-config";
+
+/// Collects the `pkg-config` directories of built derivations
+///
+/// # Example
+/// ```no_run
+/// # fn main() -> Result<(), nix_build::Error> {
+/// use nix_build::PkgConfig;
+///
+/// let derivations = nix_build::build()?;
+/// let openssl = PkgConfig::new().derivations(&derivations).probe("openssl")?;
+///
+/// for dir in &openssl.link_paths {
+///     println!("cargo:rustc-link-search=native={}", dir.display());
+/// }
+/// # Ok(()) }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct PkgConfig {
+    dirs: Vec<PathBuf>,
+}
+
+/// Result of a `pkg-config` module lookup
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PkgConfigLibrary {
+    /// Libraries to link (`-l`)
+    pub libs: Vec<String>,
+    /// Library search paths (`-L`)
+    pub link_paths: Vec<PathBuf>,
+    /// Include paths (`-I`)
+    pub include_paths: Vec<PathBuf>,
+    /// Preprocessor definitions (`-D`), with their optional value
+    pub defines: Vec<(String, Option<String>)>,
+    /// Remaining flags not recognized above
+    pub other_flags: Vec<String>,
+}
+
+impl PkgConfig {
+    /// Create an empty [`PkgConfig`]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the `pkg-config` directory of the given derivation, if any
+    pub fn derivation(&mut self, derivation: &Derivation) -> &mut Self {
+        self.dirs.extend(derivation.pkg_config_dir());
+        self
+    }
+
+    /// Add the `pkg-config` directories of all the given derivations
+    pub fn derivations<'a>(
+        &mut self,
+        derivations: impl IntoIterator<Item = &'a Derivation>,
+    ) -> &mut Self {
+        for derivation in derivations {
+            self.derivation(derivation);
+        }
+        self
+    }
+
+    /// Add an arbitrary directory to the search path
+    pub fn dir(&mut self, dir: impl Into<PathBuf>) -> &mut Self {
+        self.dirs.push(dir.into());
+        self
+    }
+
+    /// Returns the collected directories
+    pub fn dirs(&self) -> &[PathBuf] {
+        &self.dirs
+    }
+
+    /// Returns the value of `PKG_CONFIG_PATH` with the collected directories
+    ///
+    /// The current value of `PKG_CONFIG_PATH` is appended, if set
+    pub fn pkg_config_path(&self) -> OsString {
+        let existing = std::env::var_os("PKG_CONFIG_PATH");
+        let paths = self
+            .dirs
+            .iter()
+            .cloned()
+            .chain(existing.iter().flat_map(std::env::split_paths));
+
+        std::env::join_paths(paths).unwrap_or_default()
+    }
+
+    /// Set `PKG_CONFIG_PATH` on the given command
+    pub fn apply<'c>(&self, cmd: &'c mut Command) -> &'c mut Command {
+        cmd.env("PKG_CONFIG_PATH", self.pkg_config_path())
+    }
+
+    /// Run `f` with `PKG_CONFIG_PATH` set to the collected directories
+    ///
+    /// Useful for crates driving `pkg-config` themselves, like the `pkg-config` crate.
+    /// The previous value of the variable is restored afterwards
+    pub fn with_env<T>(&self, f: impl FnOnce() -> T) -> T {
+        let previous = std::env::var_os("PKG_CONFIG_PATH");
+        std::env::set_var("PKG_CONFIG_PATH", self.pkg_config_path());
+
+        let result = f();
+
+        match previous {
+            Some(previous) => std::env::set_var("PKG_CONFIG_PATH", previous),
+            None => std::env::remove_var("PKG_CONFIG_PATH"),
+        }
+        result
+    }
+
+    /// Returns a `pkg-config` [`Command`] configured with the collected directories
+    ///
+    /// Will prioritize the `PKG_CONFIG` environment variable if set
+    pub fn command(&self) -> Command {
+        let program =
+            std::env::var_os("PKG_CONFIG").unwrap_or_else(|| OsString::from(PKG_CONFIG_BIN_NAME));
+
+        let mut cmd = Command::new(program);
+        self.apply(&mut cmd);
+        cmd
+    }
+
+    fn query(&self, module: &str, flag: &str) -> Result<String> {
+        let output = self
+            .command()
+            .args([OsStr::new(flag), OsStr::new(module)])
+            .output()?;
+
+        if !output.status.success() {
+            return Err(Error::CommandFailed {
+                program: PKG_CONFIG_BIN_NAME.to_owned(),
+                stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+            });
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    /// Resolve the compiler and linker flags of `module` with `pkg-config`
+    pub fn probe(&self, module: &str) -> Result<PkgConfigLibrary> {
+        let mut library = PkgConfigLibrary::default();
+
+        let flags = self.query(module, "--cflags")? + " " + &self.query(module, "--libs")?;
+
+        for flag in flags.split_whitespace() {
+            if let Some(lib) = flag.strip_prefix("-l") {
+                library.libs.push(lib.to_owned());
+            } else if let Some(path) = flag.strip_prefix("-L") {
+                library.link_paths.push(PathBuf::from(path));
+            } else if let Some(path) = flag.strip_prefix("-I") {
+                library.include_paths.push(PathBuf::from(path));
+            } else if let Some(define) = flag.strip_prefix("-D") {
+                let (name, value) = match define.split_once('=') {
+                    Some((name, value)) => (name, Some(value.to_owned())),
+                    None => (define, None),
+                };
+                library.defines.push((name.to_owned(), value));
+            } else {
+                library.other_flags.push(flag.to_owned());
+            }
+        }
+
+        Ok(library)
+    }
+}
@@ -0,0 +1,34 @@
+//! Downloads into the store ahead of evaluation, to learn their hash
+
+use std::path::PathBuf;
+
+use crate::{command, Result};
+
+/// Content downloaded into the store by [`prefetch_url`] or [`prefetch_flake`]
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Prefetched {
+    pub store_path: PathBuf,
+    /// SRI hash of the content, like `sha256-...`
+    pub hash: String,
+}
+
+/// Download the file at `url` into the store, with `nix store prefetch-file`
+///
+/// # Example
+/// ```no_run
+/// # fn main() -> Result<(), nix_build::Error> {
+/// let url = "https://example.com/sdk-1.0.tar.gz";
+/// let sdk = nix_build::prefetch_url(url)?;
+///
+/// let expr = format!(r#"builtins.fetchurl {{ url = "{url}"; sha256 = "{}"; }}"#, sdk.hash);
+/// # Ok(()) }
+/// ```
+pub fn prefetch_url(url: &str) -> Result<Prefetched> {
+    command::nix_json(["store", "prefetch-file", "--json", url])
+}
+
+/// Download the source of the flake `flake_ref` into the store, with `nix flake prefetch`
+pub fn prefetch_flake(flake_ref: &str) -> Result<Prefetched> {
+    command::nix_json(["flake", "prefetch", "--json", flake_ref])
+}
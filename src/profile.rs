@@ -0,0 +1,167 @@
+//! Persistent installs into nix profiles, with `nix profile`
+
+use std::{
+    collections::BTreeMap,
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+use crate::{command, Error, Result};
+
+/// Package installed in a [`Profile`]
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProfileElement {
+    /// Name of the element, used to remove or upgrade it
+    ///
+    /// Older versions of nix identify elements by their index instead
+    #[serde(skip)]
+    pub name: String,
+    #[serde(default = "active_default")]
+    pub active: bool,
+    /// Attribute path the package was installed from, like `legacyPackages.x86_64-linux.hello`
+    pub attr_path: Option<String>,
+    /// Flake reference as given at install time, like `flake:nixpkgs`
+    pub original_url: Option<String>,
+    /// Locked flake reference the package was installed from
+    pub url: Option<String>,
+    #[serde(default)]
+    pub store_paths: Vec<PathBuf>,
+    pub priority: Option<i64>,
+}
+
+fn active_default() -> bool {
+    true
+}
+
+/// Output of `nix profile list --json`
+#[derive(Deserialize)]
+struct Manifest {
+    elements: Elements,
+}
+
+/// Elements are keyed by name since nix 2.20, listed in order before
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Elements {
+    Named(BTreeMap<String, ProfileElement>),
+    Indexed(Vec<ProfileElement>),
+}
+
+/// Nix profile, like `~/.nix-profile`, holding persistently installed packages
+///
+/// # Example
+/// ```no_run
+/// # fn main() -> Result<(), nix_build::Error> {
+/// use nix_build::Profile;
+///
+/// let profile = Profile::new("/opt/dev/profile");
+/// profile.install(["nixpkgs#cmake", "nixpkgs#ninja"])?;
+///
+/// for element in profile.list()? {
+///     println!("{} from {:?}", element.name, element.original_url);
+/// }
+/// # Ok(()) }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Profile {
+    path: PathBuf,
+}
+
+impl Profile {
+    /// Create a new [`Profile`] at the given path
+    ///
+    /// The profile is created by nix on the first install
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Returns the path of the profile
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Run `nix profile <subcommand>` on this profile with the given arguments
+    fn profile<I, S>(&self, subcommand: &str, args: I) -> Result<Vec<u8>>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<OsString>,
+    {
+        let mut all: Vec<OsString> = vec![
+            "profile".into(),
+            subcommand.into(),
+            "--profile".into(),
+            self.path.clone().into(),
+        ];
+        all.extend(args.into_iter().map(Into::into));
+
+        command::nix_stdout(all)
+    }
+
+    /// Install the given flake installables
+    pub fn install<I, S>(&self, installables: I) -> Result<()>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<OsString>,
+    {
+        self.profile("install", installables)?;
+        Ok(())
+    }
+
+    /// Remove the elements with the given names, see [`ProfileElement::name`]
+    pub fn remove<I, S>(&self, names: I) -> Result<()>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<OsString>,
+    {
+        self.profile("remove", names)?;
+        Ok(())
+    }
+
+    /// Upgrade the elements with the given names to the latest version of their flake
+    pub fn upgrade<I, S>(&self, names: I) -> Result<()>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<OsString>,
+    {
+        self.profile("upgrade", names)?;
+        Ok(())
+    }
+
+    /// Upgrade all the elements to the latest version of their flake
+    pub fn upgrade_all(&self) -> Result<()> {
+        self.upgrade(["--all"])
+    }
+
+    /// Returns the elements installed in the profile
+    ///
+    /// A profile that doesn't exist yet is empty
+    pub fn list(&self) -> Result<Vec<ProfileElement>> {
+        if self.path.symlink_metadata().is_err() {
+            return Ok(vec![]);
+        }
+
+        let stdout = self.profile("list", ["--json"])?;
+        let manifest: Manifest =
+            serde_json::from_slice(&stdout).map_err(|_| Error::UnknownOutput)?;
+
+        Ok(match manifest.elements {
+            Elements::Named(elements) => elements
+                .into_iter()
+                .map(|(name, element)| ProfileElement { name, ..element })
+                .collect(),
+            Elements::Indexed(elements) => elements
+                .into_iter()
+                .enumerate()
+                .map(|(index, element)| ProfileElement {
+                    name: index.to_string(),
+                    ..element
+                })
+                .collect(),
+        })
+    }
+}
@@ -0,0 +1,39 @@
+//! Progress of builds rendered with `indicatif`
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use indicatif::{HumanBytes, ProgressBar, ProgressDrawTarget, ProgressStyle};
+
+use crate::{build_log::EventHandler, BuildEvent};
+
+/// Returns an event handler rendering a spinner for the build of `target` on stderr
+pub(crate) fn handler(target: &str) -> EventHandler {
+    let bar = ProgressBar::with_draw_target(None, ProgressDrawTarget::stderr());
+    bar.set_style(
+        ProgressStyle::with_template("{spinner} nix {prefix}: {wide_msg} [{elapsed}]")
+            .unwrap_or_else(|_| ProgressStyle::default_spinner()),
+    );
+    bar.set_prefix(target.to_owned());
+    bar.enable_steady_tick(Duration::from_millis(120));
+
+    Arc::new(Mutex::new(move |event| match event {
+        BuildEvent::EvaluationStarted => bar.set_message("evaluating"),
+        BuildEvent::FetchingInput { url } => bar.set_message(format!("fetching {url}")),
+        BuildEvent::BuildingDrv { drv_path } => {
+            bar.set_message(format!("building {}", drv_path.display()))
+        }
+        BuildEvent::DownloadProgress {
+            url,
+            done,
+            expected,
+        } => bar.set_message(format!(
+            "downloading {url} ({}/{})",
+            HumanBytes(done),
+            HumanBytes(expected)
+        )),
+        BuildEvent::Done { .. } => bar.finish_and_clear(),
+    }))
+}
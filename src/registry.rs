@@ -0,0 +1,72 @@
+//! Flake registry operations, with `nix registry`
+
+use crate::{command, Error, Result};
+
+/// Registry an entry is defined in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegistryScope {
+    /// The user registry, `~/.config/nix/registry.json`
+    User,
+    /// The system registry, `/etc/nix/registry.json`
+    System,
+    /// The global registry, fetched from `flake-registry`
+    Global,
+}
+
+/// Entry of the flake registry, mapping a flake reference to another
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegistryEntry {
+    pub scope: RegistryScope,
+    /// Flake reference being mapped, like `flake:nixpkgs`
+    pub from: String,
+    /// Flake reference it's mapped to, like `github:NixOS/nixpkgs/nixpkgs-unstable`
+    pub to: String,
+}
+
+/// Returns the entries of all the flake registries
+pub fn registry_list() -> Result<Vec<RegistryEntry>> {
+    let stdout = command::nix_stdout(["registry", "list"])?;
+
+    String::from_utf8_lossy(&stdout)
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let mut fields = line.split_whitespace();
+            let scope = match fields.next() {
+                Some("user") => RegistryScope::User,
+                Some("system") => RegistryScope::System,
+                Some("global") => RegistryScope::Global,
+                _ => return Err(Error::UnknownOutput),
+            };
+
+            match (fields.next(), fields.next()) {
+                (Some(from), Some(to)) => Ok(RegistryEntry {
+                    scope,
+                    from: from.to_owned(),
+                    to: to.to_owned(),
+                }),
+                _ => Err(Error::UnknownOutput),
+            }
+        })
+        .collect()
+}
+
+/// Pin the flake `name` in the user registry to the locked flake reference `to`
+///
+/// # Example
+/// ```no_run
+/// # fn main() -> Result<(), nix_build::Error> {
+/// // use the same nixpkgs as the build
+/// nix_build::registry_pin("nixpkgs", "github:NixOS/nixpkgs/0123456789abcdef")?;
+/// # Ok(()) }
+/// ```
+pub fn registry_pin(name: &str, to: &str) -> Result<()> {
+    command::nix_stdout(["registry", "pin", name, to])?;
+    Ok(())
+}
+
+/// Map the flake `name` to `url` in the user registry
+pub fn registry_add(name: &str, url: &str) -> Result<()> {
+    command::nix_stdout(["registry", "add", name, url])?;
+    Ok(())
+}
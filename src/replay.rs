@@ -0,0 +1,200 @@
+//! Recording of `nix` invocations, replayed on machines without nix
+
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+    process::Output,
+    sync::Mutex,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{build_json, testing, CommandRunner, Error, NixRunner, Result};
+
+/// Invocation recorded in the fixture file of a [`RecordReplay`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Recording {
+    args: Vec<String>,
+    success: bool,
+    stdout: String,
+    stderr: String,
+    /// Output paths printed by `nix build --json`, checked when replaying
+    #[serde(default)]
+    paths: Vec<PathBuf>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Fixture {
+    invocations: Vec<Recording>,
+}
+
+/// Whether a [`RecordReplay`] runs nix or replays its recordings
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplayMode {
+    /// Run nix, recording the invocations
+    Record,
+    /// Replay the recorded invocations without running nix
+    Replay,
+}
+
+/// [`NixRunner`] recording the invocations of nix into a fixture file,
+/// to replay them on machines without nix
+///
+/// Invocations are matched by their arguments, ignoring the ones enabling experimental
+/// features, which depend on the installed nix. When replaying, the output paths of the
+/// recorded builds must exist, like when restored from a CI artifact, and
+/// [`Error::OutputMissing`] is returned otherwise
+///
+/// # Example
+/// ```no_run
+/// # fn main() -> Result<(), nix_build::Error> {
+/// use nix_build::{Config, RecordReplay};
+///
+/// // records where nix is installed, replays elsewhere
+/// let runner = RecordReplay::auto("nix-fixture.json")?;
+/// let derivations = Config::new()
+///     .target_flake("nixpkgs#protobuf")
+///     .runner(runner)
+///     .build()?;
+/// # Ok(()) }
+/// ```
+#[derive(Debug)]
+pub struct RecordReplay {
+    file: PathBuf,
+    mode: ReplayMode,
+    runner: CommandRunner,
+    fixture: Mutex<Fixture>,
+}
+
+impl RecordReplay {
+    /// Run nix and record its invocations into `file`, keeping the previous recordings
+    pub fn record(file: impl Into<PathBuf>) -> Result<Self> {
+        Self::new(file.into(), ReplayMode::Record)
+    }
+
+    /// Replay the invocations recorded into `file`
+    pub fn replay(file: impl Into<PathBuf>) -> Result<Self> {
+        Self::new(file.into(), ReplayMode::Replay)
+    }
+
+    /// Record into `file` if nix is installed, replay it otherwise
+    pub fn auto(file: impl Into<PathBuf>) -> Result<Self> {
+        match crate::is_nix_available() {
+            Some(_) => Self::record(file),
+            None => Self::replay(file),
+        }
+    }
+
+    fn new(file: PathBuf, mode: ReplayMode) -> Result<Self> {
+        let fixture = match std::fs::read(&file) {
+            Ok(json) => serde_json::from_slice(&json).map_err(std::io::Error::from)?,
+            Err(err)
+                if mode == ReplayMode::Record && err.kind() == std::io::ErrorKind::NotFound =>
+            {
+                Fixture::default()
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        Ok(Self {
+            file,
+            mode,
+            runner: CommandRunner::new(),
+            fixture: Mutex::new(fixture),
+        })
+    }
+
+    /// Returns whether nix is run or replayed
+    pub fn mode(&self) -> ReplayMode {
+        self.mode
+    }
+
+    /// Returns the fixture file
+    pub fn file(&self) -> &Path {
+        &self.file
+    }
+
+    fn record_output(&self, key: Vec<String>, output: &Output) -> Result<()> {
+        let paths = build_json::parse(&output.stdout)
+            .map(|derivations| {
+                derivations
+                    .iter()
+                    .flat_map(|drv| drv.outputs.values())
+                    .map(|path| path.to_path_buf())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let recording = Recording {
+            args: key,
+            success: output.status.success(),
+            stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+            paths,
+        };
+
+        let mut fixture = self.fixture.lock().unwrap();
+        fixture
+            .invocations
+            .retain(|recorded| recorded.args != recording.args);
+        fixture.invocations.push(recording);
+
+        if let Some(dir) = self.file.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+        let json = serde_json::to_vec_pretty(&*fixture).map_err(std::io::Error::from)?;
+        std::fs::write(&self.file, json)?;
+        Ok(())
+    }
+}
+
+/// Returns the arguments identifying an invocation, without the experimental features
+fn key(args: &[OsString]) -> Vec<String> {
+    let mut key = vec![];
+    let mut args = args.iter().map(|arg| arg.to_string_lossy().into_owned());
+
+    while let Some(arg) = args.next() {
+        if arg == "--experimental-features" || arg == "--extra-experimental-features" {
+            args.next();
+            continue;
+        }
+        key.push(arg);
+    }
+    key
+}
+
+impl NixRunner for RecordReplay {
+    fn run(&self, args: &[OsString], env: &[(OsString, OsString)]) -> Result<Output> {
+        let key = key(args);
+
+        if self.mode == ReplayMode::Record {
+            let output = self.runner.run(args, env)?;
+            self.record_output(key, &output)?;
+            return Ok(output);
+        }
+
+        let fixture = self.fixture.lock().unwrap();
+        let recording = fixture
+            .invocations
+            .iter()
+            .find(|recording| recording.args == key)
+            .ok_or_else(|| Error::CommandFailed {
+                program: "nix".to_owned(),
+                stderr: format!(
+                    "no invocation `nix {}` recorded in {}",
+                    key.join(" "),
+                    self.file.display()
+                ),
+            })?;
+
+        if let Some(missing) = recording.paths.iter().find(|path| !path.exists()) {
+            return Err(Error::OutputMissing(missing.clone()));
+        }
+
+        Ok(Output {
+            status: testing::exit_status(recording.success),
+            stdout: recording.stdout.clone().into_bytes(),
+            stderr: recording.stderr.clone().into_bytes(),
+        })
+    }
+}
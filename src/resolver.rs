@@ -0,0 +1,141 @@
+use std::fmt;
+
+use crate::{Config, Derivation, Error, PkgConfig, PkgConfigLibrary, Result};
+
+/// Way a dependency can be provided, see [`Resolver`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Strategy {
+    /// Built with nix
+    Nix,
+    /// Found on the system with `pkg-config`
+    PkgConfig,
+    /// Built by the fallback of the crate
+    Vendored,
+}
+
+impl fmt::Display for Strategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Nix => "nix",
+            Self::PkgConfig => "pkg-config",
+            Self::Vendored => "vendored",
+        })
+    }
+}
+
+/// Dependency provided by the first successful strategy of a [`Resolver`]
+#[derive(Debug, Clone)]
+pub enum Resolution {
+    /// The derivations built with nix
+    Nix(Vec<Derivation>),
+    /// The library found on the system with `pkg-config`
+    PkgConfig(PkgConfigLibrary),
+    /// The vendored fallback succeeded
+    Vendored,
+}
+
+impl Resolution {
+    /// Returns the strategy that provided the dependency
+    pub fn strategy(&self) -> Strategy {
+        match self {
+            Self::Nix(_) => Strategy::Nix,
+            Self::PkgConfig(_) => Strategy::PkgConfig,
+            Self::Vendored => Strategy::Vendored,
+        }
+    }
+}
+
+/// Provides a dependency with nix when possible, then from the system with `pkg-config`,
+/// then with a vendored build
+///
+/// Strategies that are not set are skipped.
+/// Fails with [`Error::ResolveFailed`] and the error of each strategy if none succeeds
+///
+/// # Example
+/// ```no_run
+/// # fn main() -> Result<(), nix_build::Error> {
+/// use nix_build::{Config, Resolution, Resolver};
+///
+/// let mut nix = Config::new();
+/// nix.target_flake("nixpkgs#zlib");
+///
+/// let resolution = Resolver::new()
+///     .nix(nix)
+///     .pkg_config("zlib")
+///     .vendored(|| {
+///         // build the bundled sources, like with the `cc` crate
+///         Ok(())
+///     })
+///     .resolve()?;
+///
+/// println!("cargo:warning=zlib provided by {}", resolution.strategy());
+/// if let Resolution::Nix(derivations) = &resolution {
+///     derivations[0].emit_link_search();
+/// }
+/// # Ok(()) }
+/// ```
+#[derive(Default)]
+pub struct Resolver {
+    nix: Option<Config>,
+    pkg_config: Option<String>,
+    vendored: Option<Box<dyn FnMut() -> Result<()>>>,
+}
+
+impl Resolver {
+    /// Create a new [`Resolver`] without any strategy
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Build the dependency with `config` first
+    pub fn nix(&mut self, config: Config) -> &mut Self {
+        self.nix = Some(config);
+        self
+    }
+
+    /// Look up the `pkg-config` module `module` of the system, if nix fails
+    pub fn pkg_config(&mut self, module: &str) -> &mut Self {
+        self.pkg_config = Some(module.to_owned());
+        self
+    }
+
+    /// Run `build` as a last resort, if the other strategies fail
+    pub fn vendored(&mut self, build: impl FnMut() -> Result<()> + 'static) -> &mut Self {
+        self.vendored = Some(Box::new(build));
+        self
+    }
+
+    /// Try the strategies in order, returning the first successful one
+    pub fn resolve(&mut self) -> Result<Resolution> {
+        let mut failures = vec![];
+
+        if let Some(config) = &self.nix {
+            match config.build() {
+                Ok(derivations) => return Ok(Resolution::Nix(derivations)),
+                Err(err) => {
+                    debug!("nix strategy failed: {err}");
+                    failures.push((Strategy::Nix, err));
+                }
+            }
+        }
+
+        if let Some(module) = &self.pkg_config {
+            match PkgConfig::new().probe(module) {
+                Ok(library) => return Ok(Resolution::PkgConfig(library)),
+                Err(err) => {
+                    debug!("pkg-config strategy failed: {err}");
+                    failures.push((Strategy::PkgConfig, err));
+                }
+            }
+        }
+
+        if let Some(build) = &mut self.vendored {
+            match build() {
+                Ok(()) => return Ok(Resolution::Vendored),
+                Err(err) => failures.push((Strategy::Vendored, err)),
+            }
+        }
+
+        Err(Error::ResolveFailed(failures))
+    }
+}
@@ -0,0 +1,91 @@
+//! Running programs provided by nix packages, with `nix run`
+
+use std::{
+    ffi::{OsStr, OsString},
+    process::{Command, ExitStatus, Output},
+};
+
+use crate::{command, version, Config, Installable, Result};
+
+/// Runs the program of a nix package without installing it
+///
+/// The package is selected like the target of a [`Config`]
+///
+/// # Example
+/// ```no_run
+/// # fn main() -> Result<(), nix_build::Error> {
+/// use nix_build::Run;
+///
+/// let status = Run::new("nixpkgs#protobuf")
+///     .args(["--rust_out=src", "proto/foo.proto"])
+///     .status()?;
+/// assert!(status.success());
+/// # Ok(()) }
+/// ```
+#[derive(Clone)]
+pub struct Run {
+    config: Config,
+    args: Vec<OsString>,
+}
+
+impl Run {
+    /// Create a new [`Run`] for `installable`, like the flake output `nixpkgs#hello`
+    pub fn new(installable: impl Into<Installable>) -> Self {
+        let mut config = Config::new();
+        config.target(installable);
+        Self::from_config(config)
+    }
+
+    /// Create a new [`Run`] for the target of `config`
+    pub fn from_config(config: Config) -> Self {
+        Self {
+            config,
+            args: vec![],
+        }
+    }
+
+    /// Returns the configuration selecting the package, to customize it
+    pub fn config(&mut self) -> &mut Config {
+        &mut self.config
+    }
+
+    /// Add an argument to pass to the program
+    pub fn arg(&mut self, arg: impl AsRef<OsStr>) -> &mut Self {
+        self.args.push(arg.as_ref().to_owned());
+        self
+    }
+
+    /// Add arguments to pass to the program
+    pub fn args<I, S>(&mut self, args: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        self.args
+            .extend(args.into_iter().map(|arg| arg.as_ref().to_owned()));
+        self
+    }
+
+    /// Returns the `nix run` [`Command`], to further configure its stdio or environment
+    pub fn command(&self) -> Result<Command> {
+        let mut cmd = command::nix()?;
+        cmd.arg("run")
+            .args(self.config.installable_args())
+            .args(version::experimental_features_args())
+            .arg("--")
+            .args(&self.args);
+        self.config.configure(&mut cmd);
+
+        Ok(cmd)
+    }
+
+    /// Run the program, inheriting stdio, and wait for its exit status
+    pub fn status(&self) -> Result<ExitStatus> {
+        Ok(self.command()?.status()?)
+    }
+
+    /// Run the program, capturing its output
+    pub fn output(&self) -> Result<Output> {
+        Ok(self.command()?.output()?)
+    }
+}
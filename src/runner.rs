@@ -0,0 +1,81 @@
+//! Execution of the `nix` invocations of a [`Config`](crate::Config)
+
+use std::{
+    ffi::OsString,
+    path::PathBuf,
+    process::{Command, Output, Stdio},
+};
+
+use crate::{command, Result};
+
+/// Runs the `nix` invocations of a [`Config`](crate::Config), see [`Config::runner`](crate::Config::runner)
+///
+/// Meant to wrap the invocations, like to run them remotely or in a sandbox,
+/// or to replace them in tests
+///
+/// # Example
+/// ```no_run
+/// # fn main() -> Result<(), nix_build::Error> {
+/// use std::{ffi::OsString, process::Output};
+/// use nix_build::{CommandRunner, Config, NixRunner};
+///
+/// struct Logged(CommandRunner);
+///
+/// impl NixRunner for Logged {
+///     fn run(
+///         &self,
+///         args: &[OsString],
+///         env: &[(OsString, OsString)],
+///     ) -> Result<Output, nix_build::Error> {
+///         eprintln!("nix {args:?}");
+///         self.0.run(args, env)
+///     }
+/// }
+///
+/// let derivations = Config::new().runner(Logged(CommandRunner::new())).build()?;
+/// # Ok(()) }
+/// ```
+pub trait NixRunner: Send + Sync {
+    /// Run `nix` with the arguments `args` and the additional environment variables `env`,
+    /// returning its output once it exits
+    ///
+    /// The structured log printed on stderr is parsed afterwards,
+    /// so the build events are only emitted once the invocation is done
+    fn run(&self, args: &[OsString], env: &[(OsString, OsString)]) -> Result<Output>;
+}
+
+/// [`NixRunner`] spawning the `nix` program with [`Command`]
+#[derive(Debug, Clone, Default)]
+pub struct CommandRunner {
+    program: Option<PathBuf>,
+}
+
+impl CommandRunner {
+    /// Create a new [`CommandRunner`] running the found `nix` program,
+    /// see [`is_nix_available`](crate::is_nix_available)
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run `program` instead of the found `nix`, like a wrapper script
+    pub fn program(&mut self, program: impl Into<PathBuf>) -> &mut Self {
+        self.program = Some(program.into());
+        self
+    }
+}
+
+impl NixRunner for CommandRunner {
+    fn run(&self, args: &[OsString], env: &[(OsString, OsString)]) -> Result<Output> {
+        let mut cmd = match &self.program {
+            Some(program) => Command::new(program),
+            None => command::nix()?,
+        };
+
+        let output = cmd
+            .args(args)
+            .envs(env.iter().map(|(name, value)| (name, value)))
+            .stdin(Stdio::null())
+            .output()?;
+        Ok(output)
+    }
+}
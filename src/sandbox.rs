@@ -0,0 +1,23 @@
+//! Isolation of the builders, from the `sandbox` nix setting
+
+/// Whether builders run in a sandbox, see [`Config::sandbox`](crate::Config::sandbox)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SandboxMode {
+    /// Run every builder in a sandbox, isolated from the network and the host filesystem
+    Enabled,
+    /// Run builders in a sandbox, except the derivations marked `__noChroot`
+    Relaxed,
+    /// Run builders without a sandbox, like inside containers where it can't be created
+    Disabled,
+}
+
+impl SandboxMode {
+    /// Returns the value of the `sandbox` nix setting
+    pub(crate) fn setting(&self) -> &'static str {
+        match self {
+            Self::Enabled => "true",
+            Self::Relaxed => "relaxed",
+            Self::Disabled => "false",
+        }
+    }
+}
@@ -0,0 +1,484 @@
+//! Software bills of materials of built closures
+
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde_json::{json, Value};
+
+use crate::{fingerprint::Hasher, out_paths, store, Config, Derivation, License, Result};
+
+/// Format of a software bill of materials
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SbomFormat {
+    /// [SPDX](https://spdx.dev) 2.3 JSON
+    Spdx,
+    /// [CycloneDX](https://cyclonedx.org) 1.5 JSON
+    CycloneDx,
+}
+
+/// Package in a software bill of materials
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SbomComponent {
+    /// Package name, like `openssl`
+    pub name: String,
+    /// Package version, if any
+    pub version: Option<String>,
+    /// Store path of the package
+    pub path: PathBuf,
+    /// Hash of the NAR serialization of the store path
+    pub nar_hash: Option<String>,
+    /// SPDX license identifier, if known
+    pub license: Option<String>,
+    /// Store paths referenced at runtime
+    pub references: Vec<PathBuf>,
+}
+
+/// Split a derivation name into its name and version, like `builtins.parseDrvName`
+///
+/// The version starts after the first dash not followed by a letter
+fn parse_drv_name(name: &str) -> (&str, Option<&str>) {
+    let split = name.char_indices().find(|&(i, c)| {
+        c == '-'
+            && name[i + 1..]
+                .chars()
+                .next()
+                .is_some_and(|next| !next.is_alphabetic())
+    });
+
+    match split {
+        Some((i, _)) => (&name[..i], Some(&name[i + 1..])),
+        None => (name, None),
+    }
+}
+
+/// Returns the name of the package providing `path`, built by `deriver`
+///
+/// Outputs other than `out` are named after their derivation
+fn package_name(path: &Path, deriver: Option<&Path>) -> String {
+    let drv_name = deriver
+        .and_then(out_paths::store_name)
+        .map(|name| name.trim_end_matches(".drv"));
+
+    drv_name
+        .or_else(|| out_paths::store_name(path))
+        .unwrap_or_default()
+        .to_owned()
+}
+
+/// Format `secs` since the unix epoch as an RFC 3339 UTC timestamp
+fn rfc3339(secs: u64) -> String {
+    let days = (secs / 86_400) as i64;
+    let time = secs % 86_400;
+
+    // civil from days, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+/// Returns the creation time of documents, honoring `SOURCE_DATE_EPOCH` for reproducibility
+fn created() -> String {
+    let secs = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.parse().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_secs())
+                .unwrap_or_default()
+        });
+
+    rfc3339(secs)
+}
+
+/// Software bill of materials of the runtime closure of built derivations
+///
+/// # Example
+/// ```no_run
+/// # fn main() -> Result<(), nix_build::Error> {
+/// use nix_build::{Config, SbomFormat};
+///
+/// let sbom = Config::new().target_flake("nixpkgs#openssl").sbom()?;
+///
+/// let out_dir = std::env::var("OUT_DIR").unwrap();
+/// std::fs::write(
+///     std::path::Path::new(&out_dir).join("openssl.spdx.json"),
+///     sbom.to_json(SbomFormat::Spdx),
+/// )?;
+/// # Ok(()) }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sbom {
+    /// Name of the document
+    pub name: String,
+    /// Packages of the closure, starting with the outputs of the derivations
+    pub components: Vec<SbomComponent>,
+}
+
+impl Sbom {
+    /// Collect the runtime closure of all the outputs of the given derivations
+    ///
+    /// Licenses are not known at this point, see [`Config::sbom`] and [`Sbom::set_license`]
+    pub fn from_derivations<'a>(
+        derivations: impl IntoIterator<Item = &'a Derivation>,
+    ) -> Result<Self> {
+        let derivations: Vec<&Derivation> = derivations.into_iter().collect();
+        let outputs: Vec<PathBuf> = derivations
+            .iter()
+            .flat_map(|derivation| derivation.outputs.values().map(|path| path.to_path_buf()))
+            .collect();
+
+        let components = store::path_info::query(&outputs, false, true)?
+            .into_iter()
+            .map(|info| {
+                let name = package_name(&info.path, info.deriver.as_deref());
+                let (name, version) = parse_drv_name(&name);
+
+                SbomComponent {
+                    name: name.to_owned(),
+                    version: version.map(str::to_owned),
+                    path: info.path,
+                    nar_hash: info.nar_hash,
+                    license: None,
+                    references: info.references,
+                }
+            })
+            .collect();
+
+        let name = derivations
+            .first()
+            .and_then(|derivation| out_paths::store_name(&derivation.drv_path))
+            .map(|name| name.trim_end_matches(".drv").to_owned())
+            .unwrap_or_default();
+
+        Ok(Self { name, components })
+    }
+
+    /// Set the license of the components provided by the given derivation
+    pub fn set_license(&mut self, derivation: &Derivation, license: impl Into<String>) {
+        let license = license.into();
+        let outputs: HashSet<&Path> = derivation
+            .outputs
+            .values()
+            .map(|path| path.as_path())
+            .collect();
+
+        for component in &mut self.components {
+            if outputs.contains(component.path.as_path()) {
+                component.license = Some(license.clone());
+            }
+        }
+    }
+
+    /// Returns the SPDX identifier of the document element for `path`
+    fn spdx_id(path: &Path) -> String {
+        let hash = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.split_once('-'))
+            .map_or("unknown", |(hash, _)| hash);
+
+        format!("SPDXRef-Package-{hash}")
+    }
+
+    fn to_spdx(&self) -> Value {
+        let known: HashSet<&PathBuf> = self.components.iter().map(|c| &c.path).collect();
+
+        let mut hasher = Hasher::new();
+        for component in &self.components {
+            hasher.write(component.path.as_os_str().as_encoded_bytes());
+        }
+
+        let packages: Vec<Value> = self
+            .components
+            .iter()
+            .map(|component| {
+                json!({
+                    "SPDXID": Self::spdx_id(&component.path),
+                    "name": component.name,
+                    "versionInfo": component.version.as_deref().unwrap_or("NOASSERTION"),
+                    "downloadLocation": "NOASSERTION",
+                    "filesAnalyzed": false,
+                    "licenseConcluded": "NOASSERTION",
+                    "licenseDeclared": component.license.as_deref().unwrap_or("NOASSERTION"),
+                    "sourceInfo": component.path.display().to_string(),
+                })
+            })
+            .collect();
+
+        let mut relationships: Vec<Value> = self
+            .components
+            .first()
+            .map(|root| {
+                json!({
+                    "spdxElementId": "SPDXRef-DOCUMENT",
+                    "relationshipType": "DESCRIBES",
+                    "relatedSpdxElement": Self::spdx_id(&root.path),
+                })
+            })
+            .into_iter()
+            .collect();
+
+        for component in &self.components {
+            for reference in &component.references {
+                if reference == &component.path || !known.contains(reference) {
+                    continue;
+                }
+                relationships.push(json!({
+                    "spdxElementId": Self::spdx_id(&component.path),
+                    "relationshipType": "DEPENDS_ON",
+                    "relatedSpdxElement": Self::spdx_id(reference),
+                }));
+            }
+        }
+
+        json!({
+            "spdxVersion": "SPDX-2.3",
+            "dataLicense": "CC0-1.0",
+            "SPDXID": "SPDXRef-DOCUMENT",
+            "name": self.name,
+            "documentNamespace": format!(
+                "https://spdx.org/spdxdocs/{}-{}",
+                self.name,
+                hasher.finish()
+            ),
+            "creationInfo": {
+                "created": created(),
+                "creators": [concat!("Tool: nix-build-", env!("CARGO_PKG_VERSION"))],
+            },
+            "packages": packages,
+            "relationships": relationships,
+        })
+    }
+
+    fn to_cyclonedx(&self) -> Value {
+        let components: Vec<Value> = self
+            .components
+            .iter()
+            .map(|component| {
+                let mut value = json!({
+                    "type": "library",
+                    "bom-ref": component.path.display().to_string(),
+                    "name": component.name,
+                    "properties": [
+                        { "name": "nix:store_path", "value": component.path.display().to_string() },
+                    ],
+                });
+
+                if let Some(version) = &component.version {
+                    value["version"] = json!(version);
+                }
+                // combined licenses, like `MIT OR Apache-2.0`, are not identifiers
+                match &component.license {
+                    Some(license) if license.contains(char::is_whitespace) => {
+                        value["licenses"] = json!([{ "expression": license }]);
+                    }
+                    Some(license) => {
+                        value["licenses"] = json!([{ "license": { "id": license } }]);
+                    }
+                    None => {}
+                }
+                if let Some(hash) = &component.nar_hash {
+                    value["properties"]
+                        .as_array_mut()
+                        .unwrap()
+                        .push(json!({ "name": "nix:nar_hash", "value": hash }));
+                }
+
+                value
+            })
+            .collect();
+
+        let dependencies: Vec<Value> = self
+            .components
+            .iter()
+            .map(|component| {
+                let depends_on: Vec<String> = component
+                    .references
+                    .iter()
+                    .filter(|reference| *reference != &component.path)
+                    .map(|reference| reference.display().to_string())
+                    .collect();
+
+                json!({
+                    "ref": component.path.display().to_string(),
+                    "dependsOn": depends_on,
+                })
+            })
+            .collect();
+
+        json!({
+            "bomFormat": "CycloneDX",
+            "specVersion": "1.5",
+            "version": 1,
+            "metadata": {
+                "timestamp": created(),
+                "component": { "type": "application", "name": self.name },
+            },
+            "components": components,
+            "dependencies": dependencies,
+        })
+    }
+
+    /// Serialize the document to JSON in the given format
+    pub fn to_json(&self, format: SbomFormat) -> String {
+        let value = match format {
+            SbomFormat::Spdx => self.to_spdx(),
+            SbomFormat::CycloneDx => self.to_cyclonedx(),
+        };
+
+        serde_json::to_string_pretty(&value).unwrap_or_default()
+    }
+}
+
+impl Config {
+    /// Build the target and collect the software bill of materials of its runtime closure
+    ///
+    /// The license of the target is evaluated from its `meta.license`, see [`Config::meta`],
+    /// while the rest of the closure is left without license.
+    /// Fails if the `meta` of the target can't be evaluated, rather than omitting its license.
+    /// See [`Sbom::from_derivations`]
+    pub fn sbom(&self) -> Result<Sbom> {
+        let derivations = self.build()?;
+        let mut sbom = Sbom::from_derivations(&derivations)?;
+
+        let meta = self.meta()?;
+        let licenses: Vec<&str> = meta.licenses.iter().filter_map(License::id).collect();
+        let license = (!licenses.is_empty()).then(|| licenses.join(" OR "));
+
+        if let Some(license) = license {
+            for derivation in &derivations {
+                sbom.set_license(derivation, license.clone());
+            }
+        }
+
+        Ok(sbom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HELLO: &str = "/nix/store/0c8ybjvkn0jxx2xy7yv5c3d7zg2g3yy2-hello-2.12.1";
+    const GLIBC: &str = "/nix/store/9w0bd6yv5m0gxvwryjfxvjvr6ch0bqsm-glibc-2.37-8";
+    const SOURCE: &str = "/nix/store/vkb3h6vb2nhcbmmp7zs1w2n0x1i8sv3l-source";
+
+    fn sbom() -> Sbom {
+        let component =
+            |path: &str, name: &str, version: Option<&str>, license: Option<&str>| SbomComponent {
+                name: name.to_owned(),
+                version: version.map(str::to_owned),
+                path: PathBuf::from(path),
+                nar_hash: Some("sha256-Tyr2UDAf/V8Mc6MNUi5a1pp4IbDo1b0nK5Wm0flC0vU=".to_owned()),
+                license: license.map(str::to_owned),
+                references: vec![PathBuf::from(path), PathBuf::from(GLIBC)],
+            };
+
+        Sbom {
+            name: "hello-2.12.1".to_owned(),
+            components: vec![
+                component(HELLO, "hello", Some("2.12.1"), Some("GPL-3.0-or-later")),
+                component(
+                    GLIBC,
+                    "glibc",
+                    Some("2.37-8"),
+                    Some("LGPL-2.1-or-later OR GPL-2.0-or-later"),
+                ),
+                component(SOURCE, "source", None, None),
+            ],
+        }
+    }
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(rfc3339(0), "1970-01-01T00:00:00Z");
+        assert_eq!(rfc3339(951_782_400), "2000-02-29T00:00:00Z");
+        assert_eq!(rfc3339(1_718_200_042), "2024-06-12T13:47:22Z");
+        assert_eq!(rfc3339(4_107_542_399), "2100-02-28T23:59:59Z");
+        assert_eq!(rfc3339(4_107_542_400), "2100-03-01T00:00:00Z");
+    }
+
+    #[test]
+    fn drv_names() {
+        assert_eq!(parse_drv_name("hello-2.12.1"), ("hello", Some("2.12.1")));
+        assert_eq!(
+            parse_drv_name("xorg-server-21.1.8"),
+            ("xorg-server", Some("21.1.8"))
+        );
+        assert_eq!(parse_drv_name("source"), ("source", None));
+    }
+
+    #[test]
+    fn cyclonedx() {
+        let json: Value = serde_json::from_str(&sbom().to_json(SbomFormat::CycloneDx)).unwrap();
+        assert_eq!(json["bomFormat"], "CycloneDX");
+        assert_eq!(json["specVersion"], "1.5");
+
+        let components = json["components"].as_array().unwrap();
+        assert_eq!(components[0]["version"], "2.12.1");
+        assert_eq!(
+            components[0]["licenses"],
+            json!([{ "license": { "id": "GPL-3.0-or-later" } }])
+        );
+        assert_eq!(
+            components[1]["licenses"],
+            json!([{ "expression": "LGPL-2.1-or-later OR GPL-2.0-or-later" }])
+        );
+        assert!(components[2].get("version").is_none());
+        assert!(components[2].get("licenses").is_none());
+
+        assert_eq!(
+            json["dependencies"][0],
+            json!({ "ref": HELLO, "dependsOn": [GLIBC] })
+        );
+    }
+
+    #[test]
+    fn spdx() {
+        let json: Value = serde_json::from_str(&sbom().to_json(SbomFormat::Spdx)).unwrap();
+        assert_eq!(json["spdxVersion"], "SPDX-2.3");
+
+        let packages = json["packages"].as_array().unwrap();
+        assert_eq!(
+            packages[0]["SPDXID"],
+            "SPDXRef-Package-0c8ybjvkn0jxx2xy7yv5c3d7zg2g3yy2"
+        );
+        assert_eq!(packages[0]["licenseDeclared"], "GPL-3.0-or-later");
+        assert_eq!(packages[2]["versionInfo"], "NOASSERTION");
+        assert_eq!(packages[2]["licenseDeclared"], "NOASSERTION");
+
+        let relationships = json["relationships"].as_array().unwrap();
+        assert_eq!(
+            relationships[0],
+            json!({
+                "spdxElementId": "SPDXRef-DOCUMENT",
+                "relationshipType": "DESCRIBES",
+                "relatedSpdxElement": "SPDXRef-Package-0c8ybjvkn0jxx2xy7yv5c3d7zg2g3yy2",
+            })
+        );
+        assert_eq!(relationships[1]["relationshipType"], "DEPENDS_ON");
+        assert_eq!(
+            relationships[1]["relatedSpdxElement"],
+            "SPDXRef-Package-9w0bd6yv5m0gxvwryjfxvjvr6ch0bqsm"
+        );
+        // hello and the source depend on glibc, which only references itself
+        assert_eq!(relationships.len(), 3);
+    }
+}
@@ -0,0 +1,52 @@
+//! Package lookup with `nix search`
+
+use std::collections::BTreeMap;
+
+use crate::{command, Result};
+
+/// Package found by [`search`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchResult {
+    /// Attribute path in the flake, like `legacyPackages.x86_64-linux.hello`
+    pub attr_path: String,
+    pub pname: String,
+    pub version: String,
+    pub description: String,
+}
+
+/// Search the packages of `flake_ref` matching the regular expression `query`
+///
+/// Results are sorted by attribute path
+///
+/// # Example
+/// ```no_run
+/// # fn main() -> Result<(), nix_build::Error> {
+/// for result in nix_build::search("nixpkgs", "^openssl")? {
+///     println!("{} {}: {}", result.pname, result.version, result.description);
+/// }
+/// # Ok(()) }
+/// ```
+pub fn search(flake_ref: &str, query: &str) -> Result<Vec<SearchResult>> {
+    #[derive(serde::Deserialize)]
+    struct Package {
+        #[serde(default)]
+        pname: String,
+        #[serde(default)]
+        version: String,
+        #[serde(default)]
+        description: String,
+    }
+
+    let packages: BTreeMap<String, Package> =
+        command::nix_json(["search", "--json", flake_ref, query])?;
+
+    Ok(packages
+        .into_iter()
+        .map(|(attr_path, package)| SearchResult {
+            attr_path,
+            pname: package.pname,
+            version: package.version,
+            description: package.description,
+        })
+        .collect())
+}
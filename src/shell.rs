@@ -0,0 +1,60 @@
+//! Ad-hoc tool environments, with `nix shell`
+
+use std::{ffi::OsStr, process::Command};
+
+use crate::{command, version, Result};
+
+/// Environment with the programs of several flake packages on `PATH`
+///
+/// Unlike [`ToolEnv`](crate::ToolEnv), the packages don't need to be built beforehand
+///
+/// # Example
+/// ```no_run
+/// # fn main() -> Result<(), nix_build::Error> {
+/// use nix_build::Shell;
+///
+/// let status = Shell::with(&["nixpkgs#cmake", "nixpkgs#ninja"])
+///     .command("cmake")?
+///     .args(["-G", "Ninja", "-B", "build"])
+///     .status()?;
+/// # Ok(()) }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Shell {
+    installables: Vec<String>,
+}
+
+impl Shell {
+    /// Create a new [`Shell`] with the given flake installables
+    pub fn with<I, S>(installables: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut shell = Self::default();
+        for installable in installables {
+            shell.package(installable.as_ref());
+        }
+        shell
+    }
+
+    /// Add a flake installable to the environment
+    pub fn package(&mut self, installable: &str) -> &mut Self {
+        self.installables.push(installable.to_owned());
+        self
+    }
+
+    /// Returns a [`Command`] running `program` inside the environment
+    ///
+    /// Arguments added to the returned command are passed to `program`
+    pub fn command(&self, program: impl AsRef<OsStr>) -> Result<Command> {
+        let mut cmd = command::nix()?;
+        cmd.arg("shell")
+            .args(&self.installables)
+            .args(version::experimental_features_args())
+            .arg("--command")
+            .arg(program);
+
+        Ok(cmd)
+    }
+}
@@ -0,0 +1,258 @@
+//! Builds running in the background
+
+use std::{
+    process::{Child, Command, ExitStatus},
+    sync::{Arc, Mutex, MutexGuard},
+    thread::JoinHandle,
+};
+
+use crate::{BuildReport, Config, Derivation, Error, Result};
+
+/// What happens to a spawned build when its [`BuildHandle`] is dropped without being waited on
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DropPolicy {
+    /// Let the build run in the background, until the process exits
+    Detach,
+    /// Terminate the `nix` process, see [`BuildHandle::kill`]
+    #[default]
+    Kill,
+    /// Block until the build finishes
+    Wait,
+}
+
+/// Set up `cmd` so the `nix` process and its builders can be terminated together,
+/// running with the scheduling priority `nice` if set
+///
+/// On Linux `nix` is started in its own process group, and is sent `SIGTERM`
+/// if the thread that spawned it dies, like when cargo is interrupted
+pub(crate) fn configure(cmd: &mut Command, nice: Option<i32>) {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+
+        #[cfg(target_os = "linux")]
+        cmd.process_group(0);
+
+        // SAFETY: `setpriority`, `prctl` and `getppid` are async-signal-safe
+        unsafe {
+            cmd.pre_exec(move || {
+                if let Some(level) = nice {
+                    if libc::setpriority(libc::PRIO_PROCESS, 0, level) == -1 {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                }
+
+                #[cfg(target_os = "linux")]
+                {
+                    if libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGTERM) == -1 {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                    // the parent died before the signal was armed
+                    if libc::getppid() == 1 {
+                        return Err(std::io::ErrorKind::Interrupted.into());
+                    }
+                }
+
+                Ok(())
+            });
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = (cmd, nice);
+}
+
+/// Terminate `child`, letting `nix` release its locks and stop its builders
+///
+/// The whole process group is sent `SIGTERM` where [`configure`] created one
+fn terminate(child: &mut Child) -> Result<()> {
+    #[cfg(target_os = "linux")]
+    {
+        if child.try_wait()?.is_some() {
+            return Ok(());
+        }
+
+        let pgid = child.id() as libc::pid_t;
+        // SAFETY: only sends a signal
+        if unsafe { libc::kill(-pgid, libc::SIGTERM) } == -1 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        Ok(())
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        child.kill()?;
+        Ok(())
+    }
+}
+
+/// State of the `nix` process run by a spawned build
+#[derive(Default)]
+struct ProcessState {
+    child: Option<Child>,
+    killed: bool,
+}
+
+/// Handle to the `nix` process run by a spawned build, shared with its [`BuildHandle`]
+#[derive(Clone, Default)]
+pub(crate) struct Process(Arc<Mutex<ProcessState>>);
+
+impl Process {
+    fn state(&self) -> MutexGuard<'_, ProcessState> {
+        self.0.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Fails with [`Error::Cancelled`] if the build has been killed
+    pub(crate) fn check(&self) -> Result<()> {
+        if self.state().killed {
+            Err(Error::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Keep the spawned `child`, killing it right away if the build has been killed
+    pub(crate) fn attach(&self, mut child: Child) -> Result<()> {
+        let mut state = self.state();
+        if state.killed {
+            let _ = terminate(&mut child);
+            let _ = child.wait();
+            return Err(Error::Cancelled);
+        }
+        state.child = Some(child);
+        Ok(())
+    }
+
+    /// Wait for the attached child to exit
+    ///
+    /// Fails with [`Error::Cancelled`] if the build has been killed
+    pub(crate) fn wait(&self) -> Result<ExitStatus> {
+        let mut state = self.state();
+        let status = match &mut state.child {
+            Some(child) => child.wait()?,
+            None => return Err(Error::Cancelled),
+        };
+        state.child = None;
+
+        if state.killed {
+            return Err(Error::Cancelled);
+        }
+        Ok(status)
+    }
+
+    /// Kill the attached child, if any, and any process spawned after
+    fn kill(&self) -> Result<()> {
+        let mut state = self.state();
+        state.killed = true;
+        if let Some(child) = &mut state.child {
+            terminate(child)?;
+        }
+        Ok(())
+    }
+}
+
+/// Result of a spawned build, see [`Config::build_with_report`]
+type Built = Result<(Vec<Derivation>, BuildReport)>;
+
+/// Build running in the background, returned by [`Config::spawn`]
+///
+/// What happens when dropped is set with [`Config::drop_policy`]
+pub struct BuildHandle {
+    thread: Option<JoinHandle<Built>>,
+    process: Process,
+    drop_policy: DropPolicy,
+}
+
+impl BuildHandle {
+    /// Wait for the build to finish, returning the built derivations
+    pub fn wait(self) -> Result<Vec<Derivation>> {
+        self.wait_with_report().map(|(derivations, _)| derivations)
+    }
+
+    /// Wait for the build to finish, also returning the metrics of the build
+    ///
+    /// See [`Config::build_with_report`]
+    pub fn wait_with_report(mut self) -> Built {
+        let thread = self.thread.take().expect("build is only joined once");
+        match thread.join() {
+            Ok(result) => result,
+            Err(panic) => std::panic::resume_unwind(panic),
+        }
+    }
+
+    /// Returns the result of the build if it has finished, without blocking
+    ///
+    /// The handle is given back if the build is still running
+    pub fn try_wait(self) -> std::result::Result<Result<Vec<Derivation>>, Self> {
+        if self.thread.as_ref().is_some_and(JoinHandle::is_finished) {
+            Ok(self.wait())
+        } else {
+            Err(self)
+        }
+    }
+
+    /// Terminate the `nix` process of the build
+    ///
+    /// `nix` is sent `SIGTERM` on Linux, killed elsewhere.
+    /// The build then fails with [`Error::Cancelled`]
+    pub fn kill(&self) -> Result<()> {
+        self.process.kill()
+    }
+}
+
+impl Drop for BuildHandle {
+    fn drop(&mut self) {
+        let Some(thread) = self.thread.take() else {
+            return;
+        };
+
+        match self.drop_policy {
+            DropPolicy::Detach => {}
+            DropPolicy::Kill => {
+                debug!("build handle dropped, terminating nix");
+                let _ = self.process.kill();
+                let _ = thread.join();
+            }
+            DropPolicy::Wait => {
+                let _ = thread.join();
+            }
+        }
+    }
+}
+
+impl Config {
+    /// Start the build in the background, returning a handle to join it
+    ///
+    /// # Example
+    /// ```no_run
+    /// # fn main() -> Result<(), nix_build::Error> {
+    /// use nix_build::Config;
+    ///
+    /// let build = Config::new().target_flake("nixpkgs#openssl").spawn();
+    ///
+    /// // unrelated work, like generating code
+    ///
+    /// let derivations = build.wait()?;
+    /// # Ok(()) }
+    /// ```
+    pub fn spawn(&self) -> BuildHandle {
+        let process = Process::default();
+
+        let mut config = self.clone();
+        config.process = Some(process.clone());
+        let thread = std::thread::spawn(move || config.build_with_report());
+
+        BuildHandle {
+            thread: Some(thread),
+            process,
+            drop_policy: self.drop_policy,
+        }
+    }
+
+    /// Set what happens to a spawned build when its [`BuildHandle`] is dropped
+    ///
+    /// Defaults to [`DropPolicy::Kill`]
+    pub fn drop_policy(&mut self, policy: DropPolicy) -> &mut Self {
+        self.drop_policy = policy;
+        self
+    }
+}
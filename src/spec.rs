@@ -0,0 +1,306 @@
+//! Declarative configuration, deserialized from files like `nix-build.toml`
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+use crate::{Config, Error, Result};
+
+/// Declarative [`Config`], to be deserialized and built with [`Config::from_spec`]
+///
+/// At most one of `flake`, `file` and `expr` can be set, targeting `default.nix` otherwise.
+/// Fields are named in kebab-case, like `out-link`
+///
+/// # Example
+/// ```no_run
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// use nix_build::{Config, ConfigSpec};
+///
+/// let spec: ConfigSpec = serde_json::from_str(
+///     r#"{ "flake": "nixpkgs#openssl", "impure": true, "substituters": ["https://my-cache.org"] }"#,
+/// )?;
+/// let derivations = Config::from_spec(&spec)?.build()?;
+/// # Ok(()) }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ConfigSpec {
+    /// See [`Config::target_flake`]
+    pub flake: Option<String>,
+    /// See [`Config::target_file`]
+    pub file: Option<PathBuf>,
+    /// See [`Config::target_expr`]
+    pub expr: Option<String>,
+    /// See [`Config::attribute`]
+    pub attribute: Option<String>,
+    /// See [`Config::arg_expr`]
+    pub args: BTreeMap<String, String>,
+    /// See [`Config::arg_str`]
+    pub argstrs: BTreeMap<String, String>,
+    /// See [`Config::override_flake`]
+    pub override_flakes: BTreeMap<String, String>,
+    /// See [`Config::impure`]
+    pub impure: Option<bool>,
+    /// See [`Config::outputs`]
+    pub outputs: Vec<String>,
+    /// See [`Config::eval_store`]
+    pub eval_store: Option<String>,
+    /// See [`Config::store_root`]
+    pub store_root: Option<PathBuf>,
+    /// See [`Config::ca_derivations`]
+    pub ca_derivations: Option<bool>,
+    /// See [`Config::system`]
+    pub system: Option<String>,
+    /// See [`Config::substituter`]
+    pub substituters: Vec<String>,
+    /// See [`Config::option`]
+    pub options: BTreeMap<String, String>,
+    /// See [`Config::out_link`]
+    pub out_link: Option<PathBuf>,
+    /// See [`Config::gc_root`]
+    pub gc_root: Option<bool>,
+    /// See [`Config::shared_out_link`]
+    pub shared_out_link: Option<bool>,
+    /// See [`Config::write_manifest`]
+    pub write_manifest: Option<bool>,
+    /// See [`Config::rpath`]
+    pub rpath: Option<bool>,
+    /// See [`Config::forward_warnings`]
+    pub forward_warnings: Option<bool>,
+    /// See [`Config::cache`]
+    pub cache: Option<bool>,
+    /// See [`Config::lock`]
+    pub lock: Option<bool>,
+    /// See [`Config::track_dependencies`]
+    pub track_dependencies: Option<bool>,
+    /// See [`Config::track_env`]
+    pub track_env: Vec<String>,
+    /// See [`Config::clear_env`]
+    pub clear_env: Option<bool>,
+    /// See [`Config::keep_env`]
+    pub keep_env: Vec<String>,
+    /// See [`Config::env`]
+    pub env: BTreeMap<String, String>,
+    /// See [`Config::git_ssh_command`]
+    pub git_ssh_command: Option<String>,
+    /// See [`Config::ssh_auth_sock`]
+    pub ssh_auth_sock: Option<PathBuf>,
+    /// See [`Config::forward_ssh_agent`]
+    pub forward_ssh_agent: Option<bool>,
+    /// See [`Config::log_file`]
+    pub log_file: Option<PathBuf>,
+    /// See [`Config::nice`]
+    pub nice: Option<i32>,
+    /// See [`Config::max_silent_time`]
+    pub max_silent_time: Option<u64>,
+    /// See [`Config::build_timeout`]
+    pub build_timeout: Option<u64>,
+    /// See [`Config::respect_cargo_jobs`]
+    pub respect_cargo_jobs: Option<bool>,
+    /// See [`Config::verify_outputs`]
+    pub verify_outputs: Option<bool>,
+    /// See [`Config::retries`]
+    pub retries: Option<u32>,
+    /// See [`Config::fallback`]
+    pub fallback: Option<bool>,
+    /// See [`Config::eval_cache`]
+    pub eval_cache: Option<bool>,
+    /// See [`Config::allow_ifd`]
+    pub allow_ifd: Option<bool>,
+    /// See [`Config::require_sigs`]
+    pub require_sigs: Option<bool>,
+    /// See [`Config::trusted_substituters_only`]
+    pub trusted_substituters_only: Option<bool>,
+    /// See [`Config::sandbox_paths`]
+    pub sandbox_paths: Option<Vec<String>>,
+    /// See [`Config::extra_sandbox_path`]
+    pub extra_sandbox_paths: Vec<PathBuf>,
+    /// See [`Config::use_cgroups`]
+    pub use_cgroups: Option<bool>,
+}
+
+impl ConfigSpec {
+    /// Resolve the relative paths of the specification from `dir`
+    pub(crate) fn relative_to(mut self, dir: &Path) -> Self {
+        for path in [
+            &mut self.file,
+            &mut self.out_link,
+            &mut self.log_file,
+            &mut self.store_root,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            *path = dir.join(&*path);
+        }
+        if self.flake.is_none() && self.file.is_none() && self.expr.is_none() {
+            self.file = Some(dir.join("default.nix"));
+        }
+        self
+    }
+}
+
+impl Config {
+    /// Create a new nix build [`Config`] from the given specification
+    ///
+    /// Fails with [`Error::InvalidConfig`] if more than one target is set
+    pub fn from_spec(spec: &ConfigSpec) -> Result<Self> {
+        let mut config = Self::new();
+
+        match (&spec.flake, &spec.file, &spec.expr) {
+            (Some(flake), None, None) => {
+                config.target_flake(flake);
+            }
+            (None, Some(file), None) => {
+                config.target_file(file);
+            }
+            (None, None, Some(expr)) => {
+                config.target_expr(expr);
+            }
+            (None, None, None) => {}
+            _ => {
+                return Err(Error::InvalidConfig(
+                    "only one of flake, file and expr can be set".to_owned(),
+                ))
+            }
+        }
+
+        if let Some(attr) = &spec.attribute {
+            config.attribute(attr);
+        }
+        for (name, value) in &spec.args {
+            config.arg_expr(name, value);
+        }
+        for (name, value) in &spec.argstrs {
+            config.arg_str(name, value);
+        }
+        for (name, flake_ref) in &spec.override_flakes {
+            config.override_flake(name, flake_ref);
+        }
+        if let Some(impure) = spec.impure {
+            config.impure(impure);
+        }
+        if !spec.outputs.is_empty() {
+            let names: Vec<&str> = spec.outputs.iter().map(String::as_str).collect();
+            config.outputs(&names);
+        }
+        if let Some(uri) = &spec.eval_store {
+            config.eval_store(uri);
+        }
+        if let Some(dir) = &spec.store_root {
+            config.store_root(dir);
+        }
+        if let Some(enabled) = spec.ca_derivations {
+            config.ca_derivations(enabled);
+        }
+        if let Some(system) = &spec.system {
+            config.system(system);
+        }
+        for url in &spec.substituters {
+            config.substituter(url);
+        }
+        for (name, value) in &spec.options {
+            config.option(name, value);
+        }
+        if let Some(out_link) = &spec.out_link {
+            config.out_link(out_link);
+        }
+        if let Some(enabled) = spec.gc_root {
+            config.gc_root(enabled);
+        }
+        if let Some(enabled) = spec.shared_out_link {
+            config.shared_out_link(enabled);
+        }
+        if let Some(enabled) = spec.write_manifest {
+            config.write_manifest(enabled);
+        }
+        if let Some(rpath) = spec.rpath {
+            config.rpath(rpath);
+        }
+        if let Some(forward) = spec.forward_warnings {
+            config.forward_warnings(forward);
+        }
+        if let Some(cache) = spec.cache {
+            config.cache(cache);
+        }
+        if let Some(lock) = spec.lock {
+            config.lock(lock);
+        }
+        if let Some(track) = spec.track_dependencies {
+            config.track_dependencies(track);
+        }
+        for name in &spec.track_env {
+            config.track_env(name);
+        }
+        if let Some(clear) = spec.clear_env {
+            config.clear_env(clear);
+        }
+        if !spec.keep_env.is_empty() {
+            let names: Vec<&str> = spec.keep_env.iter().map(String::as_str).collect();
+            config.keep_env(&names);
+        }
+        for (key, value) in &spec.env {
+            config.env(key, value);
+        }
+        if let Some(cmd) = &spec.git_ssh_command {
+            config.git_ssh_command(cmd);
+        }
+        if let Some(forward) = spec.forward_ssh_agent {
+            config.forward_ssh_agent(forward);
+        }
+        if let Some(socket) = &spec.ssh_auth_sock {
+            config.ssh_auth_sock(socket);
+        }
+        if let Some(path) = &spec.log_file {
+            config.log_file(path);
+        }
+        if let Some(level) = spec.nice {
+            config.nice(level);
+        }
+        if let Some(secs) = spec.max_silent_time {
+            config.max_silent_time(secs);
+        }
+        if let Some(secs) = spec.build_timeout {
+            config.build_timeout(secs);
+        }
+        if let Some(enabled) = spec.respect_cargo_jobs {
+            config.respect_cargo_jobs(enabled);
+        }
+        if let Some(enabled) = spec.verify_outputs {
+            config.verify_outputs(enabled);
+        }
+        if let Some(retries) = spec.retries {
+            config.retries(retries);
+        }
+        if let Some(enabled) = spec.fallback {
+            config.fallback(enabled);
+        }
+        if let Some(enabled) = spec.eval_cache {
+            config.eval_cache(enabled);
+        }
+        if let Some(allowed) = spec.allow_ifd {
+            config.allow_ifd(allowed);
+        }
+        if let Some(required) = spec.require_sigs {
+            config.require_sigs(required);
+        }
+        if let Some(enabled) = spec.trusted_substituters_only {
+            config.trusted_substituters_only(enabled);
+        }
+        if let Some(paths) = &spec.sandbox_paths {
+            let paths: Vec<&str> = paths.iter().map(String::as_str).collect();
+            config.sandbox_paths(&paths);
+        }
+        for path in &spec.extra_sandbox_paths {
+            config.extra_sandbox_path(path);
+        }
+        if let Some(enabled) = spec.use_cgroups {
+            config.use_cgroups(enabled);
+        }
+
+        Ok(config)
+    }
+}
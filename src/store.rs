@@ -0,0 +1,32 @@
+//! Queries and operations on the nix store
+
+mod store_path;
+pub use store_path::StorePath;
+pub(crate) use store_path::{BASE32_CHARS, HASH_LEN};
+
+pub(crate) mod path_info;
+pub use path_info::{path_info, path_infos, PathInfo};
+
+mod verify;
+pub use verify::{CorruptPath, Verification, Verify};
+
+mod closure;
+pub use closure::closure;
+
+mod why_depends;
+pub use why_depends::{why_depends, Dependency, Reference};
+
+mod diff_closures;
+pub use diff_closures::{diff_closures, ClosureChange};
+
+mod copy;
+pub use copy::CopyTo;
+
+mod sign;
+pub use sign::sign;
+
+pub(crate) mod gc;
+pub use gc::{add_root, clean_roots, delete, gc, list_roots, remove_crate_roots, GcResult, GcRoot};
+
+mod nar;
+pub use nar::{export_nar, import_nar};
@@ -0,0 +1,62 @@
+//! Runtime closures of store paths
+
+use std::{
+    collections::BTreeSet,
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+
+use crate::{command, version, Derivation, Result};
+
+/// Returns the runtime closure of the given store paths, including the paths themselves
+///
+/// Uses `nix path-info --recursive`, or `nix-store --query --requisites`
+/// when the unified nix CLI isn't available
+pub fn closure<I, P>(paths: I) -> Result<BTreeSet<PathBuf>>
+where
+    I: IntoIterator<Item = P>,
+    P: AsRef<Path>,
+{
+    let paths: Vec<OsString> = paths
+        .into_iter()
+        .map(|path| path.as_ref().as_os_str().to_owned())
+        .collect();
+
+    let stdout = if version::has_nix_command() {
+        let mut args: Vec<OsString> = vec!["path-info".into(), "--recursive".into()];
+        args.extend(paths);
+        command::nix_stdout(args)?
+    } else {
+        command::run(
+            command::legacy("nix-store")?
+                .args(["--query", "--requisites"])
+                .args(paths),
+        )?
+    };
+
+    Ok(String::from_utf8_lossy(&stdout)
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(PathBuf::from)
+        .collect())
+}
+
+impl Derivation {
+    /// Returns the runtime closure of all the outputs of this derivation
+    ///
+    /// # Example
+    /// ```no_run
+    /// # fn main() -> Result<(), nix_build::Error> {
+    /// let derivations = nix_build::Config::new()
+    ///     .target_flake("nixpkgs#openssl")
+    ///     .build()?;
+    ///
+    /// for path in derivations[0].closure()? {
+    ///     println!("copying {}", path.display());
+    /// }
+    /// # Ok(()) }
+    /// ```
+    pub fn closure(&self) -> Result<BTreeSet<PathBuf>> {
+        closure(self.outputs.values())
+    }
+}
@@ -0,0 +1,127 @@
+//! Copies of store paths to other stores, with `nix copy`
+
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+
+use crate::{command, Derivation, Result};
+
+/// Copies store paths to another store, like a binary cache
+///
+/// # Example
+/// ```no_run
+/// # fn main() -> Result<(), nix_build::Error> {
+/// use nix_build::store::CopyTo;
+///
+/// let derivations = nix_build::build()?;
+///
+/// CopyTo::new("s3://my-cache?region=eu-west-1")
+///     .secret_key_file("/etc/nix/cache-key.sec")
+///     .derivations(&derivations)
+///     .run()?;
+/// # Ok(()) }
+/// ```
+#[derive(Debug, Clone)]
+pub struct CopyTo {
+    to: String,
+    paths: Vec<PathBuf>,
+    check_sigs: bool,
+    secret_key_file: Option<PathBuf>,
+    substitute_on_destination: bool,
+}
+
+impl CopyTo {
+    /// Create a new [`CopyTo`] to the store at `store_uri`
+    pub fn new(store_uri: impl Into<String>) -> Self {
+        Self {
+            to: store_uri.into(),
+            paths: vec![],
+            check_sigs: true,
+            secret_key_file: None,
+            substitute_on_destination: false,
+        }
+    }
+
+    /// Add a store path to copy
+    pub fn path(&mut self, path: impl AsRef<Path>) -> &mut Self {
+        self.paths.push(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Add all the outputs of the given derivation
+    pub fn derivation(&mut self, derivation: &Derivation) -> &mut Self {
+        self.paths
+            .extend(derivation.outputs.values().map(|path| path.to_path_buf()));
+        self
+    }
+
+    /// Add all the outputs of the given derivations
+    pub fn derivations<'a>(
+        &mut self,
+        derivations: impl IntoIterator<Item = &'a Derivation>,
+    ) -> &mut Self {
+        for derivation in derivations {
+            self.derivation(derivation);
+        }
+        self
+    }
+
+    /// Set to require the paths to be signed by keys trusted by the destination
+    ///
+    /// Enabled by default, disable to pass `--no-check-sigs`
+    pub fn check_sigs(&mut self, check: bool) -> &mut Self {
+        self.check_sigs = check;
+        self
+    }
+
+    /// Sign the copied paths with the given secret key
+    ///
+    /// Passed as the `secret-key` parameter of the destination store URI,
+    /// supported by binary cache stores
+    pub fn secret_key_file(&mut self, path: impl Into<PathBuf>) -> &mut Self {
+        self.secret_key_file = Some(path.into());
+        self
+    }
+
+    /// Set to let the destination substitute missing paths from its own substituters
+    pub fn substitute_on_destination(&mut self, substitute: bool) -> &mut Self {
+        self.substitute_on_destination = substitute;
+        self
+    }
+
+    /// Returns the destination store URI, including the configured parameters
+    fn store_uri(&self) -> String {
+        match &self.secret_key_file {
+            Some(key) => {
+                let separator = if self.to.contains('?') { '&' } else { '?' };
+                format!("{}{separator}secret-key={}", self.to, key.display())
+            }
+            None => self.to.clone(),
+        }
+    }
+
+    /// Run `nix copy --to`
+    pub fn run(&self) -> Result<()> {
+        let mut args: Vec<OsString> = vec!["copy".into(), "--to".into(), self.store_uri().into()];
+        if !self.check_sigs {
+            args.push("--no-check-sigs".into());
+        }
+        if self.substitute_on_destination {
+            args.push("--substitute-on-destination".into());
+        }
+        args.extend(self.paths.iter().map(OsString::from));
+
+        command::nix_stdout(args)?;
+        Ok(())
+    }
+}
+
+impl Derivation {
+    /// CopyTo all the outputs of this derivation to the store at `store_uri`
+    ///
+    /// See [`CopyTo`]
+    pub fn copy_to(&self, store_uri: &str) -> Result<()> {
+        CopyTo::new(store_uri).derivation(self).run()
+    }
+}
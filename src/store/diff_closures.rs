@@ -0,0 +1,142 @@
+//! Differences between closures, from `nix store diff-closures`
+
+use std::{ffi::OsString, path::Path};
+
+use crate::{build_log::strip_ansi, command, Result};
+
+/// Change of a package between two closures
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClosureChange {
+    /// Package name, like `openssl`
+    pub package: String,
+    /// Versions in the old closure, empty if the package was added
+    ///
+    /// Both versions are empty when only the size changed
+    pub old_versions: Vec<String>,
+    /// Versions in the new closure, empty if the package was removed
+    pub new_versions: Vec<String>,
+    /// Size difference in bytes
+    ///
+    /// Nix only reports differences larger than 8 KiB, smaller ones are `0`
+    pub size_delta: i64,
+}
+
+impl ClosureChange {
+    /// Parse a line like `bash: 4.4-p23 → 5.0-p17, +1.2 KiB`
+    fn parse(line: &str) -> Option<Self> {
+        let (package, rest) = line.split_once(": ")?;
+
+        let (versions, size) = match rest.rsplit_once(", ") {
+            Some((versions, size)) if size.ends_with(" KiB") => (Some(versions), Some(size)),
+            _ if rest.ends_with(" KiB") => (None, Some(rest)),
+            _ => (Some(rest), None),
+        };
+
+        let (old_versions, new_versions) = match versions {
+            Some(versions) => {
+                let (old, new) = versions.split_once(" → ")?;
+                (parse_versions(old), parse_versions(new))
+            }
+            None => (vec![], vec![]),
+        };
+
+        let size_delta = match size {
+            Some(size) => {
+                let kib: f64 = size.trim_end_matches(" KiB").parse().ok()?;
+                (kib * 1024.0).round() as i64
+            }
+            None => 0,
+        };
+
+        Some(Self {
+            package: package.to_owned(),
+            old_versions,
+            new_versions,
+            size_delta,
+        })
+    }
+}
+
+/// Parse a list of versions like `1.0, 1.1`, where `∅` means none and `ε` an empty version
+fn parse_versions(versions: &str) -> Vec<String> {
+    if versions == "∅" {
+        return vec![];
+    }
+
+    versions
+        .split(", ")
+        .map(|version| if version == "ε" { "" } else { version })
+        .map(str::to_owned)
+        .collect()
+}
+
+/// Returns the package changes between the closures of the store paths `old` and `new`
+///
+/// # Example
+/// ```no_run
+/// # fn main() -> Result<(), nix_build::Error> {
+/// let changes =
+///     nix_build::store::diff_closures("/nix/store/...-app-1.0", "/nix/store/...-app-1.1")?;
+///
+/// for change in changes {
+///     println!(
+///         "{}: {:?} -> {:?} ({:+} bytes)",
+///         change.package, change.old_versions, change.new_versions, change.size_delta
+///     );
+/// }
+/// # Ok(()) }
+/// ```
+pub fn diff_closures(old: impl AsRef<Path>, new: impl AsRef<Path>) -> Result<Vec<ClosureChange>> {
+    let args: [OsString; 4] = [
+        "store".into(),
+        "diff-closures".into(),
+        old.as_ref().into(),
+        new.as_ref().into(),
+    ];
+
+    // size differences are colored, even when not printed to a terminal
+    let stdout = command::nix_stdout(args)?;
+    Ok(strip_ansi(&String::from_utf8_lossy(&stdout))
+        .lines()
+        .filter_map(ClosureChange::parse)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn package_changes() {
+        let stdout = "\
+bash: 5.1-p16 → 5.2-p15, \x1b[31;1m+12.5 KiB\x1b[0m
+glibc: 2.35-224 → 2.37-8, \x1b[32;1m-320.1 KiB\x1b[0m
+libunistring: ∅ → 1.1, \x1b[31;1m+1786.4 KiB\x1b[0m
+readline: 8.1p2 → ∅
+openssl: \x1b[31;1m+9.0 KiB\x1b[0m
+source: ε → 1.0, ε
+";
+        let changes: Vec<ClosureChange> = strip_ansi(stdout)
+            .lines()
+            .filter_map(ClosureChange::parse)
+            .collect();
+
+        let change = |package: &str, old: &[&str], new: &[&str], size_delta| ClosureChange {
+            package: package.to_owned(),
+            old_versions: old.iter().map(|v| v.to_string()).collect(),
+            new_versions: new.iter().map(|v| v.to_string()).collect(),
+            size_delta,
+        };
+        assert_eq!(
+            changes,
+            [
+                change("bash", &["5.1-p16"], &["5.2-p15"], 12800),
+                change("glibc", &["2.35-224"], &["2.37-8"], -327782),
+                change("libunistring", &[], &["1.1"], 1829274),
+                change("readline", &["8.1p2"], &[], 0),
+                change("openssl", &[], &[], 9216),
+                change("source", &[""], &["1.0", ""], 0),
+            ]
+        );
+    }
+}
@@ -0,0 +1,274 @@
+//! Garbage collection and GC roots
+
+use std::{
+    collections::HashMap,
+    ffi::OsString,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::{command, Error, Result};
+
+/// Prefix of the names of the GC roots created by [`Config::gc_root`](crate::Config::gc_root)
+pub(crate) const ROOT_PREFIX: &str = "nix-build-root-";
+
+/// Result of a garbage collection
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GcResult {
+    /// Number of store paths deleted
+    pub paths_deleted: u64,
+    /// Approximate number of bytes freed
+    pub bytes_freed: u64,
+}
+
+impl GcResult {
+    /// Parse the summary line printed by nix, like `12 store paths deleted, 34.56 MiB freed`
+    fn parse(output: &str) -> Result<Self> {
+        let line = output
+            .lines()
+            .rev()
+            .find(|line| line.contains(" freed"))
+            .ok_or(Error::UnknownOutput)?;
+
+        let paths_deleted = line
+            .split_whitespace()
+            .next()
+            .and_then(|count| count.parse().ok())
+            .ok_or(Error::UnknownOutput)?;
+
+        let bytes_freed = line
+            .rsplit_once(", ")
+            .and_then(|(_, freed)| {
+                let mut parts = freed.split_whitespace();
+                let value: f64 = parts.next()?.parse().ok()?;
+                let unit: u32 = match parts.next()? {
+                    "B" | "bytes" => 0,
+                    "KiB" => 1,
+                    "MiB" => 2,
+                    "GiB" => 3,
+                    "TiB" => 4,
+                    _ => return None,
+                };
+                Some((value * 1024f64.powi(unit as i32)) as u64)
+            })
+            .ok_or(Error::UnknownOutput)?;
+
+        Ok(Self {
+            paths_deleted,
+            bytes_freed,
+        })
+    }
+}
+
+/// Delete unreachable store paths, stopping after `max_freed` bytes if set
+///
+/// # Example
+/// ```no_run
+/// # fn main() -> Result<(), nix_build::Error> {
+/// let result = nix_build::store::gc(Some(10 * 1024 * 1024 * 1024))?;
+/// println!("freed {} bytes", result.bytes_freed);
+/// # Ok(()) }
+/// ```
+pub fn gc(max_freed: Option<u64>) -> Result<GcResult> {
+    let mut cmd = command::legacy("nix-store")?;
+    cmd.arg("--gc");
+    if let Some(max) = max_freed {
+        cmd.args(["--max-freed", &max.to_string()]);
+    }
+
+    debug!("running {cmd:?}");
+    let output = cmd.output()?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() {
+        return Err(Error::CommandFailed {
+            program: "nix-store --gc".to_owned(),
+            stderr: stderr.into_owned(),
+        });
+    }
+
+    // the summary is printed to stderr, or stdout by older versions
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    GcResult::parse(&format!("{stdout}\n{stderr}"))
+}
+
+/// Delete the given store paths, failing if any of them is still reachable
+pub fn delete<I, P>(paths: I) -> Result<()>
+where
+    I: IntoIterator<Item = P>,
+    P: AsRef<Path>,
+{
+    let mut args: Vec<OsString> = vec!["store".into(), "delete".into()];
+    args.extend(
+        paths
+            .into_iter()
+            .map(|path| path.as_ref().as_os_str().to_owned()),
+    );
+
+    command::nix_stdout(args)?;
+    Ok(())
+}
+
+/// Symlink preventing a store path from being garbage collected
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GcRoot {
+    /// Path of the symlink
+    pub link: PathBuf,
+    /// Store path kept alive
+    pub target: PathBuf,
+}
+
+/// Returns the GC roots registered in the store
+///
+/// Roots held by running processes are omitted
+pub fn list_roots() -> Result<Vec<GcRoot>> {
+    let stdout = command::run(command::legacy("nix-store")?.args(["--gc", "--print-roots"]))?;
+
+    Ok(String::from_utf8_lossy(&stdout)
+        .lines()
+        .filter_map(|line| line.split_once(" -> "))
+        .filter(|(link, _)| link.starts_with('/') && !link.starts_with("/proc/"))
+        .map(|(link, target)| GcRoot {
+            link: PathBuf::from(link),
+            target: PathBuf::from(target),
+        })
+        .collect())
+}
+
+/// Register `link` as a GC root pointing to the store path `path`
+pub fn add_root(path: impl AsRef<Path>, link: impl AsRef<Path>) -> Result<()> {
+    let link = link.as_ref();
+    if let Some(dir) = link.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+
+    command::run(
+        command::legacy("nix-store")?
+            .arg("--add-root")
+            .arg(link)
+            .arg("--realise")
+            .arg(path.as_ref()),
+    )?;
+    Ok(())
+}
+
+/// Remove all the GC roots created by [`Config::gc_root`](crate::Config::gc_root)
+///
+/// Returns the removed links. The store paths they kept alive are freed by the next [`gc`]
+pub fn remove_crate_roots() -> Result<Vec<PathBuf>> {
+    let mut removed = vec![];
+
+    for root in list_roots()? {
+        let ours = root
+            .link
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.starts_with(ROOT_PREFIX));
+
+        if ours && root.link.symlink_metadata().is_ok() {
+            std::fs::remove_file(&root.link)?;
+            removed.push(root.link);
+        }
+    }
+
+    Ok(removed)
+}
+
+/// Remove the GC roots created by [`Config::gc_root`](crate::Config::gc_root) in `dir`,
+/// except for the ones of the `keep_latest` configurations built most recently
+///
+/// Roots are refreshed on every build of their configuration, cached or not, so the roots of
+/// configurations that changed since stop being refreshed and are removed eventually.
+/// Returns the removed links. The store paths they kept alive are freed by the next [`gc`]
+///
+/// # Example
+/// ```no_run
+/// # fn main() -> Result<(), nix_build::Error> {
+/// // keep the roots of the 4 configurations of the workspace
+/// let removed = nix_build::store::clean_roots("target/debug/nix-gc-roots", 4)?;
+/// println!("removed {} stale roots", removed.len());
+/// # Ok(()) }
+/// ```
+pub fn clean_roots(dir: impl AsRef<Path>, keep_latest: usize) -> Result<Vec<PathBuf>> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(err.into()),
+    };
+
+    // links of the same configuration, named `<prefix><fingerprint>[-<n>][-<output>]`
+    let mut configs: HashMap<String, (SystemTime, Vec<PathBuf>)> = HashMap::new();
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name();
+        let Some(fingerprint) = name
+            .to_str()
+            .and_then(|name| name.strip_prefix(ROOT_PREFIX))
+            .map(|rest| rest.split('-').next().unwrap_or(rest))
+        else {
+            continue;
+        };
+
+        let modified = entry.path().symlink_metadata()?.modified()?;
+        let (latest, links) = configs
+            .entry(fingerprint.to_owned())
+            .or_insert((SystemTime::UNIX_EPOCH, vec![]));
+        *latest = (*latest).max(modified);
+        links.push(entry.path());
+    }
+
+    let mut configs: Vec<(SystemTime, Vec<PathBuf>)> = configs.into_values().collect();
+    configs.sort_by_key(|(modified, _)| std::cmp::Reverse(*modified));
+
+    let mut removed: Vec<PathBuf> = configs
+        .into_iter()
+        .skip(keep_latest)
+        .flat_map(|(_, links)| links)
+        .collect();
+    removed.sort();
+
+    for link in &removed {
+        debug!("removing stale GC root {}", link.display());
+        std::fs::remove_file(link)?;
+    }
+
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summary() {
+        let output = "
+finding garbage collector roots...
+removing stale link from '/nix/var/nix/gcroots/auto/3y6hzxhfmxp2l8sd3b1n4smr5vcx4mdv' to '/tmp/result'
+deleting garbage...
+deleting '/nix/store/0c8ybjvkn0jxx2xy7yv5c3d7zg2g3yy2-hello-2.12.1'
+deleting '/nix/store/vkb3h6vb2nhcbmmp7zs1w2n0x1i8sv3l-hello-2.12.1.drv'
+deleting unused links...
+note: currently hard linking saves 12.34 MiB
+2 store paths deleted, 1.50 MiB freed
+";
+        assert_eq!(
+            GcResult::parse(output).unwrap(),
+            GcResult {
+                paths_deleted: 2,
+                bytes_freed: 1572864,
+            }
+        );
+
+        let nothing = "0 store paths deleted, 0.00 MiB freed\n";
+        assert_eq!(GcResult::parse(nothing).unwrap(), GcResult::default());
+
+        let interrupted = "finding garbage collector roots...\ndeleting garbage...\n";
+        assert!(matches!(
+            GcResult::parse(interrupted),
+            Err(Error::UnknownOutput)
+        ));
+        assert!(matches!(
+            GcResult::parse("2 store paths deleted, 1.50 PiB freed"),
+            Err(Error::UnknownOutput)
+        ));
+    }
+}
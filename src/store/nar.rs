@@ -0,0 +1,76 @@
+//! Archives of store paths, with `nix-store --export` and `--import`
+
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+    process::Stdio,
+};
+
+use crate::{command, store, Derivation, Error, Result};
+
+/// Export the runtime closure of the given store paths to the archive `file`
+///
+/// The archive can be imported on another machine with [`import_nar`],
+/// without access to any substituter
+pub fn export_nar<I, P>(paths: I, file: impl AsRef<Path>) -> Result<()>
+where
+    I: IntoIterator<Item = P>,
+    P: AsRef<Path>,
+{
+    let closure = store::closure(paths)?;
+    let archive = File::create(file)?;
+
+    let mut cmd = command::legacy("nix-store")?;
+    cmd.arg("--export")
+        .args(closure)
+        .stdout(Stdio::from(archive));
+    debug!("running {cmd:?}");
+
+    let output = cmd.output()?;
+    if !output.status.success() {
+        return Err(Error::CommandFailed {
+            program: "nix-store --export".to_owned(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        });
+    }
+
+    Ok(())
+}
+
+/// Import the store paths in the archive `file`, created by [`export_nar`]
+///
+/// Returns the imported paths. The paths must be signed by a trusted key,
+/// unless the current user is trusted by the nix daemon
+pub fn import_nar(file: impl AsRef<Path>) -> Result<Vec<PathBuf>> {
+    let archive = File::open(file)?;
+
+    let stdout = command::run(
+        command::legacy("nix-store")?
+            .arg("--import")
+            .stdin(Stdio::from(archive)),
+    )?;
+
+    Ok(String::from_utf8_lossy(&stdout)
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(PathBuf::from)
+        .collect())
+}
+
+impl Derivation {
+    /// Export the outputs of this derivation, along with their runtime closure, to `file`
+    ///
+    /// # Example
+    /// ```no_run
+    /// # fn main() -> Result<(), nix_build::Error> {
+    /// let derivations = nix_build::build()?;
+    /// derivations[0].export_nar("artifacts/native-deps.closure")?;
+    ///
+    /// // on the air-gapped builder
+    /// nix_build::store::import_nar("artifacts/native-deps.closure")?;
+    /// # Ok(()) }
+    /// ```
+    pub fn export_nar(&self, file: impl AsRef<Path>) -> Result<()> {
+        export_nar(self.outputs.values(), file)
+    }
+}
@@ -0,0 +1,219 @@
+//! Metadata of store paths, from `nix path-info`
+
+use std::{
+    collections::HashMap,
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+
+use crate::{command, Error, Result};
+
+/// Metadata of a valid store path
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PathInfo {
+    /// Store path
+    #[serde(default)]
+    pub path: PathBuf,
+    /// Hash of the NAR serialization of the path
+    #[serde(default)]
+    pub nar_hash: Option<String>,
+    /// Size in bytes of the NAR serialization of the path
+    #[serde(default)]
+    pub nar_size: u64,
+    /// Size in bytes of the NAR serialization of the path and its whole runtime closure
+    ///
+    /// Only reported when requested
+    #[serde(default)]
+    pub closure_size: Option<u64>,
+    /// Store paths referenced at runtime
+    #[serde(default)]
+    pub references: Vec<PathBuf>,
+    /// Derivation that built the path, if known
+    #[serde(default)]
+    pub deriver: Option<PathBuf>,
+    /// Signatures of the path, like `cache.nixos.org-1:...`
+    #[serde(default)]
+    pub signatures: Vec<String>,
+}
+
+/// `nix path-info --json` returns an array before 2.19, and an object keyed by path after
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum PathInfos {
+    List(Vec<ListedPathInfo>),
+    Map(HashMap<PathBuf, Option<PathInfo>>),
+}
+
+/// Entry of the array printed before 2.19, where invalid paths are marked with `"valid": false`
+#[derive(serde::Deserialize)]
+struct ListedPathInfo {
+    #[serde(default)]
+    valid: Option<bool>,
+    #[serde(flatten)]
+    info: PathInfo,
+}
+
+/// Query the metadata of the given store paths, in order
+///
+/// When `recursive` is set, the metadata of their runtime closure follows, sorted by path.
+/// Invalid paths are skipped
+pub(crate) fn query(
+    paths: &[PathBuf],
+    closure_size: bool,
+    recursive: bool,
+) -> Result<Vec<PathInfo>> {
+    let mut args: Vec<OsString> = vec!["path-info".into(), "--json".into()];
+    if closure_size {
+        args.push("--closure-size".into());
+    }
+    if recursive {
+        args.push("--recursive".into());
+    }
+    args.extend(paths.iter().map(OsString::from));
+
+    parse(&command::nix_stdout(args)?, paths)
+}
+
+/// Parse the output of `nix path-info --json` for the given store paths, skipping invalid ones
+fn parse(json: &[u8], paths: &[PathBuf]) -> Result<Vec<PathInfo>> {
+    let infos = match serde_json::from_slice(json).map_err(|_| Error::UnknownOutput)? {
+        PathInfos::List(infos) => infos
+            .into_iter()
+            .enumerate()
+            .filter(|(_, listed)| listed.valid != Some(false))
+            .filter_map(|(i, ListedPathInfo { mut info, .. })| {
+                if info.path.as_os_str().is_empty() {
+                    info.path = paths.get(i)?.clone();
+                }
+                Some(info)
+            })
+            .collect(),
+        PathInfos::Map(mut infos) => {
+            let mut ordered: Vec<PathInfo> = paths
+                .iter()
+                .filter_map(|path| Some((path, infos.remove(path)??)))
+                .map(|(path, mut info)| {
+                    info.path = path.clone();
+                    info
+                })
+                .collect();
+
+            let mut rest: Vec<PathInfo> = infos
+                .into_iter()
+                .filter_map(|(path, info)| {
+                    let mut info = info?;
+                    info.path = path;
+                    Some(info)
+                })
+                .collect();
+            rest.sort_by(|a, b| a.path.cmp(&b.path));
+
+            ordered.extend(rest);
+            ordered
+        }
+    };
+
+    Ok(infos)
+}
+
+/// Returns whether the output of `nix path-info --json` lists a valid path
+///
+/// Invalid paths are `null` in the object printed since 2.19,
+/// and marked with `"valid": false` in the array printed before
+pub(crate) fn lists_valid(json: &[u8]) -> Result<bool> {
+    let infos: serde_json::Value =
+        serde_json::from_slice(json).map_err(|_| Error::UnknownOutput)?;
+    Ok(match infos {
+        serde_json::Value::Array(infos) => infos
+            .iter()
+            .any(|info| info.get("valid") != Some(&serde_json::Value::Bool(false))),
+        serde_json::Value::Object(infos) => infos.values().any(|info| !info.is_null()),
+        _ => return Err(Error::UnknownOutput),
+    })
+}
+
+/// Returns the metadata of the given store paths, including their closure sizes
+///
+/// # Example
+/// ```no_run
+/// # fn main() -> Result<(), nix_build::Error> {
+/// const BUDGET: u64 = 200 * 1024 * 1024;
+///
+/// let derivations = nix_build::build()?;
+/// let outputs: Vec<_> = derivations[0].outputs.values().collect();
+///
+/// for info in nix_build::store::path_infos(outputs)? {
+///     if info.closure_size.unwrap_or_default() > BUDGET {
+///         println!("cargo:warning={} exceeds the closure size budget", info.path.display());
+///     }
+/// }
+/// # Ok(()) }
+/// ```
+pub fn path_infos<I, P>(paths: I) -> Result<Vec<PathInfo>>
+where
+    I: IntoIterator<Item = P>,
+    P: AsRef<Path>,
+{
+    let paths: Vec<PathBuf> = paths
+        .into_iter()
+        .map(|path| path.as_ref().to_path_buf())
+        .collect();
+
+    query(&paths, true, false)
+}
+
+/// Returns the metadata of the given store path, including its closure size
+///
+/// Fails with [`Error::OutputMissing`] if the path isn't valid
+pub fn path_info(path: impl AsRef<Path>) -> Result<PathInfo> {
+    let path = path.as_ref();
+
+    path_infos([path])?
+        .pop()
+        .ok_or_else(|| Error::OutputMissing(path.to_path_buf()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HELLO: &str = "/nix/store/0c8ybjvkn0jxx2xy7yv5c3d7zg2g3yy2-hello-2.12.1";
+    const GLIBC: &str = "/nix/store/9w0bd6yv5m0gxvwryjfxvjvr6ch0bqsm-glibc-2.37-8";
+    const MISSING: &str = "/nix/store/vkb3h6vb2nhcbmmp7zs1w2n0x1i8sv3l-missing-1.0";
+
+    #[test]
+    fn list_before_2_19() {
+        let json = br#"[{"path":"/nix/store/0c8ybjvkn0jxx2xy7yv5c3d7zg2g3yy2-hello-2.12.1","narHash":"sha256:1mkvday29m2qxg1fnbv8xh9s6151bh8a2xzhh0k86j7lqhyfwibh","narSize":226560,"references":["/nix/store/0c8ybjvkn0jxx2xy7yv5c3d7zg2g3yy2-hello-2.12.1","/nix/store/9w0bd6yv5m0gxvwryjfxvjvr6ch0bqsm-glibc-2.37-8"],"deriver":"/nix/store/2l6ww0f0lb4kxs3bqhdzpljzjxvhc6kb-hello-2.12.1.drv","signatures":["cache.nixos.org-1:BX5Kk2RN6qSUXa9XgSVzgwLlm1hWGt6Ss7pSMqTvKoHKPuvIFYELqQGQm/iYKhxvtKBQoYgvoLD1/2Wl2Y3jAw=="],"valid":true,"closureSize":32169856},{"path":"/nix/store/vkb3h6vb2nhcbmmp7zs1w2n0x1i8sv3l-missing-1.0","valid":false}]"#;
+
+        let infos = parse(json, &[HELLO.into(), MISSING.into()]).unwrap();
+        assert_eq!(infos.len(), 1);
+        assert_eq!(infos[0].path, Path::new(HELLO));
+        assert_eq!(infos[0].nar_size, 226560);
+        assert_eq!(infos[0].closure_size, Some(32169856));
+        assert_eq!(infos[0].references[1], Path::new(GLIBC));
+        assert_eq!(infos[0].signatures.len(), 1);
+
+        assert!(lists_valid(json).unwrap());
+        assert!(!lists_valid(br#"[{"path":"/nix/store/vkb3h6vb2nhcbmmp7zs1w2n0x1i8sv3l-missing-1.0","valid":false}]"#).unwrap());
+    }
+
+    #[test]
+    fn object_since_2_19() {
+        let json = br#"{"/nix/store/0c8ybjvkn0jxx2xy7yv5c3d7zg2g3yy2-hello-2.12.1":{"ca":null,"closureSize":32169856,"deriver":"/nix/store/2l6ww0f0lb4kxs3bqhdzpljzjxvhc6kb-hello-2.12.1.drv","narHash":"sha256-Tyr2UDAf/V8Mc6MNUi5a1pp4IbDo1b0nK5Wm0flC0vU=","narSize":226560,"references":["/nix/store/0c8ybjvkn0jxx2xy7yv5c3d7zg2g3yy2-hello-2.12.1","/nix/store/9w0bd6yv5m0gxvwryjfxvjvr6ch0bqsm-glibc-2.37-8"],"registrationTime":1718200042,"signatures":[],"ultimate":false},"/nix/store/vkb3h6vb2nhcbmmp7zs1w2n0x1i8sv3l-missing-1.0":null}"#;
+
+        let infos = parse(json, &[MISSING.into(), HELLO.into()]).unwrap();
+        assert_eq!(infos.len(), 1);
+        assert_eq!(infos[0].path, Path::new(HELLO));
+        assert_eq!(
+            infos[0].nar_hash.as_deref(),
+            Some("sha256-Tyr2UDAf/V8Mc6MNUi5a1pp4IbDo1b0nK5Wm0flC0vU=")
+        );
+
+        assert!(lists_valid(json).unwrap());
+        assert!(!lists_valid(
+            br#"{"/nix/store/vkb3h6vb2nhcbmmp7zs1w2n0x1i8sv3l-missing-1.0":null}"#
+        )
+        .unwrap());
+    }
+}
@@ -0,0 +1,47 @@
+//! Signing store paths with a local key, with `nix store sign`
+
+use std::{ffi::OsString, path::Path};
+
+use crate::{command, Derivation, Result};
+
+/// Sign the given store paths with the secret key in `key_file`
+///
+/// When `recursive` is set, their runtime closure is signed as well
+pub fn sign<I, P>(paths: I, key_file: impl AsRef<Path>, recursive: bool) -> Result<()>
+where
+    I: IntoIterator<Item = P>,
+    P: AsRef<Path>,
+{
+    let mut args: Vec<OsString> = vec![
+        "store".into(),
+        "sign".into(),
+        "--key-file".into(),
+        key_file.as_ref().into(),
+    ];
+    if recursive {
+        args.push("--recursive".into());
+    }
+    args.extend(
+        paths
+            .into_iter()
+            .map(|path| path.as_ref().as_os_str().to_owned()),
+    );
+
+    command::nix_stdout(args)?;
+    Ok(())
+}
+
+impl Derivation {
+    /// Sign all the outputs of this derivation with the secret key in `key_file`
+    ///
+    /// # Example
+    /// ```no_run
+    /// # fn main() -> Result<(), nix_build::Error> {
+    /// let derivations = nix_build::build()?;
+    /// derivations[0].sign("/etc/nix/cache-key.sec")?;
+    /// # Ok(()) }
+    /// ```
+    pub fn sign(&self, key_file: impl AsRef<Path>) -> Result<()> {
+        sign(self.outputs.values(), key_file, false)
+    }
+}
@@ -0,0 +1,153 @@
+//! Validated paths of the nix store
+
+use std::{
+    ffi::OsStr,
+    fmt,
+    ops::Deref,
+    path::{Path, PathBuf},
+};
+
+use crate::{Error, Result};
+
+/// Length of the hash part of store paths, in nix base32 characters
+pub(crate) const HASH_LEN: usize = 32;
+
+/// Characters of the nix base32 encoding, which omits `e`, `o`, `u` and `t`
+pub(crate) const BASE32_CHARS: &str = "0123456789abcdfghijklmnpqrsvwxyz";
+
+/// Path of the nix store, like `/nix/store/<hash>-<name>`
+///
+/// Dereferences to [`Path`], so it can be used like the path of a file
+///
+/// # Example
+/// ```
+/// use nix_build::store::StorePath;
+///
+/// let path = StorePath::new("/nix/store/7mjh6jsk2dlzzdbl5wdr3ny0mh0p5b3k-hello-2.12.1").unwrap();
+/// assert_eq!(path.hash(), "7mjh6jsk2dlzzdbl5wdr3ny0mh0p5b3k");
+/// assert_eq!(path.name(), "hello-2.12.1");
+/// assert!(path.join("bin/hello").ends_with("bin/hello"));
+///
+/// assert!(StorePath::new("/usr/bin/hello").is_err());
+/// ```
+#[derive(
+    Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize, serde::Deserialize,
+)]
+#[serde(try_from = "PathBuf", into = "PathBuf")]
+pub struct StorePath(PathBuf);
+
+impl StorePath {
+    /// Validate that `path` is an absolute path shaped like `<store>/<hash>-<name>`
+    ///
+    /// Fails with [`Error::InvalidStorePath`] otherwise
+    pub fn new(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+
+        let valid = path.is_absolute()
+            && path
+                .file_name()
+                .and_then(OsStr::to_str)
+                .and_then(|base| base.split_once('-'))
+                .is_some_and(|(hash, name)| {
+                    hash.len() == HASH_LEN
+                        && hash.chars().all(|c| BASE32_CHARS.contains(c))
+                        && !name.is_empty()
+                });
+
+        if valid {
+            Ok(Self(path))
+        } else {
+            Err(Error::InvalidStorePath(path))
+        }
+    }
+
+    fn base_name(&self) -> &str {
+        // validated on construction
+        self.0
+            .file_name()
+            .and_then(OsStr::to_str)
+            .unwrap_or_default()
+    }
+
+    /// Returns the hash part of the path, like `7mjh6jsk2dlzzdbl5wdr3ny0mh0p5b3k`
+    pub fn hash(&self) -> &str {
+        &self.base_name()[..HASH_LEN]
+    }
+
+    /// Returns the name part of the path, like `hello-2.12.1` or `hello-2.12.1.drv`
+    pub fn name(&self) -> &str {
+        &self.base_name()[HASH_LEN + 1..]
+    }
+
+    /// Returns the directory of the store, like `/nix/store`
+    pub fn store_dir(&self) -> &Path {
+        self.0.parent().unwrap_or(Path::new("/"))
+    }
+
+    /// Returns whether the path exists on the local filesystem
+    pub fn exists(&self) -> bool {
+        self.0.exists()
+    }
+
+    /// Returns the path as a [`Path`]
+    pub fn as_path(&self) -> &Path {
+        &self.0
+    }
+
+    /// Converts the store path into a [`PathBuf`]
+    pub fn into_path_buf(self) -> PathBuf {
+        self.0
+    }
+}
+
+impl Deref for StorePath {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for StorePath {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<OsStr> for StorePath {
+    fn as_ref(&self) -> &OsStr {
+        self.0.as_os_str()
+    }
+}
+
+impl fmt::Display for StorePath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.display().fmt(f)
+    }
+}
+
+impl TryFrom<PathBuf> for StorePath {
+    type Error = Error;
+
+    fn try_from(path: PathBuf) -> Result<Self> {
+        Self::new(path)
+    }
+}
+
+impl From<StorePath> for PathBuf {
+    fn from(path: StorePath) -> Self {
+        path.0
+    }
+}
+
+impl PartialEq<Path> for StorePath {
+    fn eq(&self, other: &Path) -> bool {
+        self.0 == other
+    }
+}
+
+impl PartialEq<PathBuf> for StorePath {
+    fn eq(&self, other: &PathBuf) -> bool {
+        &self.0 == other
+    }
+}
@@ -0,0 +1,242 @@
+//! Integrity checks of store paths, with `nix store verify`
+
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+
+use crate::{command, version, Derivation, Error, Result};
+
+/// Exit status bit set by `nix store verify` when some paths are corrupt
+const STATUS_CORRUPT: i32 = 1;
+/// Exit status bit set by `nix store verify` when some paths are untrusted
+const STATUS_UNTRUSTED: i32 = 2;
+
+/// Store path whose content doesn't match the hash registered in the store
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CorruptPath {
+    pub path: PathBuf,
+    /// Registered NAR hash
+    pub expected: String,
+    /// Actual NAR hash of the content
+    pub got: String,
+}
+
+/// Result of a store verification
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Verification {
+    /// Paths whose content has been modified
+    pub corrupt: Vec<CorruptPath>,
+    /// Paths lacking enough trusted signatures
+    pub untrusted: Vec<PathBuf>,
+}
+
+impl Verification {
+    /// Returns whether all the paths have been verified successfully
+    pub fn is_ok(&self) -> bool {
+        self.corrupt.is_empty() && self.untrusted.is_empty()
+    }
+
+    /// Parse the error output of `nix store verify`
+    fn parse(stderr: &str) -> Self {
+        let mut verification = Self::default();
+
+        for line in stderr.lines().map(str::trim) {
+            let mut quoted = line.split('\'').skip(1).step_by(2);
+            let Some(path) = quoted.next().map(PathBuf::from) else {
+                continue;
+            };
+
+            if line.contains("was modified!") {
+                if let Some((expected, got)) = quoted.next().zip(quoted.next()) {
+                    verification.corrupt.push(CorruptPath {
+                        path,
+                        expected: expected.to_owned(),
+                        got: got.to_owned(),
+                    });
+                }
+            } else if line.ends_with("is untrusted") {
+                verification.untrusted.push(path);
+            }
+        }
+
+        verification
+    }
+}
+
+/// Verifies the integrity and trust of store paths
+///
+/// # Example
+/// ```no_run
+/// # fn main() -> Result<(), nix_build::Error> {
+/// use nix_build::store::Verify;
+///
+/// let derivations = nix_build::build()?;
+/// let verification = Verify::new()
+///     .derivations(&derivations)
+///     .recursive(true)
+///     .run()?;
+///
+/// for path in &verification.untrusted {
+///     println!("cargo:warning={} is not signed by a trusted key", path.display());
+/// }
+/// # Ok(()) }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Verify {
+    paths: Vec<PathBuf>,
+    recursive: bool,
+    contents: bool,
+    trust: bool,
+    sigs_needed: Option<usize>,
+}
+
+impl Default for Verify {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Verify {
+    /// Create a new [`Verify`] checking both contents and signatures
+    pub fn new() -> Self {
+        Self {
+            paths: vec![],
+            recursive: false,
+            contents: true,
+            trust: true,
+            sigs_needed: None,
+        }
+    }
+
+    /// Add a store path to verify
+    pub fn path(&mut self, path: impl AsRef<Path>) -> &mut Self {
+        self.paths.push(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Add all the outputs of the given derivation
+    pub fn derivation(&mut self, derivation: &Derivation) -> &mut Self {
+        self.paths
+            .extend(derivation.outputs.values().map(|path| path.to_path_buf()));
+        self
+    }
+
+    /// Add all the outputs of the given derivations
+    pub fn derivations<'a>(
+        &mut self,
+        derivations: impl IntoIterator<Item = &'a Derivation>,
+    ) -> &mut Self {
+        for derivation in derivations {
+            self.derivation(derivation);
+        }
+        self
+    }
+
+    /// Set to also verify the runtime closure of the paths
+    pub fn recursive(&mut self, recursive: bool) -> &mut Self {
+        self.recursive = recursive;
+        self
+    }
+
+    /// Set to check the content of the paths against their registered hash
+    ///
+    /// Enabled by default
+    pub fn contents(&mut self, contents: bool) -> &mut Self {
+        self.contents = contents;
+        self
+    }
+
+    /// Set to check the paths are signed by trusted keys
+    ///
+    /// Enabled by default
+    pub fn trust(&mut self, trust: bool) -> &mut Self {
+        self.trust = trust;
+        self
+    }
+
+    /// Set the number of trusted signatures required for each path
+    pub fn sigs_needed(&mut self, count: usize) -> &mut Self {
+        self.sigs_needed = Some(count);
+        self
+    }
+
+    /// Run `nix store verify`
+    ///
+    /// Corrupt and untrusted paths are reported in the returned [`Verification`],
+    /// any other failure results in [`Error::CommandFailed`]
+    pub fn run(&self) -> Result<Verification> {
+        let mut args: Vec<OsString> = vec!["store".into(), "verify".into()];
+        if self.recursive {
+            args.push("--recursive".into());
+        }
+        if !self.contents {
+            args.push("--no-contents".into());
+        }
+        if !self.trust {
+            args.push("--no-trust".into());
+        }
+        if let Some(count) = self.sigs_needed {
+            args.push("--sigs-needed".into());
+            args.push(count.to_string().into());
+        }
+        args.extend(self.paths.iter().map(OsString::from));
+
+        let mut cmd = command::nix()?;
+        cmd.args(args).args(version::experimental_features_args());
+        debug!("running {cmd:?}");
+
+        let output = cmd.output()?;
+        let stderr = String::from_utf8_lossy(&output.stderr);
+
+        let expected = STATUS_CORRUPT | STATUS_UNTRUSTED;
+        match output.status.code() {
+            Some(0) => Ok(Verification::default()),
+            Some(code) if code & !expected == 0 => Ok(Verification::parse(&stderr)),
+            _ => Err(Error::CommandFailed {
+                program: "nix store verify".to_owned(),
+                stderr: stderr.into_owned(),
+            }),
+        }
+    }
+}
+
+impl Derivation {
+    /// Verify the content and signatures of the outputs of this derivation
+    ///
+    /// See [`Verify`]
+    pub fn verify(&self) -> Result<Verification> {
+        Verify::new().derivation(self).run()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn corrupt_and_untrusted_paths() {
+        let stderr = "\
+path '/nix/store/0c8ybjvkn0jxx2xy7yv5c3d7zg2g3yy2-hello-2.12.1' was modified! expected hash 'sha256:1mkvday29m2qxg1fnbv8xh9s6151bh8a2xzhh0k86j7lqhyfwibh', got 'sha256:0ym9wa3hzvs2b9smsl3d7i0cbxcmmn4d4qa2wd6y2ly4kf5bx3as'
+path '/nix/store/9w0bd6yv5m0gxvwryjfxvjvr6ch0bqsm-my-crate-0.1.0' is untrusted
+";
+        let verification = Verification::parse(stderr);
+        assert!(!verification.is_ok());
+        assert_eq!(
+            verification.corrupt,
+            [CorruptPath {
+                path: PathBuf::from("/nix/store/0c8ybjvkn0jxx2xy7yv5c3d7zg2g3yy2-hello-2.12.1"),
+                expected: "sha256:1mkvday29m2qxg1fnbv8xh9s6151bh8a2xzhh0k86j7lqhyfwibh".to_owned(),
+                got: "sha256:0ym9wa3hzvs2b9smsl3d7i0cbxcmmn4d4qa2wd6y2ly4kf5bx3as".to_owned(),
+            }]
+        );
+        assert_eq!(
+            verification.untrusted,
+            [PathBuf::from(
+                "/nix/store/9w0bd6yv5m0gxvwryjfxvjvr6ch0bqsm-my-crate-0.1.0"
+            )]
+        );
+
+        assert!(Verification::parse("").is_ok());
+    }
+}
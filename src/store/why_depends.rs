@@ -0,0 +1,156 @@
+//! Dependency chains between store paths, from `nix why-depends`
+
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+
+use crate::{build_log::strip_ansi, command, Result};
+
+/// Where a store path references the next one in a dependency chain
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reference {
+    /// File containing the reference, relative to the store path
+    pub file: PathBuf,
+    /// Excerpt of the file around the reference
+    pub excerpt: String,
+}
+
+/// Store path in a dependency chain
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dependency {
+    pub path: PathBuf,
+    /// Where the previous path in the chain references this one
+    ///
+    /// Only reported when precise, and never for the first path
+    pub reference: Option<Reference>,
+}
+
+/// Parse the tree printed by `nix why-depends`
+fn parse(stdout: &str) -> Vec<Dependency> {
+    let mut chain: Vec<Dependency> = vec![];
+    let mut reference = None;
+
+    for line in stdout.lines() {
+        let line = line.trim_start_matches(['└', '├', '│', '─', ' ']);
+
+        if let Some(path) = line.strip_prefix('→') {
+            chain.push(Dependency {
+                path: PathBuf::from(path.trim()),
+                reference: reference.take(),
+            });
+        } else if line.starts_with('/') && !line.contains(": ") {
+            chain.push(Dependency {
+                path: PathBuf::from(line.trim_end()),
+                reference: None,
+            });
+        } else if let Some((file, excerpt)) = line.split_once(": ") {
+            reference = Some(Reference {
+                file: PathBuf::from(file),
+                excerpt: excerpt.trim().to_owned(),
+            });
+        }
+    }
+
+    chain
+}
+
+/// Returns the shortest chain of references from the store path `from` to `to`
+///
+/// The chain starts with `from` and ends with `to`, and is empty if `from` doesn't depend on `to`.
+/// When `precise` is set, each step reports the file holding the reference
+///
+/// # Example
+/// ```no_run
+/// # fn main() -> Result<(), nix_build::Error> {
+/// let built = nix_build::Config::new().build_outputs()?;
+/// let out = built.first_out().expect("the target has an out output");
+///
+/// let chain = nix_build::store::why_depends(out, "/nix/store/...-gcc-13.2.0", true)?;
+/// for dependency in &chain {
+///     println!("{}", dependency.path.display());
+/// }
+/// # Ok(()) }
+/// ```
+pub fn why_depends(
+    from: impl AsRef<Path>,
+    to: impl AsRef<Path>,
+    precise: bool,
+) -> Result<Vec<Dependency>> {
+    let mut args: Vec<OsString> = vec![
+        "why-depends".into(),
+        from.as_ref().into(),
+        to.as_ref().into(),
+    ];
+    if precise {
+        args.push("--precise".into());
+    }
+
+    // visited paths and references are colored, even when not printed to a terminal
+    let stdout = command::nix_stdout(args)?;
+    Ok(parse(&strip_ansi(&String::from_utf8_lossy(&stdout))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HELLO: &str = "/nix/store/vkb3h6vb2nhcbmmp7zs1w2n0x1i8sv3l-hello-2.12.1";
+    const GLIBC: &str = "/nix/store/0c8ybjvkn0jxx2xy7yv5c3d7zg2g3yy2-glibc-2.37-8";
+    const LIBIDN: &str = "/nix/store/9w0bd6yv5m0gxvwryjfxvjvr6ch0bqsm-libidn2-2.3.4";
+
+    #[test]
+    fn chain() {
+        let stdout = format!(
+            "{HELLO}\x1b[0m\n\
+             └───{GLIBC}\x1b[0m\n\
+             \x20   └───{LIBIDN}\x1b[0m\n"
+        );
+
+        let paths: Vec<PathBuf> = parse(&strip_ansi(&stdout))
+            .into_iter()
+            .map(|dependency| {
+                assert_eq!(dependency.reference, None);
+                dependency.path
+            })
+            .collect();
+        assert_eq!(paths, [HELLO, GLIBC, LIBIDN].map(PathBuf::from));
+    }
+
+    #[test]
+    fn precise_chain() {
+        let stdout = format!(
+            "{HELLO}\x1b[0m\n\
+             └───bin/hello: …\x1b[31;1m0c8ybjvkn0jxx2xy7yv5c3d7zg2g3yy2\x1b[0m-glibc-2.37-8/lib/ld-linux-x86-64.so.2…\n\
+             \x20   → {GLIBC}\x1b[0m\n\
+             \x20   └───lib/libc.so.6: …\x1b[32;1m9w0bd6yv5m0gxvwryjfxvjvr6ch0bqsm\x1b[0m-libidn2-2.3.4/lib…\n\
+             \x20       → {LIBIDN}\x1b[0m\n"
+        );
+
+        let chain = parse(&strip_ansi(&stdout));
+        assert_eq!(
+            chain,
+            [
+                Dependency {
+                    path: PathBuf::from(HELLO),
+                    reference: None,
+                },
+                Dependency {
+                    path: PathBuf::from(GLIBC),
+                    reference: Some(Reference {
+                        file: PathBuf::from("bin/hello"),
+                        excerpt: "…0c8ybjvkn0jxx2xy7yv5c3d7zg2g3yy2-glibc-2.37-8/lib/ld-linux-x86-64.so.2…"
+                            .to_owned(),
+                    }),
+                },
+                Dependency {
+                    path: PathBuf::from(LIBIDN),
+                    reference: Some(Reference {
+                        file: PathBuf::from("lib/libc.so.6"),
+                        excerpt: "…9w0bd6yv5m0gxvwryjfxvjvr6ch0bqsm-libidn2-2.3.4/lib…".to_owned(),
+                    }),
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,222 @@
+//! Test doubles for code building with nix, usable without nix installed
+
+use std::{
+    collections::HashMap,
+    ffi::OsString,
+    process::{ExitStatus, Output},
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    fingerprint::Hasher,
+    store::{StorePath, BASE32_CHARS, HASH_LEN},
+    Config, Derivation, Error, NixRunner, Result,
+};
+
+/// Invocation of `nix` recorded by a [`MockNix`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Invocation {
+    /// Arguments of the invocation, starting with the subcommand like `build`
+    pub args: Vec<OsString>,
+    /// Environment variables set on the invocation, like `NIX_CONFIG`
+    pub env: Vec<(OsString, OsString)>,
+}
+
+impl Invocation {
+    /// Returns the subcommand of the invocation, like `build`
+    pub fn subcommand(&self) -> Option<&str> {
+        self.args.first().and_then(|arg| arg.to_str())
+    }
+
+    /// Returns whether `arg` is one of the arguments
+    pub fn has_arg(&self, arg: &str) -> bool {
+        self.args.iter().any(|a| a == arg)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Response {
+    subcommand: String,
+    success: bool,
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+}
+
+#[derive(Debug, Default)]
+struct MockState {
+    responses: Vec<Response>,
+    invocations: Vec<Invocation>,
+}
+
+/// [`NixRunner`] recording the invocations and replaying canned outputs
+///
+/// Responses are replayed in order for each subcommand, the last one being repeated.
+/// Clones share the same responses and invocations, so a clone can be given to
+/// [`Config::runner`] while the original is inspected
+///
+/// # Example
+/// ```
+/// use nix_build::testing::{self, MockNix};
+///
+/// let mock = MockNix::new();
+/// mock.build_result(&[testing::derivation("hello", &["out"])]);
+///
+/// let derivations = mock
+///     .config()
+///     .target_flake("nixpkgs#hello")
+///     .build()
+///     .unwrap();
+/// assert_eq!(derivations[0].outputs["out"].name(), "hello");
+///
+/// let invocations = mock.invocations();
+/// assert!(invocations[0].has_arg("nixpkgs#hello"));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct MockNix {
+    state: Arc<Mutex<MockState>>,
+}
+
+impl MockNix {
+    /// Create a new [`MockNix`] without any response
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn push(&self, response: Response) -> &Self {
+        self.state.lock().unwrap().responses.push(response);
+        self
+    }
+
+    /// Reply to the invocations of `subcommand`, like `build` or `eval`, with `stdout`
+    pub fn respond(&self, subcommand: &str, stdout: impl Into<Vec<u8>>) -> &Self {
+        self.push(Response {
+            subcommand: subcommand.to_owned(),
+            success: true,
+            stdout: stdout.into(),
+            stderr: vec![],
+        })
+    }
+
+    /// Fail the invocations of `subcommand`, printing `stderr`
+    pub fn fail(&self, subcommand: &str, stderr: &str) -> &Self {
+        self.push(Response {
+            subcommand: subcommand.to_owned(),
+            success: false,
+            stdout: vec![],
+            stderr: stderr.as_bytes().to_vec(),
+        })
+    }
+
+    /// Reply to `nix build` with `derivations`, as printed by `nix build --json`
+    pub fn build_result(&self, derivations: &[Derivation]) -> &Self {
+        let json = serde_json::to_vec(derivations).expect("derivations are serializable");
+        self.respond("build", json)
+    }
+
+    /// Returns the invocations recorded so far, in order
+    pub fn invocations(&self) -> Vec<Invocation> {
+        self.state.lock().unwrap().invocations.clone()
+    }
+
+    /// Returns a [`Config`] running its invocations with this mock
+    ///
+    /// Cargo directives are disabled and the outputs are not verified, as they don't exist
+    pub fn config(&self) -> Config {
+        let mut config = Config::new();
+        config
+            .runner(self.clone())
+            .cargo_integration(false)
+            .verify_outputs(false);
+        config
+    }
+}
+
+impl NixRunner for MockNix {
+    fn run(&self, args: &[OsString], env: &[(OsString, OsString)]) -> Result<Output> {
+        let mut state = self.state.lock().unwrap();
+        let invocation = Invocation {
+            args: args.to_vec(),
+            env: env.to_vec(),
+        };
+        let subcommand = invocation.subcommand().unwrap_or_default().to_owned();
+        state.invocations.push(invocation);
+
+        let mut matching = state
+            .responses
+            .iter()
+            .enumerate()
+            .filter(|(_, response)| response.subcommand == subcommand)
+            .map(|(i, _)| i);
+        let response = match (matching.next(), matching.next()) {
+            (Some(i), Some(_)) => state.responses.remove(i),
+            (Some(i), None) => state.responses[i].clone(),
+            (None, _) => {
+                return Err(Error::CommandFailed {
+                    program: "nix".to_owned(),
+                    stderr: format!("no mock response for `nix {subcommand}`"),
+                })
+            }
+        };
+
+        Ok(Output {
+            status: exit_status(response.success),
+            stdout: response.stdout,
+            stderr: response.stderr,
+        })
+    }
+}
+
+#[cfg(unix)]
+pub(crate) fn exit_status(success: bool) -> ExitStatus {
+    use std::os::unix::process::ExitStatusExt;
+    // wait status, with the exit code in the second byte
+    ExitStatus::from_raw(if success { 0 } else { 1 << 8 })
+}
+
+#[cfg(windows)]
+pub(crate) fn exit_status(success: bool) -> ExitStatus {
+    use std::os::windows::process::ExitStatusExt;
+    ExitStatus::from_raw(if success { 0 } else { 1 })
+}
+
+/// Returns a store path named `name`, with a hash derived from the name
+///
+/// # Example
+/// ```
+/// let path = nix_build::testing::store_path("hello-2.12.1");
+/// assert_eq!(path.store_dir(), std::path::Path::new("/nix/store"));
+/// ```
+pub fn store_path(name: &str) -> StorePath {
+    let alphabet = BASE32_CHARS.as_bytes();
+    let hash: String = (0..HASH_LEN)
+        .map(|i| {
+            let digest = Hasher::new().write(name).write([i as u8]).finish();
+            char::from(alphabet[(digest.as_u64() % alphabet.len() as u64) as usize])
+        })
+        .collect();
+
+    StorePath::new(format!("{}/{hash}-{name}", crate::NIX_STORE_DIR))
+        .expect("names of test store paths are valid")
+}
+
+/// Returns a derivation named `name` with the given outputs, at paths made with [`store_path`]
+///
+/// Outputs other than `out` are named `<name>-<output>`, like nix does
+pub fn derivation(name: &str, outputs: &[&str]) -> Derivation {
+    let outputs = outputs
+        .iter()
+        .map(|output| {
+            let path = match *output {
+                "out" => store_path(name),
+                output => store_path(&format!("{name}-{output}")),
+            };
+            ((*output).to_owned(), path)
+        })
+        .collect();
+
+    Derivation {
+        drv_path: store_path(&format!("{name}.drv")),
+        outputs,
+        realisations: HashMap::new(),
+    }
+}
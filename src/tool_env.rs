@@ -0,0 +1,143 @@
+use std::{
+    ffi::{OsStr, OsString},
+    path::PathBuf,
+    process::Command,
+};
+
+use crate::{Derivation, PkgConfig};
+
+#[cfg(target_os = "macos")]
+const LIBRARY_PATH_VAR: &str = "DYLD_LIBRARY_PATH";
+#[cfg(not(target_os = "macos"))]
+const LIBRARY_PATH_VAR: &str = "LD_LIBRARY_PATH";
+
+/// Environment composed from the outputs of several derivations
+///
+/// Meant to spawn tools (code generators, test binaries...) provided by nix
+/// without modifying the environment of the current process
+///
+/// # Example
+/// ```no_run
+/// # fn main() -> Result<(), nix_build::Error> {
+/// use nix_build::ToolEnv;
+///
+/// let derivations = nix_build::Config::new()
+///     .target_flake("nixpkgs#protobuf")
+///     .build()?;
+///
+/// let status = ToolEnv::new()
+///     .derivations(&derivations)
+///     .command("protoc")
+///     .arg("--version")
+///     .status()?;
+/// # Ok(()) }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ToolEnv {
+    bin_dirs: Vec<PathBuf>,
+    lib_dirs: Vec<PathBuf>,
+    pkg_config: PkgConfig,
+    ld_library_path: bool,
+    pkg_config_path: bool,
+}
+
+fn prepend_paths(var: &str, dirs: &[PathBuf]) -> OsString {
+    let existing = std::env::var_os(var);
+    let paths = dirs
+        .iter()
+        .cloned()
+        .chain(existing.iter().flat_map(std::env::split_paths));
+
+    std::env::join_paths(paths).unwrap_or_default()
+}
+
+impl ToolEnv {
+    /// Create an empty [`ToolEnv`]
+    ///
+    /// Only `PATH` is set by default
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the outputs of the given derivation to the environment
+    pub fn derivation(&mut self, derivation: &Derivation) -> &mut Self {
+        self.bin_dirs
+            .extend(derivation.find_dirs(&["bin", "out", "dev"], "bin"));
+        self.lib_dirs
+            .extend(derivation.find_dirs(&["lib", "out", "dev"], "lib"));
+        self.pkg_config.derivation(derivation);
+        self
+    }
+
+    /// Add the outputs of all the given derivations to the environment
+    pub fn derivations<'a>(
+        &mut self,
+        derivations: impl IntoIterator<Item = &'a Derivation>,
+    ) -> &mut Self {
+        for derivation in derivations {
+            self.derivation(derivation);
+        }
+        self
+    }
+
+    /// Set to also prepend the `lib` directories to `LD_LIBRARY_PATH`
+    ///
+    /// `DYLD_LIBRARY_PATH` is used on macOS
+    pub fn ld_library_path(&mut self, ld_library_path: bool) -> &mut Self {
+        self.ld_library_path = ld_library_path;
+        self
+    }
+
+    /// Set to also prepend the `pkg-config` directories to `PKG_CONFIG_PATH`
+    pub fn pkg_config_path(&mut self, pkg_config_path: bool) -> &mut Self {
+        self.pkg_config_path = pkg_config_path;
+        self
+    }
+
+    /// Returns the environment variables to set, with the current values appended
+    pub fn vars(&self) -> Vec<(OsString, OsString)> {
+        let mut vars = vec![(
+            OsString::from("PATH"),
+            prepend_paths("PATH", &self.bin_dirs),
+        )];
+
+        if self.ld_library_path {
+            vars.push((
+                OsString::from(LIBRARY_PATH_VAR),
+                prepend_paths(LIBRARY_PATH_VAR, &self.lib_dirs),
+            ));
+        }
+
+        if self.pkg_config_path {
+            vars.push((
+                OsString::from("PKG_CONFIG_PATH"),
+                self.pkg_config.pkg_config_path(),
+            ));
+        }
+
+        vars
+    }
+
+    /// Apply the environment to the given command
+    pub fn apply<'c>(&self, cmd: &'c mut Command) -> &'c mut Command {
+        cmd.envs(self.vars())
+    }
+
+    /// Returns a [`Command`] for `program` with the environment applied
+    ///
+    /// `program` is resolved against the composed `PATH`
+    pub fn command(&self, program: impl AsRef<OsStr>) -> Command {
+        let path = prepend_paths("PATH", &self.bin_dirs);
+        let program = program.as_ref();
+
+        let resolved = std::env::current_dir()
+            .ok()
+            .and_then(|cwd| which::which_in(program, Some(&path), cwd).ok())
+            .map(OsString::from)
+            .unwrap_or_else(|| program.to_owned());
+
+        let mut cmd = Command::new(resolved);
+        self.apply(&mut cmd);
+        cmd
+    }
+}
@@ -0,0 +1,98 @@
+//! Discovery of the local files read during evaluation
+
+use std::{
+    collections::BTreeSet,
+    ffi::OsStr,
+    path::{Path, PathBuf},
+};
+
+/// Marker printed by nix, at `-v` or higher verbosity, for each evaluated file
+const EVALUATING_FILE: &str = "evaluating file '";
+
+/// Returns the files reported as evaluated in the given nix log
+pub(crate) fn evaluated_files(log: &str) -> BTreeSet<PathBuf> {
+    log.lines()
+        .filter_map(|line| {
+            let (_, rest) = line.split_once(EVALUATING_FILE)?;
+            let (path, _) = rest.rsplit_once('\'')?;
+            Some(PathBuf::from(path))
+        })
+        .collect()
+}
+
+/// Returns the store path the given local flake is copied to when evaluated
+pub(crate) fn flake_source(flake: &Path) -> Option<PathBuf> {
+    #[derive(serde::Deserialize)]
+    struct Metadata {
+        path: PathBuf,
+    }
+
+    let args = [
+        OsStr::new("flake"),
+        OsStr::new("metadata"),
+        OsStr::new("--json"),
+        flake.as_os_str(),
+    ];
+    crate::command::nix_json::<Metadata, _, _>(args)
+        .ok()
+        .map(|metadata| metadata.path)
+}
+
+/// Map the evaluated files back to the local filesystem
+///
+/// Files evaluated from the store copy of `flake` are translated to their local path,
+/// while any other file in the store is discarded
+pub(crate) fn local_files(
+    files: BTreeSet<PathBuf>,
+    flake: Option<(&Path, &Path)>,
+) -> BTreeSet<PathBuf> {
+    files
+        .into_iter()
+        .filter_map(|file| {
+            if let Some((source, local)) = flake {
+                if let Ok(relative) = file.strip_prefix(source) {
+                    return Some(local.join(relative));
+                }
+            }
+
+            if file.starts_with(crate::NIX_STORE_DIR) {
+                None
+            } else {
+                Some(file)
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evaluated_local_files() {
+        let log = "\
+evaluating file '/nix/store/0c8ybjvkn0jxx2xy7yv5c3d7zg2g3yy2-source/flake.nix'
+evaluating file '/nix/store/0c8ybjvkn0jxx2xy7yv5c3d7zg2g3yy2-source/nix/package.nix'
+evaluating file '/nix/store/9w0bd6yv5m0gxvwryjfxvjvr6ch0bqsm-source/pkgs/top-level/impure.nix'
+evaluating file '/home/user/overlay's/default.nix'
+copying '/home/user/project' to the store
+evaluating derivation 'git+file:///home/user/project#packages.x86_64-linux.default'...
+";
+        let files = evaluated_files(log);
+        assert_eq!(files.len(), 4);
+
+        let source = Path::new("/nix/store/0c8ybjvkn0jxx2xy7yv5c3d7zg2g3yy2-source");
+        let local = Path::new("/home/user/project");
+        let files: Vec<PathBuf> = local_files(files, Some((source, local)))
+            .into_iter()
+            .collect();
+        assert_eq!(
+            files,
+            [
+                PathBuf::from("/home/user/overlay's/default.nix"),
+                PathBuf::from("/home/user/project/flake.nix"),
+                PathBuf::from("/home/user/project/nix/package.nix"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,377 @@
+use std::{
+    ffi::{OsStr, OsString},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::{cargo, Config, Derivation, Error, Result};
+
+/// Shared libraries copied out of the store by [`Derivation::vendor_into_out_dir`]
+#[derive(Debug, Clone)]
+pub struct Vendored {
+    /// Directory the libraries have been copied to
+    pub dir: PathBuf,
+    /// Paths to the copied libraries
+    pub libraries: Vec<PathBuf>,
+}
+
+/// Returns whether `name` is a shared library, like `libfoo.so`, `libfoo.so.1.2` or `libfoo.dylib`
+fn is_shared_library(name: &str) -> bool {
+    if !name.starts_with("lib") {
+        return false;
+    }
+    if name.ends_with(".so") || name.ends_with(".dylib") {
+        return true;
+    }
+
+    name.split_once(".so.").is_some_and(|(_, version)| {
+        version
+            .split('.')
+            .all(|part| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit()))
+    })
+}
+
+/// Returns the install names of the libraries linked by a library,
+/// from the output of `otool -L`, starting with the id of the library itself
+fn linked_libraries(otool: &str) -> Vec<&str> {
+    otool
+        .lines()
+        .skip(1)
+        .filter_map(|line| line.trim().split(" (compatibility version").next())
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+/// Locate `tool` in `PATH`, or build `installable` with nix to provide it
+pub(crate) fn find_tool(tool: &str, installable: &str) -> Result<PathBuf> {
+    if let Ok(path) = which::which(tool) {
+        return Ok(path);
+    }
+
+    Config::new()
+        .target_flake(installable)
+        .build()?
+        .iter()
+        .filter_map(Derivation::bin_dir)
+        .map(|bin| bin.join(tool))
+        .find(|path| path.exists())
+        .ok_or_else(|| Error::CommandFailed {
+            program: tool.to_owned(),
+            stderr: format!("`{tool}` not found in {installable}"),
+        })
+}
+
+/// Run `tool` with the given arguments, returning its stdout
+fn run_tool(tool: &Path, args: &[&OsStr]) -> Result<Vec<u8>> {
+    let output = Command::new(tool).args(args).output()?;
+
+    if !output.status.success() {
+        return Err(Error::CommandFailed {
+            program: tool.display().to_string(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        });
+    }
+
+    Ok(output.stdout)
+}
+
+/// Copy `src` into `dest`, preserving symlinks pointing in the same directory
+fn copy_library(src: &Path, dest: &Path) -> Result<bool> {
+    if dest.symlink_metadata().is_ok() {
+        std::fs::remove_file(dest)?;
+    }
+
+    #[cfg(unix)]
+    if let Ok(target) = std::fs::read_link(src) {
+        if target.parent() == Some(Path::new("")) {
+            std::os::unix::fs::symlink(target, dest)?;
+            return Ok(false);
+        }
+    }
+
+    std::fs::copy(src, dest)?;
+
+    // store files are read-only, but the copy needs to be patched
+    let mut permissions = std::fs::metadata(dest)?.permissions();
+    #[allow(clippy::permissions_set_readonly_false)]
+    permissions.set_readonly(false);
+    std::fs::set_permissions(dest, permissions)?;
+
+    Ok(true)
+}
+
+/// Copy the directory `src` into `dest` recursively, following symlinks
+fn copy_tree(src: &Path, dest: &Path) -> Result<()> {
+    std::fs::create_dir_all(dest)?;
+
+    for entry in std::fs::read_dir(src)? {
+        let entry = entry?;
+        let (src, dest) = (entry.path(), dest.join(entry.file_name()));
+
+        if src.is_dir() {
+            copy_tree(&src, &dest)?;
+            continue;
+        }
+
+        if dest.symlink_metadata().is_ok() {
+            std::fs::remove_file(&dest)?;
+        }
+        std::fs::copy(&src, &dest)?;
+
+        // store files are read-only, which would prevent overwriting them on the next run
+        let mut permissions = std::fs::metadata(&dest)?.permissions();
+        #[allow(clippy::permissions_set_readonly_false)]
+        permissions.set_readonly(false);
+        std::fs::set_permissions(&dest, permissions)?;
+    }
+
+    Ok(())
+}
+
+impl Derivation {
+    /// Copy the `share/<subdir>` directories of the outputs into `<out_dir>/<subdir>`,
+    /// preserving their structure, and return the destination
+    ///
+    /// Meant for data consumed by the build, like schemas or protobuf definitions.
+    /// The `out`, `dev` and `lib` outputs are copied first, then the others by name,
+    /// so files of later outputs take precedence. `cargo:rerun-if-changed` is emitted
+    /// for the copied directories when running inside a build script.
+    ///
+    /// Fails with [`Error::OutputMissing`] if no output has the directory
+    ///
+    /// # Example
+    /// ```no_run
+    /// # fn main() -> Result<(), nix_build::Error> {
+    /// let derivations = nix_build::Config::new()
+    ///     .target_flake("nixpkgs#protobuf")
+    ///     .build()?;
+    ///
+    /// let out_dir = std::env::var("OUT_DIR").unwrap();
+    /// let protos = derivations[0].install_share("protobuf", &out_dir)?;
+    /// # Ok(()) }
+    /// ```
+    pub fn install_share(&self, subdir: &str, out_dir: impl AsRef<Path>) -> Result<PathBuf> {
+        let mut outputs: Vec<&str> = vec!["out", "dev", "lib"];
+        let mut rest: Vec<&str> = self
+            .outputs
+            .keys()
+            .map(String::as_str)
+            .filter(|output| !outputs.contains(output))
+            .collect();
+        rest.sort_unstable();
+        outputs.extend(rest);
+
+        let share = Path::new("share").join(subdir);
+        let dirs: Vec<PathBuf> = self.find_dirs(&outputs, &share.to_string_lossy()).collect();
+        if dirs.is_empty() {
+            let root = self.out().or_else(|| self.outputs.values().next());
+            let missing = root.map_or(share.clone(), |root| root.join(&share));
+            return Err(Error::OutputMissing(missing));
+        }
+
+        let dest = out_dir.as_ref().join(subdir);
+        for dir in &dirs {
+            copy_tree(dir, &dest)?;
+            if cargo::is_build_script() {
+                cargo::rerun_if_changed(dir);
+            }
+        }
+
+        Ok(dest)
+    }
+
+    /// Copy the shared libraries of this derivation into `$OUT_DIR`
+    ///
+    /// The copies are patched to look for their dependencies next to themselves first
+    /// (with `patchelf`, or `install_name_tool` on macOS) and
+    /// `cargo:rustc-link-search=native=` is emitted for the destination directory,
+    /// making the linked binaries independent of the builder's store for these libraries.
+    /// Dependencies from other store paths, like `libstdc++`, are not copied,
+    /// and are still loaded from the store
+    ///
+    /// The patching tools are looked up in `PATH`, and are otherwise built with nix
+    ///
+    /// # Example
+    /// ```no_run
+    /// # fn main() -> Result<(), nix_build::Error> {
+    /// let derivations = nix_build::build()?;
+    /// let vendored = derivations[0].vendor_into_out_dir()?;
+    ///
+    /// for lib in &vendored.libraries {
+    ///     println!("cargo:warning=vendored {}", lib.display());
+    /// }
+    /// # Ok(()) }
+    /// ```
+    pub fn vendor_into_out_dir(&self) -> Result<Vendored> {
+        let out_dir = std::env::var_os("OUT_DIR").ok_or(Error::MissingEnvVar("OUT_DIR"))?;
+
+        let name = self
+            .out()
+            .or_else(|| self.outputs.values().next())
+            .and_then(|path| path.file_name())
+            .unwrap_or_else(|| OsStr::new("vendored"));
+        let dir = Path::new(&out_dir).join("nix-vendor").join(name);
+        std::fs::create_dir_all(&dir)?;
+
+        let apple = matches!(cargo::target_os().as_deref(), Some("macos" | "ios"));
+
+        let mut libraries = vec![];
+        let mut to_patch = vec![];
+        for lib_dir in self.find_dirs(&["lib", "out", "dev"], "lib") {
+            for entry in std::fs::read_dir(lib_dir)? {
+                let entry = entry?;
+                let file_name = entry.file_name();
+
+                if !file_name
+                    .to_str()
+                    .map(is_shared_library)
+                    .unwrap_or_default()
+                {
+                    continue;
+                }
+
+                let dest = dir.join(&file_name);
+                if copy_library(&entry.path(), &dest)? {
+                    to_patch.push(dest.clone());
+                }
+                libraries.push(dest);
+            }
+        }
+
+        if !to_patch.is_empty() {
+            if apple {
+                patch_install_names(&to_patch, &libraries)?;
+            } else {
+                patch_rpaths(&to_patch)?;
+            }
+        }
+
+        cargo::rustc_link_search("native", &dir);
+
+        Ok(Vendored { dir, libraries })
+    }
+}
+
+/// Make the copied Mach-O libraries `libs` load the ones among `vendored` next to themselves
+///
+/// The id of each library is set to `@rpath/<name>`, and the absolute install names of its
+/// dependencies are rewritten the same way when they have been vendored too
+fn patch_install_names(libs: &[PathBuf], vendored: &[PathBuf]) -> Result<()> {
+    let install_name_tool = find_tool("install_name_tool", "nixpkgs#darwin.cctools")?;
+    let otool = find_tool("otool", "nixpkgs#darwin.cctools")?;
+
+    let is_vendored = |name: &OsStr| vendored.iter().any(|lib| lib.file_name() == Some(name));
+
+    for lib in libs {
+        let id = format!(
+            "@rpath/{}",
+            lib.file_name().unwrap_or_default().to_string_lossy()
+        );
+        let mut args: Vec<OsString> = vec!["-id".into(), id.into()];
+
+        let linked = run_tool(&otool, &[OsStr::new("-L"), lib.as_os_str()])?;
+        for dependency in linked_libraries(&String::from_utf8_lossy(&linked)) {
+            let path = Path::new(dependency);
+            match path.file_name() {
+                Some(name) if path.is_absolute() && is_vendored(name) => {
+                    let name = format!("@rpath/{}", name.to_string_lossy());
+                    args.extend(["-change".into(), dependency.into(), name.into()]);
+                }
+                _ => {}
+            }
+        }
+
+        args.push(lib.into());
+        let args: Vec<&OsStr> = args.iter().map(OsString::as_os_str).collect();
+        run_tool(&install_name_tool, &args)?;
+
+        // the rpath might already be present, in which case the tool fails
+        let _ = run_tool(
+            &install_name_tool,
+            &[
+                OsStr::new("-add_rpath"),
+                OsStr::new("@loader_path"),
+                lib.as_os_str(),
+            ],
+        );
+    }
+
+    Ok(())
+}
+
+/// Make the copied ELF libraries `libs` look for their dependencies next to themselves first
+///
+/// `$ORIGIN` is prepended to the original run path, so dependencies that haven't been
+/// vendored are still found in the store
+fn patch_rpaths(libs: &[PathBuf]) -> Result<()> {
+    let patchelf = find_tool("patchelf", "nixpkgs#patchelf")?;
+
+    for lib in libs {
+        let rpath = run_tool(&patchelf, &[OsStr::new("--print-rpath"), lib.as_os_str()])?;
+        let rpath: Vec<&str> = std::iter::once("$ORIGIN")
+            .chain(
+                std::str::from_utf8(&rpath)
+                    .unwrap_or_default()
+                    .trim()
+                    .split(':')
+                    .filter(|entry| !entry.is_empty() && *entry != "$ORIGIN"),
+            )
+            .collect();
+
+        run_tool(
+            &patchelf,
+            &[
+                OsStr::new("--set-rpath"),
+                OsStr::new(&rpath.join(":")),
+                lib.as_os_str(),
+            ],
+        )?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shared_library_names() {
+        for name in [
+            "libz.so",
+            "libz.so.1",
+            "libz.so.1.3.1",
+            "libz.dylib",
+            "libz.1.dylib",
+        ] {
+            assert!(is_shared_library(name), "{name}");
+        }
+        for name in [
+            "libz.a",
+            "libfoo.sol.txt",
+            "libz.so.1.txt",
+            "libz.so.",
+            "z.so",
+        ] {
+            assert!(!is_shared_library(name), "{name}");
+        }
+    }
+
+    #[test]
+    fn otool_linked_libraries() {
+        let otool = "\
+/tmp/out/nix-vendor/libssl.3.dylib:
+\t@rpath/libssl.3.dylib (compatibility version 3.0.0, current version 3.0.0)
+\t/nix/store/0c8ybjvkn0jxx2xy7yv5c3d7zg2g3yy2-openssl-3.0.13/lib/libcrypto.3.dylib (compatibility version 3.0.0, current version 3.0.0)
+\t/usr/lib/libSystem.B.dylib (compatibility version 1.0.0, current version 1319.0.0)
+";
+        assert_eq!(
+            linked_libraries(otool),
+            [
+                "@rpath/libssl.3.dylib",
+                "/nix/store/0c8ybjvkn0jxx2xy7yv5c3d7zg2g3yy2-openssl-3.0.13/lib/libcrypto.3.dylib",
+                "/usr/lib/libSystem.B.dylib",
+            ]
+        );
+    }
+}
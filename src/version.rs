@@ -0,0 +1,330 @@
+//! Detection of the version and capabilities of the installed nix
+
+use std::{collections::HashMap, fmt, sync::OnceLock};
+
+use crate::{command, Error, Result};
+
+/// Version of nix
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct NixVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl fmt::Display for NixVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+impl NixVersion {
+    /// Parse the output of `nix --version`, like `nix (Nix) 2.18.1`
+    ///
+    /// # Example
+    /// ```
+    /// # use nix_build::NixVersion;
+    /// let version = NixVersion::parse("nix (Nix) 2.18.1").unwrap();
+    ///
+    /// assert_eq!(version.to_string(), "2.18.1");
+    /// assert!(version.at_least(2, 11));
+    /// ```
+    pub fn parse(output: &str) -> Option<Self> {
+        let version = output.split_whitespace().last()?;
+        let mut parts = version.split(['.', '-', '+']).map(|part| {
+            let digits = part
+                .find(|c: char| !c.is_ascii_digit())
+                .map_or(part, |end| &part[..end]);
+            digits.parse::<u32>().ok()
+        });
+
+        Some(Self {
+            major: parts.next()??,
+            minor: parts.next().flatten().unwrap_or_default(),
+            patch: parts.next().flatten().unwrap_or_default(),
+        })
+    }
+
+    /// Returns whether this version is at least `major.minor`
+    pub fn at_least(&self, major: u32, minor: u32) -> bool {
+        (self.major, self.minor) >= (major, minor)
+    }
+}
+
+/// Implementation of nix providing the `nix` program
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Implementation {
+    /// The reference implementation, [Nix](https://nixos.org)
+    Nix,
+    /// [Lix](https://lix.systems)
+    Lix,
+    /// [Determinate Nix](https://determinate.systems), where flakes are stable
+    Determinate,
+    /// An implementation that couldn't be recognized
+    Unknown,
+}
+
+impl fmt::Display for Implementation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Nix => "Nix",
+            Self::Lix => "Lix",
+            Self::Determinate => "Determinate Nix",
+            Self::Unknown => "unknown",
+        })
+    }
+}
+
+impl Implementation {
+    /// Parse the output of `nix --version`, like `nix (Lix, like Nix) 2.91.1`
+    ///
+    /// # Example
+    /// ```
+    /// # use nix_build::Implementation;
+    /// assert_eq!(Implementation::parse("nix (Nix) 2.18.1"), Implementation::Nix);
+    /// assert_eq!(
+    ///     Implementation::parse("nix (Determinate Nix 3.6.2) 2.29.0"),
+    ///     Implementation::Determinate
+    /// );
+    /// ```
+    pub fn parse(output: &str) -> Self {
+        let Some(name) = output
+            .split_once('(')
+            .and_then(|(_, rest)| rest.split_once(')'))
+            .map(|(name, _)| name)
+        else {
+            return Self::Unknown;
+        };
+
+        if name.starts_with("Lix") {
+            Self::Lix
+        } else if name.starts_with("Determinate") {
+            Self::Determinate
+        } else if name == "Nix" {
+            Self::Nix
+        } else {
+            Self::Unknown
+        }
+    }
+}
+
+/// Features supported by the installed nix
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capabilities {
+    /// Implementation of nix
+    pub implementation: Implementation,
+    /// Version of nix
+    pub version: NixVersion,
+    /// The unified `nix` CLI (`nix-command`) is available
+    pub nix_command: bool,
+    /// Flakes are available
+    pub flakes: bool,
+    /// Content-addressed derivations are available
+    pub ca_derivations: bool,
+    /// `nix build --json` is available
+    pub build_json: bool,
+    /// `nix build --print-out-paths` is available
+    pub print_out_paths: bool,
+    /// `nix print-dev-env --json` is available
+    pub print_dev_env_json: bool,
+    /// Experimental features enabled in the nix configuration
+    pub experimental_features: Vec<String>,
+}
+
+impl Capabilities {
+    /// Derive the capabilities from the given implementation and version alone
+    fn from_version(implementation: Implementation, version: NixVersion) -> Self {
+        let modern = version.at_least(2, 4);
+
+        Self {
+            implementation,
+            version,
+            nix_command: modern,
+            flakes: modern,
+            ca_derivations: modern,
+            build_json: modern,
+            print_out_paths: version.at_least(2, 11),
+            print_dev_env_json: modern,
+            experimental_features: vec![],
+        }
+    }
+
+    /// Returns whether the experimental feature `name` is enabled in the nix configuration
+    pub fn is_enabled(&self, name: &str) -> bool {
+        self.experimental_features
+            .iter()
+            .any(|feature| feature == name)
+    }
+
+    /// Returns the arguments enabling the experimental features used by the crate
+    ///
+    /// Flakes are stable in Determinate Nix, so no argument is needed there.
+    /// Otherwise the features are added to the configured ones with `--extra-experimental-features`
+    pub(crate) fn experimental_features_args(&self) -> &'static [&'static str] {
+        match self.implementation {
+            Implementation::Determinate => &[],
+            _ if self.nix_command => &["--extra-experimental-features", "nix-command flakes"],
+            _ => &command::EXPERIMENTAL_FEATURES,
+        }
+    }
+}
+
+/// Returns the output of `nix --version`, run once per process
+pub(crate) fn version_output() -> Option<&'static str> {
+    static OUTPUT: OnceLock<Option<String>> = OnceLock::new();
+
+    OUTPUT
+        .get_or_init(|| {
+            let output = command::nix().ok()?.arg("--version").output().ok()?;
+            Some(String::from_utf8_lossy(&output.stdout).into_owned())
+        })
+        .as_deref()
+}
+
+/// Returns the version of the found nix program, detected once per process
+pub(crate) fn detect() -> Option<NixVersion> {
+    version_output().and_then(NixVersion::parse)
+}
+
+/// Returns the capabilities of the found nix program, detected once per process
+///
+/// The experimental features are left empty when the nix configuration couldn't be queried,
+/// see [`settings`] for the error
+pub(crate) fn detect_capabilities() -> Option<&'static Capabilities> {
+    static CAPABILITIES: OnceLock<Option<Capabilities>> = OnceLock::new();
+
+    CAPABILITIES
+        .get_or_init(|| {
+            let output = version_output()?;
+            let mut capabilities = Capabilities::from_version(
+                Implementation::parse(output),
+                NixVersion::parse(output)?,
+            );
+
+            if capabilities.nix_command {
+                capabilities.experimental_features =
+                    setting_list("experimental-features").unwrap_or_default();
+            }
+
+            Some(capabilities)
+        })
+        .as_ref()
+}
+
+/// Query the values of the settings of the nix configuration, by name
+fn query_settings(version: NixVersion) -> Result<HashMap<String, serde_json::Value>> {
+    #[derive(serde::Deserialize)]
+    struct Setting {
+        value: serde_json::Value,
+    }
+
+    // `show-config` has been deprecated in favor of `config show` in 2.19
+    let args: &[&str] = if version.at_least(2, 19) {
+        &["config", "show", "--json"]
+    } else {
+        &["show-config", "--json"]
+    };
+
+    let mut cmd = command::nix()?;
+    cmd.args(args);
+    // the unified CLI is experimental since 2.4, and the configuration may not enable it.
+    // The capabilities are detected from these settings, so the feature is always added
+    if version.at_least(2, 4) {
+        cmd.args(["--extra-experimental-features", "nix-command"]);
+    }
+
+    let stdout = command::run(&mut cmd)?;
+    let settings: HashMap<String, Setting> =
+        serde_json::from_slice(&stdout).map_err(|_| Error::UnknownOutput)?;
+
+    Ok(settings
+        .into_iter()
+        .map(|(name, setting)| (name, setting.value))
+        .collect())
+}
+
+/// Returns the settings of the nix configuration, queried once per process
+///
+/// The failure of the query is cached too, and returned as [`Error::CommandFailed`]
+fn settings() -> Result<&'static HashMap<String, serde_json::Value>> {
+    static SETTINGS: OnceLock<std::result::Result<HashMap<String, serde_json::Value>, String>> =
+        OnceLock::new();
+
+    SETTINGS
+        .get_or_init(|| {
+            let version = detect().ok_or_else(|| "unknown nix version".to_owned())?;
+            query_settings(version).map_err(|err| match err {
+                Error::CommandFailed { stderr, .. } => stderr,
+                err => err.to_string(),
+            })
+        })
+        .as_ref()
+        .map_err(|stderr| Error::CommandFailed {
+            program: "nix".to_owned(),
+            stderr: format!("couldn't query the nix configuration: {stderr}"),
+        })
+}
+
+/// Returns the value of the setting `name` of the nix configuration, if set
+///
+/// Fails if the nix configuration couldn't be queried
+pub(crate) fn setting(name: &str) -> Result<Option<&'static serde_json::Value>> {
+    Ok(settings()?.get(name))
+}
+
+/// Returns the values of the list setting `name` of the nix configuration, like `substituters`
+///
+/// Fails if the nix configuration couldn't be queried, or the setting is not a list
+pub(crate) fn setting_list(name: &str) -> Result<Vec<String>> {
+    match setting(name)? {
+        Some(value) => serde_json::from_value(value.clone()).map_err(|_| Error::UnknownOutput),
+        None => Ok(vec![]),
+    }
+}
+
+/// Returns the version of the found `nix` program
+///
+/// # Example
+/// ```no_run
+/// # fn main() -> Result<(), nix_build::Error> {
+/// let version = nix_build::nix_version()?;
+/// if !version.at_least(2, 4) {
+///     println!("cargo:warning=nix {version} is too old, please upgrade");
+/// }
+/// # Ok(()) }
+/// ```
+pub fn nix_version() -> Result<NixVersion> {
+    command::nix()?;
+    detect().ok_or(Error::UnknownOutput)
+}
+
+/// Returns whether the unified `nix` CLI is available
+///
+/// Assumes it is when the capabilities couldn't be detected
+pub(crate) fn has_nix_command() -> bool {
+    crate::is_nix_available().is_some()
+        && detect_capabilities().map_or(true, |capabilities| capabilities.nix_command)
+}
+
+/// Returns the arguments enabling the experimental features used by the crate,
+/// adapted to the found nix implementation
+pub(crate) fn experimental_features_args() -> &'static [&'static str] {
+    detect_capabilities().map_or(&command::EXPERIMENTAL_FEATURES, |capabilities| {
+        capabilities.experimental_features_args()
+    })
+}
+
+/// Returns the capabilities of the found `nix` program
+///
+/// Probes `nix --version` and the nix configuration, once per process
+///
+/// Fails if the nix configuration couldn't be queried, as the enabled experimental features
+/// would be unknown
+pub fn capabilities() -> Result<Capabilities> {
+    command::nix()?;
+    let capabilities = detect_capabilities().ok_or(Error::UnknownOutput)?;
+    if capabilities.nix_command {
+        settings()?;
+    }
+    Ok(capabilities.clone())
+}
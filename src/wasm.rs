@@ -0,0 +1,117 @@
+use crate::{cargo, Config, Derivation, Library, LinkKind, Linker};
+
+/// WebAssembly target of C dependencies, see [`WasmCross`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WasmTarget {
+    /// `wasm32-wasip1`, built with the `pkgsCross.wasi32` package set of nixpkgs
+    Wasip1,
+    /// `wasm32-unknown-emscripten`, built with the `emscriptenStdenv` of nixpkgs
+    Emscripten,
+}
+
+impl WasmTarget {
+    /// Returns the target matching the rust target triple `triple`, if any
+    ///
+    /// `wasm32-wasi` is accepted as the former name of `wasm32-wasip1`
+    pub fn from_triple(triple: &str) -> Option<Self> {
+        match triple {
+            "wasm32-wasip1" | "wasm32-wasi" => Some(Self::Wasip1),
+            "wasm32-unknown-emscripten" => Some(Self::Emscripten),
+            _ => None,
+        }
+    }
+
+    /// Returns the target the crate is being built for, from the `TARGET` set by cargo
+    /// for build scripts
+    pub fn detect() -> Option<Self> {
+        Self::from_triple(&std::env::var("TARGET").ok()?)
+    }
+}
+
+/// Builds C dependencies of nixpkgs for WebAssembly and links them
+///
+/// Emscripten packages are built by overriding their `stdenv`,
+/// which only works for packages taking it as an argument
+///
+/// # Example
+/// ```no_run
+/// # fn main() -> Result<(), nix_build::Error> {
+/// use nix_build::{WasmCross, WasmTarget};
+///
+/// let cross = WasmCross::new(WasmTarget::Wasip1);
+/// let derivations = cross.config("tree-sitter").build()?;
+/// WasmCross::link(&derivations[0]);
+/// # Ok(()) }
+/// ```
+#[derive(Debug, Clone)]
+pub struct WasmCross {
+    target: WasmTarget,
+    nixpkgs: String,
+}
+
+impl WasmCross {
+    /// Create a new [`WasmCross`] for the given target, using `<nixpkgs>`
+    pub fn new(target: WasmTarget) -> Self {
+        Self {
+            target,
+            nixpkgs: "import <nixpkgs> {}".to_owned(),
+        }
+    }
+
+    /// Create a new [`WasmCross`] for the target the crate is being built for,
+    /// if it's a supported WebAssembly target
+    pub fn detect() -> Option<Self> {
+        WasmTarget::detect().map(Self::new)
+    }
+
+    /// Use the nixpkgs set evaluated by `expr`, like [`crate::exprs::nixpkgs_from_flake`]
+    pub fn nixpkgs(&mut self, expr: &str) -> &mut Self {
+        self.nixpkgs = expr.to_owned();
+        self
+    }
+
+    /// Returns the target of the dependencies
+    pub fn target(&self) -> WasmTarget {
+        self.target
+    }
+
+    /// Returns the expression building the package `attr` of nixpkgs for the target
+    pub fn expr(&self, attr: &str) -> String {
+        let nixpkgs = &self.nixpkgs;
+        match self.target {
+            WasmTarget::Wasip1 => format!("(({nixpkgs}).pkgsCross.wasi32).{attr}"),
+            WasmTarget::Emscripten => format!(
+                "let pkgs = {nixpkgs}; in pkgs.{attr}.override {{ stdenv = pkgs.emscriptenStdenv; }}"
+            ),
+        }
+    }
+
+    /// Returns a [`Config`] building the package `attr` of nixpkgs for the target
+    pub fn config(&self, attr: &str) -> Config {
+        let mut config = Config::new();
+        config.target_expr(&self.expr(attr));
+        config
+    }
+
+    /// Emit the link directives for the static libraries of `derivation`
+    ///
+    /// WebAssembly modules can't load dynamic libraries, which are ignored.
+    /// Returns the libraries that have been linked
+    pub fn link(derivation: &Derivation) -> Vec<Library> {
+        let libraries: Vec<Library> = Linker::new(derivation)
+            .prefer_static(true)
+            .libraries()
+            .into_iter()
+            .filter(|lib| lib.kind == LinkKind::Static)
+            .collect();
+
+        if !libraries.is_empty() {
+            derivation.emit_link_search();
+        }
+        for lib in &libraries {
+            cargo::rustc_link_lib(lib.kind, &lib.name);
+        }
+
+        libraries
+    }
+}